@@ -0,0 +1,47 @@
+//! Sweeps one expression over many inputs with `eval` and with the JIT.
+//! Run with `cargo bench`.
+
+extern crate ntlang;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use ntlang::eval::eval;
+use ntlang::{Config, EvalEnvSt, Word};
+
+const PROGRAM: &str = "t = x * 0x9E3779B9; u = t ^ t >> 16; (u + (y << 3) - (x & y)) >- (y & 7) | ~u / (x | 1)";
+const RUNS: u32 = 1_000_000;
+
+fn time<F: FnMut(u32, u32) -> u32>(mut f: F) -> (Duration, u32) {
+    let start = Instant::now();
+    let mut sum: u32 = 0;
+    for i in 0..RUNS {
+        sum = sum.wrapping_add(f(black_box(i), black_box(i.wrapping_mul(7))));
+    }
+    (start.elapsed(), sum)
+}
+
+fn main() {
+    let config = Config::new();
+    let node = ntlang::parse(PROGRAM, &config).unwrap();
+    let jit = ntlang::jit(PROGRAM, &config).unwrap();
+    assert_eq!(jit.params(), ["x", "y"]);
+
+    let (eval_time, eval_sum) = time(|x, y| {
+        let mut env = EvalEnvSt::new();
+        env.set("x", Word::new(u128::from(x), 32));
+        env.set("y", Word::new(u128::from(y), 32));
+        eval(&mut env, &Some(&node)).unwrap().bits() as u32
+    });
+    let (jit_time, jit_sum) = time(|x, y| jit.call(&[x, y]).unwrap());
+    assert_eq!(eval_sum, jit_sum);
+
+    let per_call = |elapsed: Duration| elapsed.as_nanos() as f64 / RUNS as f64;
+    println!("{}", PROGRAM);
+    println!("eval: {:8.1} ns/call", per_call(eval_time));
+    println!(
+        "jit:  {:8.1} ns/call ({}), {:.1}x faster",
+        per_call(jit_time),
+        if jit.is_native() { "native" } else { "interpreter fallback" },
+        eval_time.as_secs_f64() / jit_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,266 @@
+//! AArch64 assembly.
+//!
+//! Uses the same scheme as the RISC-V backend: IR temporaries in `w9`-`w15`
+//! or, once those run out, in frame slots reloaded through `w16`/`w17`, and
+//! variables in the frame. Slots are addressed from `sp` with `ldr`/`str`,
+//! whose scaled offset reaches 16 KiB; beyond that the offset goes through
+//! `x8`, which holds no temporary. All arithmetic is on `w` registers,
+//! which wraps at 32 bits like `eval`, or on `x` registers with 8-byte
+//! slots for a function legalized to 64 bits.
+
+use codegen::CodegenError;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrUnOp, Temp};
+use codegen::regalloc::{self, Loc};
+
+/// Registers for IR temporaries, in allocation order (`w9`-`w15`).
+const TEMPS: [u8; 7] = [9, 10, 11, 12, 13, 14, 15];
+/// Hold spilled operands while an instruction uses them (`w16`, `w17`).
+const SCRATCH: [u8; 2] = [16, 17];
+/// Parameters arrive in `w0`-`w7`.
+pub const MAX_PARAMS: usize = 8;
+
+const DIV_BY_ZERO: &str = ".Ldiv_by_zero";
+
+/// The largest immediate `ldr`/`str` can encode, which is scaled by the
+/// slot size.
+const MAX_SCALED_OFFSET: usize = 4095;
+/// The largest immediate `sub sp, sp, #imm` can encode.
+const MAX_ADD_IMM: usize = 4095;
+
+struct A64Gen<'a> {
+    /// Whether the function computes in `x` registers.
+    wide: bool,
+    locs: &'a [Loc],
+    /// Frame slot of the first spilled temporary.
+    spill_base: usize,
+    /// The value of each temporary assigned a constant.
+    constants: Vec<Option<u32>>,
+    lines: Vec<String>,
+    uses_div: bool,
+}
+
+impl<'a> A64Gen<'a> {
+    fn emit(&mut self, line: String) {
+        self.lines.push(format!("    {}", line));
+    }
+
+    /// The name of register `reg` at the width the function computes in.
+    fn reg(&self, reg: u8) -> String {
+        format!("{}{}", if self.wide { "x" } else { "w" }, reg)
+    }
+
+    fn slot_size(&self) -> usize {
+        if self.wide {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Offset from `sp` of frame slot `slot`; variables come first, then
+    /// spilled temporaries.
+    fn slot_offset(&self, slot: usize) -> usize {
+        self.slot_size() * slot
+    }
+
+    /// Emits `op` (`ldr` or `str`) of `reg` and frame slot `slot`.
+    fn access(&mut self, op: &str, reg: u8, slot: usize) {
+        let offset = self.slot_offset(slot);
+        let rt = self.reg(reg);
+        if offset <= self.slot_size() * MAX_SCALED_OFFSET {
+            self.emit(format!("{} {}, [sp, #{}]", op, rt, offset));
+        } else {
+            self.load_imm(8, offset as u64);
+            self.emit(format!("{} {}, [sp, x8]", op, rt));
+        }
+    }
+
+    /// Builds `value` in `rd` 16 bits at a time, skipping zero halfwords
+    /// after the first.
+    fn load_imm(&mut self, rd: u8, value: u64) {
+        let rd = self.reg(rd);
+        self.emit(format!("mov {}, #{}", rd, value & 0xFFFF));
+        for shift in [16, 32, 48].iter() {
+            let part = value >> shift & 0xFFFF;
+            if part != 0 {
+                self.emit(format!("movk {}, #{}, lsl #{}", rd, part, shift));
+            }
+        }
+    }
+
+    /// Returns the register holding `temp`, reloading it into `scratch` if
+    /// it was spilled.
+    fn read(&mut self, temp: Temp, scratch: u8) -> u8 {
+        match self.locs[temp] {
+            Loc::Reg(i) => TEMPS[i],
+            Loc::Slot(slot) => {
+                let slot = self.spill_base + slot;
+                self.access("ldr", scratch, slot);
+                scratch
+            }
+        }
+    }
+
+    /// Returns the register to compute `temp` into; `write` then stores it
+    /// if `temp` is spilled.
+    fn dest(&self, temp: Temp) -> u8 {
+        match self.locs[temp] {
+            Loc::Reg(i) => TEMPS[i],
+            Loc::Slot(_) => SCRATCH[0],
+        }
+    }
+
+    fn write(&mut self, temp: Temp, rd: u8) {
+        if let Loc::Slot(slot) = self.locs[temp] {
+            let slot = self.spill_base + slot;
+            self.access("str", rd, slot);
+        }
+    }
+
+    fn gen(&mut self, inst: &IrInst) {
+        match *inst {
+            IrInst::Const { dst, ref value, .. } => {
+                let rd = self.dest(dst);
+                self.load_imm(rd, value.bits() as u64);
+                self.write(dst, rd);
+            }
+            IrInst::Load { dst, var, .. } => {
+                let rd = self.dest(dst);
+                self.access("ldr", rd, var);
+                self.write(dst, rd);
+            }
+            IrInst::Store { var, src, .. } => {
+                let rs = self.read(src, SCRATCH[0]);
+                self.access("str", rs, var);
+            }
+            IrInst::Unary { dst, op, src, .. } => {
+                let rs = self.read(src, SCRATCH[0]);
+                let rd = self.dest(dst);
+                let mnemonic = match op {
+                    IrUnOp::Neg => "neg",
+                    IrUnOp::Not => "mvn",
+                };
+                let (rd_name, rs_name) = (self.reg(rd), self.reg(rs));
+                self.emit(format!("{} {}, {}", mnemonic, rd_name, rs_name));
+                self.write(dst, rd);
+            }
+            IrInst::Binary { dst, ty, op, lhs, rhs } => {
+                let rs1 = self.read(lhs, SCRATCH[0]);
+                let rs2 = self.read(rhs, SCRATCH[1]);
+                let rd = self.dest(dst);
+                let (d, n, m) = (self.reg(rd), self.reg(rs1), self.reg(rs2));
+                let mnemonic = match op {
+                    IrBinOp::Add => "add",
+                    IrBinOp::Sub => "sub",
+                    IrBinOp::Mul => "mul",
+                    IrBinOp::Div => {
+                        self.uses_div = true;
+                        self.emit(format!("cbz {}, {}", m, DIV_BY_ZERO));
+                        "udiv"
+                    }
+                    IrBinOp::Shl | IrBinOp::Shr if self.constants[rhs].is_none_or(|n| n >= ty.width) => {
+                        // The count is taken mod the width, so one of the
+                        // width or more selects 0, or becomes all ones (the
+                        // width less one) for `asr`, in `w8`/`x8`, which holds
+                        // no temporary.
+                        let (check, zero) = (self.reg(8), if self.wide { "xzr" } else { "wzr" });
+                        self.emit(format!("cmp {}, #{}", m, ty.width));
+                        if ty.signed {
+                            self.emit(format!("csinv {}, {}, {}, lo", check, m, zero));
+                            self.emit(format!("asr {}, {}, {}", d, n, check));
+                        } else {
+                            let mnemonic = if op == IrBinOp::Shl { "lsl" } else { "lsr" };
+                            self.emit(format!("{} {}, {}, {}", mnemonic, d, n, m));
+                            self.emit(format!("csel {}, {}, {}, lo", d, d, zero));
+                        }
+                        self.write(dst, rd);
+                        return;
+                    }
+                    IrBinOp::Shl => "lsl",
+                    IrBinOp::Shr if ty.signed => "asr",
+                    IrBinOp::Shr => "lsr",
+                    IrBinOp::And => "and",
+                    IrBinOp::Or => "orr",
+                    IrBinOp::Xor => "eor",
+                };
+                self.emit(format!("{} {}, {}, {}", mnemonic, d, n, m));
+                self.write(dst, rd);
+            }
+            IrInst::Ret { ty, src } => {
+                let rs = self.read(src, SCRATCH[0]);
+                let extract = if ty.signed { "sbfx" } else { "ubfx" };
+                self.emit(format!("{} x0, x{}, #0, #{}", extract, rs, ty.width));
+            }
+        }
+    }
+}
+
+/// Compiles `func` to an AArch64 function. Parameters arrive in `w0`,
+/// `w1`, ..., or `x0`, `x1`, ... above 32 bits; the result is returned in
+/// `x0`, extended to 64 bits as the IR's `ret` specifies.
+pub fn compile(func: &IrFunction) -> Result<String, CodegenError> {
+    let vars = &func.vars;
+    if vars.params.len() > MAX_PARAMS {
+        return Err(CodegenError::TooManyParams {
+            count: vars.params.len(),
+            max: MAX_PARAMS,
+            span: func.span,
+        });
+    }
+
+    let func = &regalloc::sethi_ullman(func);
+    let alloc = regalloc::linear_scan(func, TEMPS.len());
+    let mut gen = A64Gen {
+        wide: func.width() > 32,
+        locs: &alloc.locs,
+        spill_base: vars.vars.len(),
+        constants: func.constants(),
+        lines: Vec::new(),
+        uses_div: false,
+    };
+
+    let frame = (gen.slot_size() * (vars.vars.len() + alloc.spill_slots)).div_ceil(16) * 16;
+    if frame > 0 {
+        gen.emit("stp x29, x30, [sp, #-16]!".to_string());
+        gen.emit("mov x29, sp".to_string());
+        if frame <= MAX_ADD_IMM {
+            gen.emit(format!("sub sp, sp, #{}", frame));
+        } else {
+            gen.load_imm(8, frame as u64);
+            gen.emit("sub sp, sp, x8".to_string());
+        }
+        for i in 0..vars.params.len() {
+            gen.access("str", i as u8, i);
+        }
+    }
+
+    for inst in &func.insts {
+        gen.gen(inst);
+    }
+
+    if frame > 0 {
+        gen.emit("mov sp, x29".to_string());
+        gen.emit("ldp x29, x30, [sp], #16".to_string());
+    }
+    gen.emit("ret".to_string());
+    if gen.uses_div {
+        gen.lines.push(format!("{}:", DIV_BY_ZERO));
+        gen.emit("brk #0".to_string());
+    }
+
+    // Labels are the only lines `emit` did not indent.
+    let instructions = gen.lines.iter().filter(|line| line.starts_with(' ')).count();
+    let name = &func.name;
+    let mut out = format!("// ntlang: {}\n", func.source);
+    out.push_str(&format!("// {}\n", alloc.stats(instructions)));
+    for (i, param) in vars.params.iter().enumerate() {
+        out.push_str(&format!("// {} = {}\n", gen.reg(i as u8), param));
+    }
+    out.push_str(&format!("    .text\n    .globl {}\n    .type {}, %function\n{}:\n", name, name, name));
+    for line in &gen.lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str(&format!("    .size {}, .-{}\n", name, name));
+    Ok(out)
+}
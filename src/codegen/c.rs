@@ -0,0 +1,280 @@
+//! C source, as a function or a `#define`.
+//!
+//! Every value is a `uint32_t`, so `+`, `-` and `*` wrap exactly like
+//! `eval`. Multiplication and left shifts go through `1u * ...` so operands
+//! can never be promoted to a signed `int`. A shift by a variable goes
+//! through a helper that checks for a count of 32 or more, which C leaves
+//! undefined, and `>-` uses an xor trick instead of shifting a negative
+//! signed value. The result is converted to `int32_t` without relying on
+//! implementation-defined conversions.
+
+use codegen::{CodegenError, VarTableSt};
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrType, IrUnOp};
+
+/// Names a variable cannot take in the output: the C11 keywords, the C23
+/// spellings of `_Bool` and friends, the `<stdint.h>` types the output uses
+/// and `abort`, which the division helper may call.
+const RESERVED: [&str; 60] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "int", "long", "register", "return", "short", "signed", "sizeof", "static",
+    "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while",
+    "inline", "restrict", "_Bool", "_Complex", "_Imaginary",
+    "_Alignas", "_Alignof", "_Atomic", "_Generic", "_Noreturn", "_Static_assert", "_Thread_local",
+    "alignas", "alignof", "bool", "constexpr", "false", "nullptr", "static_assert", "thread_local", "true",
+    "typeof", "typeof_unqual", "uint32_t", "int32_t", "uint64_t", "int64_t", "abort",
+];
+
+const SHL_HELPER: &str = "\
+static inline uint32_t ntlang_shl(uint32_t a, uint32_t n)
+{
+    return n < 32u ? (uint32_t)(1u * a << n) : 0u;
+}
+";
+
+const SHR_HELPER: &str = "\
+static inline uint32_t ntlang_shr(uint32_t a, uint32_t n)
+{
+    return n < 32u ? a >> n : 0u;
+}
+";
+
+const SRA_HELPER: &str = "\
+static inline uint32_t ntlang_sra(uint32_t a, uint32_t n)
+{
+    uint32_t sign = 0u - (a >> 31);
+    return ((a ^ sign) >> (n < 31u ? n : 31u)) ^ sign;
+}
+";
+
+const DIV_HELPER: &str = "\
+#ifndef NTLANG_DIV_BY_ZERO
+#define NTLANG_DIV_BY_ZERO() abort()
+#endif
+
+static inline uint32_t ntlang_div(uint32_t a, uint32_t b)
+{
+    if (b == 0u) {
+        NTLANG_DIV_BY_ZERO();
+        return 0u;
+    }
+    return a / b;
+}
+";
+
+const SIGNED_HELPER: &str = "\
+static inline int32_t ntlang_signed(uint32_t v)
+{
+    return v >= 0x80000000u ? -(int32_t)~v - 1 : (int32_t)v;
+}
+";
+
+struct CGen<'a> {
+    vars: &'a VarTableSt,
+    /// Parenthesize and cast variable references, for macro arguments.
+    in_macro: bool,
+    /// The C expression computing each IR temporary.
+    temps: Vec<String>,
+    /// The value of each temporary assigned a constant.
+    constants: Vec<Option<u32>>,
+    uses_shl: bool,
+    uses_shr: bool,
+    uses_sra: bool,
+    uses_div: bool,
+}
+
+/// A variable's name in C, avoiding `RESERVED` and the `ntlang_` prefix.
+/// Names ending in `_` get another one, so no two variables share a name.
+fn c_name(name: &str) -> String {
+    if RESERVED.contains(&name) || name.starts_with("ntlang_") || name.ends_with('_') {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+impl<'a> CGen<'a> {
+    /// The C expression for an instruction's result. Temporaries are read
+    /// once, so each operand's expression is inlined where it is used.
+    fn expr(&mut self, inst: &IrInst) -> String {
+        match *inst {
+            IrInst::Const { ref value, .. } => {
+                let value = value.bits() as u32;
+                if value < 10 {
+                    format!("{}u", value)
+                } else {
+                    format!("0x{:X}u", value)
+                }
+            }
+            IrInst::Load { var, .. } => {
+                let name = c_name(&self.vars.vars[var]);
+                if self.in_macro {
+                    format!("((uint32_t)({}))", name)
+                } else {
+                    name
+                }
+            }
+            IrInst::Unary { op, src, .. } => {
+                let a = &self.temps[src];
+                match op {
+                    IrUnOp::Neg => format!("(uint32_t)(0u - {})", a),
+                    IrUnOp::Not => format!("(uint32_t)~{}", a),
+                }
+            }
+            IrInst::Binary { ty, op, lhs, rhs, .. } => {
+                let (a, b) = (&self.temps[lhs], &self.temps[rhs]);
+                let short = self.constants[rhs].is_some_and(|n| n < 32);
+                match op {
+                    IrBinOp::Add => format!("(uint32_t)({} + {})", a, b),
+                    IrBinOp::Sub => format!("(uint32_t)({} - {})", a, b),
+                    IrBinOp::Mul => format!("(uint32_t)(1u * {} * {})", a, b),
+                    IrBinOp::Div => {
+                        self.uses_div = true;
+                        format!("ntlang_div({}, {})", a, b)
+                    }
+                    IrBinOp::Shl if short => format!("(uint32_t)(1u * {} << {})", a, b),
+                    IrBinOp::Shl => {
+                        self.uses_shl = true;
+                        format!("ntlang_shl({}, {})", a, b)
+                    }
+                    IrBinOp::Shr if ty.signed => {
+                        self.uses_sra = true;
+                        format!("ntlang_sra({}, {})", a, b)
+                    }
+                    IrBinOp::Shr if short => format!("({} >> {})", a, b),
+                    IrBinOp::Shr => {
+                        self.uses_shr = true;
+                        format!("ntlang_shr({}, {})", a, b)
+                    }
+                    IrBinOp::And => format!("({} & {})", a, b),
+                    IrBinOp::Or => format!("({} | {})", a, b),
+                    IrBinOp::Xor => format!("({} ^ {})", a, b),
+                }
+            }
+            IrInst::Store { .. } | IrInst::Ret { .. } => unreachable!(),
+        }
+    }
+
+    /// Reduces `value` to `ty.width` bits in the type returned by the
+    /// generated function.
+    fn result(&mut self, value: &str, ty: IrType) -> String {
+        let masked = if ty.width < 32 {
+            format!("({} & 0x{:X}u)", value, (1u32 << ty.width) - 1)
+        } else {
+            value.to_string()
+        };
+        if !ty.signed {
+            return masked;
+        }
+        if ty.width < 32 {
+            // Sign-extend from bit `width - 1`.
+            let sign = 1u32 << (ty.width - 1);
+            format!("ntlang_signed((uint32_t)(({} ^ 0x{:X}u) - 0x{:X}u))", masked, sign, sign)
+        } else {
+            format!("ntlang_signed({})", masked)
+        }
+    }
+}
+
+/// Compiles `func` to a C function `ntlang_expr` taking the free variables
+/// as `uint32_t` parameters, or, with `define` set, to a function-like
+/// macro `NTLANG_EXPR`. A zero divisor calls `NTLANG_DIV_BY_ZERO()`, which
+/// defaults to `abort()`.
+pub fn compile(func: &IrFunction, define: bool) -> Result<String, CodegenError> {
+    let vars = &func.vars;
+    if define && vars.vars.len() > vars.params.len() {
+        return Err(CodegenError::Unsupported {
+            what: "assignments in a #define".to_string(),
+            span: func.span,
+        });
+    }
+
+    let params: Vec<String> = vars.params.iter().map(|name| c_name(name)).collect();
+    let uses = func.use_counts();
+    let mut gen = CGen {
+        vars,
+        in_macro: define,
+        temps: vec![String::new(); func.temps],
+        constants: func.constants(),
+        uses_shl: false,
+        uses_shr: false,
+        uses_sra: false,
+        uses_div: false,
+    };
+
+    // Statements followed by the returned value. In a macro the statements
+    // can only be discarded values, which go in a comma expression.
+    let mut stmts = Vec::new();
+    let mut ret = IrType::unsigned(32);
+    for inst in &func.insts {
+        match *inst {
+            IrInst::Store { var, src, .. } => {
+                stmts.push(format!("{} = {}", c_name(&vars.vars[var]), gen.temps[src]));
+            }
+            IrInst::Ret { ty, src } => {
+                let value = gen.temps[src].clone();
+                let result = gen.result(&value, ty);
+                stmts.push(result);
+                ret = ty;
+            }
+            _ => {
+                let dst = inst.dst().unwrap();
+                gen.temps[dst] = gen.expr(inst);
+                if uses[dst] == 0 {
+                    stmts.push(format!("(void){}", gen.temps[dst]));
+                }
+            }
+        }
+    }
+    let result = stmts.pop().unwrap();
+
+    let body = if define {
+        let mut value = result;
+        if !stmts.is_empty() {
+            value = format!("({}, {})", stmts.join(", "), value);
+        }
+        format!("#define NTLANG_EXPR({}) ({})\n", params.join(", "), value)
+    } else {
+        let ret_type = if ret.signed { "int32_t" } else { "uint32_t" };
+        let mut body = String::new();
+        let args: Vec<String> = params.iter().map(|name| format!("uint32_t {}", name)).collect();
+        let args = if args.is_empty() { "void".to_string() } else { args.join(", ") };
+        body.push_str(&format!("static inline {} {}({})\n{{\n", ret_type, func.name, args));
+        for name in &vars.vars[vars.params.len()..] {
+            body.push_str(&format!("    uint32_t {};\n", c_name(name)));
+        }
+        for stmt in &stmts {
+            body.push_str(&format!("    {};\n", stmt));
+        }
+        body.push_str(&format!("    return {};\n}}\n", result));
+        body
+    };
+
+    let mut out = format!("/* ntlang: {} */\n", func.source.replace("*/", "* /"));
+    out.push_str("#include <stdint.h>\n");
+    if gen.uses_div {
+        out.push_str("#include <stdlib.h>\n");
+    }
+    out.push('\n');
+    if gen.uses_div {
+        out.push_str(DIV_HELPER);
+        out.push('\n');
+    }
+    if gen.uses_shl {
+        out.push_str(SHL_HELPER);
+        out.push('\n');
+    }
+    if gen.uses_shr {
+        out.push_str(SHR_HELPER);
+        out.push('\n');
+    }
+    if gen.uses_sra {
+        out.push_str(SRA_HELPER);
+        out.push('\n');
+    }
+    if ret.signed {
+        out.push_str(SIGNED_HELPER);
+        out.push('\n');
+    }
+    out.push_str(&body);
+    Ok(out)
+}
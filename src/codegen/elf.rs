@@ -0,0 +1,306 @@
+//! A static Linux x86-64 executable, written without an assembler or linker.
+//!
+//! The program reads the expression's free variables from its arguments, in
+//! parameter order, as decimal, `0x` hexadecimal or `0b` binary integers,
+//! each optionally negated with a leading `-` and taken modulo 2^32. It then
+//! runs the IR and prints the result the way `eval_print` does, in the base
+//! chosen when it was compiled. Bad arguments exit with status 1 and a zero
+//! divisor with status 4, matching the command line tool.
+//!
+//! Code generation favours simplicity over speed: every variable and
+//! temporary lives in a frame slot, as `x86_64_enc::instruction` lays them
+//! out. The file is a single read-only, executable segment holding the
+//! headers, the code and the messages.
+
+use codegen::CodegenError;
+use codegen::ir::{IrFunction, IrInst};
+use codegen::x86_64_enc::*;
+use config::Config;
+
+/// Where the segment is loaded.
+const BASE_ADDR: u64 = 0x40_0000;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+/// Bytes reserved below the slots for the formatted result.
+const BUFFER_SIZE: i32 = 64;
+
+const SYS_WRITE: u32 = 1;
+const SYS_EXIT: u32 = 60;
+
+const EXIT_USAGE_ERROR: u32 = 1;
+const EXIT_EVAL_ERROR: u32 = 4;
+
+/// Emits `write(fd, label, len); exit(status)`.
+fn write_and_exit(asm: &mut Asm, fd: u32, message: Label, len: usize, status: u32) {
+    asm.mov_imm(RAX, SYS_WRITE);
+    asm.mov_imm(RDI, fd);
+    asm.lea_label(RSI, message);
+    asm.mov_imm(RDX, len as u32);
+    asm.syscall();
+    asm.mov_imm(RAX, SYS_EXIT);
+    asm.mov_imm(RDI, status);
+    asm.syscall();
+}
+
+/// Emits the routine that parses the NUL-terminated string at `rsi` into
+/// `eax`, jumping to `usage` if it is not an integer. Clobbers `ecx`,
+/// `edx`, `rsi`, `r8d` and `r9d`.
+fn parse_number(asm: &mut Asm, usage: Label) {
+    let positive = asm.label();
+    let not_hex = asm.label();
+    let digits = asm.label();
+    let next = asm.label();
+    let have_digit = asm.label();
+    let done = asm.label();
+    let unsigned = asm.label();
+
+    asm.alu(XOR, false, RAX, RAX);
+    asm.alu(XOR, false, R8, R8);
+    asm.mov_imm(R9, 10);
+    asm.load_byte(RCX, RSI, 0);
+    asm.alu_imm(CMP, false, RCX, b'-' as u32);
+    asm.jcc(CC_NE, positive);
+    asm.inc(false, R8);
+    asm.inc(true, RSI);
+    asm.bind(positive);
+
+    // A `0x` or `0b` prefix selects the base.
+    asm.load_byte(RCX, RSI, 0);
+    asm.alu_imm(CMP, false, RCX, b'0' as u32);
+    asm.jcc(CC_NE, digits);
+    asm.load_byte(RCX, RSI, 1);
+    asm.alu_imm(OR, false, RCX, 0x20);
+    asm.alu_imm(CMP, false, RCX, b'x' as u32);
+    asm.jcc(CC_NE, not_hex);
+    asm.mov_imm(R9, 16);
+    asm.lea(RSI, RSI, 2);
+    asm.jmp(digits);
+    asm.bind(not_hex);
+    asm.alu_imm(CMP, false, RCX, b'b' as u32);
+    asm.jcc(CC_NE, digits);
+    asm.mov_imm(R9, 2);
+    asm.lea(RSI, RSI, 2);
+
+    asm.bind(digits);
+    asm.load_byte(RCX, RSI, 0);
+    asm.test(false, RCX, RCX);
+    asm.jcc(CC_E, usage);
+    asm.bind(next);
+    asm.load_byte(RCX, RSI, 0);
+    asm.test(false, RCX, RCX);
+    asm.jcc(CC_E, done);
+    asm.lea(RDX, RCX, -(b'0' as i32));
+    asm.alu_imm(CMP, false, RDX, 9);
+    asm.jcc(CC_BE, have_digit);
+    // Letters, either case, are digits 10 and up.
+    asm.alu_imm(OR, false, RCX, 0x20);
+    asm.lea(RDX, RCX, -(b'a' as i32));
+    asm.alu_imm(CMP, false, RDX, 5);
+    asm.jcc(CC_A, usage);
+    asm.alu_imm(ADD, false, RDX, 10);
+    asm.bind(have_digit);
+    asm.alu(CMP, false, RDX, R9);
+    asm.jcc(CC_AE, usage);
+    asm.imul(false, RAX, R9);
+    asm.alu(ADD, false, RAX, RDX);
+    asm.inc(true, RSI);
+    asm.jmp(next);
+
+    asm.bind(done);
+    asm.test(false, R8, R8);
+    asm.jcc(CC_E, unsigned);
+    asm.unary(NEG, false, RAX);
+    asm.bind(unsigned);
+    asm.ret();
+}
+
+/// Emits code that formats `rax`, the result as `ret` extends it, into the
+/// buffer ending at `rdi`, moving `rdi` back to the first character.
+fn format_result(asm: &mut Asm, cp: &Config, width: u32, signed: bool) {
+    asm.dec(true, RDI);
+    asm.store_byte_imm(RDI, b'\n');
+    match cp.base {
+        10 => {
+            let positive = asm.label();
+            let next = asm.label();
+            let done = asm.label();
+            asm.alu(XOR, false, R8, R8);
+            if signed {
+                asm.test(true, RAX, RAX);
+                asm.jcc(CC_NS, positive);
+                asm.unary(NEG, true, RAX);
+                asm.inc(false, R8);
+                asm.bind(positive);
+            }
+            asm.mov_imm(RCX, 10);
+            asm.bind(next);
+            asm.alu(XOR, false, RDX, RDX);
+            asm.unary(DIV, true, RCX);
+            asm.alu_imm(ADD, false, RDX, b'0' as u32);
+            asm.dec(true, RDI);
+            asm.store_byte(RDI, RDX);
+            asm.test(true, RAX, RAX);
+            asm.jcc(CC_NE, next);
+            asm.test(false, R8, R8);
+            asm.jcc(CC_E, done);
+            asm.dec(true, RDI);
+            asm.store_byte_imm(RDI, b'-');
+            asm.bind(done);
+        }
+        2 | 16 => {
+            let (bits, count, prefix) = if cp.base == 2 {
+                (1, width, b'b')
+            } else {
+                (4, width.div_ceil(4), b'x')
+            };
+            let next = asm.label();
+            let decimal = asm.label();
+            if width < 32 {
+                asm.alu_imm(AND, false, RAX, (1u32 << width) - 1);
+            } else {
+                asm.mov(false, RAX, RAX);
+            }
+            asm.mov_imm(RCX, count);
+            asm.bind(next);
+            asm.mov(false, RDX, RAX);
+            asm.alu_imm(AND, false, RDX, (1 << bits) - 1);
+            asm.alu_imm(CMP, false, RDX, 10);
+            asm.jcc(CC_B, decimal);
+            asm.alu_imm(ADD, false, RDX, (b'A' - b'0' - 10) as u32);
+            asm.bind(decimal);
+            asm.alu_imm(ADD, false, RDX, b'0' as u32);
+            asm.dec(true, RDI);
+            asm.store_byte(RDI, RDX);
+            asm.shift_imm(SHR, false, RAX, bits);
+            asm.dec(false, RCX);
+            asm.jcc(CC_NE, next);
+            asm.dec(true, RDI);
+            asm.store_byte_imm(RDI, prefix);
+            asm.dec(true, RDI);
+            asm.store_byte_imm(RDI, b'0');
+        }
+        // `eval_format` prints nothing for other bases.
+        _ => {}
+    }
+}
+
+/// Compiles `func` to a static executable that prints its result in
+/// `cp.base`.
+pub fn compile(func: &IrFunction, cp: &Config) -> Result<Vec<u8>, CodegenError> {
+    let ret = match func.insts.last() {
+        Some(&IrInst::Ret { ty, .. }) => ty,
+        _ => unreachable!("IR functions end in ret"),
+    };
+    if func.insts.iter().any(|inst| match *inst {
+        IrInst::Unary { ty, .. } | IrInst::Binary { ty, .. } => ty.width != 32,
+        IrInst::Ret { ty, .. } => ty.width > 32,
+        _ => false,
+    }) {
+        return Err(CodegenError::Unsupported {
+            what: "arithmetic wider than 32 bits".to_string(),
+            span: func.span,
+        });
+    }
+
+    let vars = &func.vars;
+    let params = vars.params.len();
+    let slots = 4 * (vars.vars.len() + func.temps) as i32;
+    let frame = (slots + 15) / 16 * 16 + BUFFER_SIZE;
+
+    let usage_text = if params == 0 {
+        "Error: expected no arguments\n".to_string()
+    } else {
+        format!(
+            "Error: expected arguments {} as decimal, 0x hex or 0b binary integers\n",
+            vars.params.join(" ")
+        )
+    };
+    let div_text = "Error: Division by zero\n";
+
+    let mut asm = Asm::new();
+    let usage = asm.label();
+    let div_by_zero = asm.label();
+    let parse = asm.label();
+    let usage_message = asm.label();
+    let div_message = asm.label();
+
+    // At entry `rsp` points at argc, followed by the argv pointers.
+    asm.mov(true, RBP, RSP);
+    asm.alu_imm(SUB, true, RSP, frame as u32);
+    asm.alu_mem_imm(CMP, true, RBP, 0, params as u32 + 1);
+    asm.jcc(CC_NE, usage);
+    for i in 0..params {
+        asm.load(true, RSI, RBP, 8 * (i as i32 + 2));
+        asm.call(parse);
+        asm.store(RBP, -4 * (i as i32 + 1), RAX);
+    }
+
+    for inst in &func.insts {
+        instruction(&mut asm, inst, vars.vars.len(), div_by_zero);
+    }
+
+    // The buffer is the bottom `BUFFER_SIZE` bytes of the frame.
+    let buffer_end = BUFFER_SIZE - frame;
+    asm.lea(RDI, RBP, buffer_end);
+    format_result(&mut asm, cp, ret.width, ret.signed);
+    asm.mov(true, RSI, RDI);
+    asm.lea(RDX, RBP, buffer_end);
+    asm.alu(SUB, true, RDX, RSI);
+    asm.mov_imm(RAX, SYS_WRITE);
+    asm.mov_imm(RDI, 1);
+    asm.syscall();
+    asm.mov_imm(RAX, SYS_EXIT);
+    asm.alu(XOR, false, RDI, RDI);
+    asm.syscall();
+
+    asm.bind(usage);
+    write_and_exit(&mut asm, 2, usage_message, usage_text.len(), EXIT_USAGE_ERROR);
+    asm.bind(div_by_zero);
+    write_and_exit(&mut asm, 2, div_message, div_text.len(), EXIT_EVAL_ERROR);
+    asm.bind(parse);
+    parse_number(&mut asm, usage);
+
+    asm.bind(usage_message);
+    asm.bytes(usage_text.as_bytes());
+    asm.bind(div_message);
+    asm.bytes(div_text.as_bytes());
+
+    Ok(write_elf(&asm.finish()))
+}
+
+/// Wraps `image`, which starts with the entry point, in an ELF header and a
+/// single loadable segment.
+fn write_elf(image: &[u8]) -> Vec<u8> {
+    let headers = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+    let size = (headers + image.len()) as u64;
+    let mut out = Vec::with_capacity(headers + image.len());
+
+    // e_ident: 64-bit, little-endian, version 1, System V ABI.
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type: ET_EXEC
+    out.extend_from_slice(&62u16.to_le_bytes()); // e_machine: EM_X86_64
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&(BASE_ADDR + headers as u64).to_le_bytes()); // e_entry
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    out.extend_from_slice(&1u32.to_le_bytes()); // p_type: PT_LOAD
+    out.extend_from_slice(&5u32.to_le_bytes()); // p_flags: R + X
+    out.extend_from_slice(&0u64.to_le_bytes()); // p_offset
+    out.extend_from_slice(&BASE_ADDR.to_le_bytes()); // p_vaddr
+    out.extend_from_slice(&BASE_ADDR.to_le_bytes()); // p_paddr
+    out.extend_from_slice(&size.to_le_bytes()); // p_filesz
+    out.extend_from_slice(&size.to_le_bytes()); // p_memsz
+    out.extend_from_slice(&0x1000u64.to_le_bytes()); // p_align
+
+    out.extend_from_slice(image);
+    out
+}
@@ -0,0 +1,480 @@
+//! A linear three-address IR between the parse tree and the code generators.
+//!
+//! `lower` turns a program into a list of instructions over numbered
+//! temporaries. Each instruction carries the width and signedness it operates
+//! at, so a backend picks `lshr` or `ashr`, `sext` or `zext`, from the
+//! instruction alone. Lowering walks the tree in evaluation order, so every
+//! temporary is assigned exactly once and read at most once, and the live
+//! temporaries always form a stack: an instruction's operands are the most
+//! recently defined live temporaries, in order. The C and WebAssembly
+//! backends rely on this to rebuild nested expressions.
+//!
+//! Instructions operate at the program's width. `legalize` rewrites a
+//! function narrower than a register into 32- or 64-bit instructions for
+//! the backends that compute in registers; it keeps the invariants above.
+
+use std::fmt;
+use codegen::VarTableSt;
+use config::Config;
+use opt;
+use parse::{ParseNode, ParseNodeType, ParseOperator};
+use scan::Span;
+use word::Word;
+
+/// Index of a temporary.
+pub type Temp = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IrType {
+    pub width: u32,
+    pub signed: bool,
+}
+
+impl IrType {
+    pub fn unsigned(width: u32) -> Self {
+        IrType { width, signed: false }
+    }
+
+    pub fn signed(width: u32) -> Self {
+        IrType { width, signed: true }
+    }
+}
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.width)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IrUnOp {
+    Neg,
+    Not,
+}
+
+/// Binary operators. `Shr` is arithmetic when its type is signed. `Div`
+/// traps on a zero divisor; lowering only produces unsigned divisions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IrBinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+
+impl fmt::Display for IrUnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for IrBinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrInst {
+    /// `dst = value`, where `value` is `ty.width` bits wide.
+    Const { dst: Temp, ty: IrType, value: Word },
+    /// `dst = var`, where `var` indexes `IrFunction::vars.vars`.
+    Load { dst: Temp, ty: IrType, var: usize },
+    /// `var = src`
+    Store { var: usize, ty: IrType, src: Temp },
+    /// `dst = op src`
+    Unary { dst: Temp, ty: IrType, op: IrUnOp, src: Temp },
+    /// `dst = lhs op rhs`. A shift count of `ty.width` or more moves every
+    /// bit out, leaving 0, or copies of the sign bit for a signed `shr`.
+    Binary { dst: Temp, ty: IrType, op: IrBinOp, lhs: Temp, rhs: Temp },
+    /// Returns the low `ty.width` bits of `src`, sign-extended if
+    /// `ty.signed` and zero-extended otherwise.
+    Ret { ty: IrType, src: Temp },
+}
+
+impl IrInst {
+    /// The temporary this instruction assigns, if any.
+    pub fn dst(&self) -> Option<Temp> {
+        match *self {
+            IrInst::Const { dst, .. }
+            | IrInst::Load { dst, .. }
+            | IrInst::Unary { dst, .. }
+            | IrInst::Binary { dst, .. } => Some(dst),
+            IrInst::Store { .. } | IrInst::Ret { .. } => None,
+        }
+    }
+
+    /// The temporaries this instruction reads, in operand order.
+    pub fn srcs(&self) -> Vec<Temp> {
+        match *self {
+            IrInst::Const { .. } | IrInst::Load { .. } => vec![],
+            IrInst::Store { src, .. } | IrInst::Unary { src, .. } | IrInst::Ret { src, .. } => vec![src],
+            IrInst::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+        }
+    }
+}
+
+/// A lowered program: one function whose parameters are the program's free
+/// variables.
+#[derive(Clone)]
+pub struct IrFunction {
+    pub name: String,
+    pub source: String,
+    /// The whole program, for errors about it.
+    pub span: Span,
+    pub vars: VarTableSt,
+    /// Number of temporaries; they are numbered from 0.
+    pub temps: usize,
+    pub insts: Vec<IrInst>,
+}
+
+impl IrFunction {
+    /// How many instructions read each temporary. A count of 0 marks a
+    /// statement whose value is discarded.
+    pub fn use_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.temps];
+        for inst in &self.insts {
+            for src in inst.srcs() {
+                counts[src] += 1;
+            }
+        }
+        counts
+    }
+
+    /// The program's width, which `ret` keeps through `legalize`.
+    pub fn width(&self) -> u32 {
+        match self.insts.last() {
+            Some(&IrInst::Ret { ty, .. }) => ty.width,
+            _ => unreachable!("IR functions end in ret"),
+        }
+    }
+
+    /// The value of each temporary that `const` assigns, if it fits in 32
+    /// bits. Backends emit a shift by a constant below the width without
+    /// checking the count.
+    pub fn constants(&self) -> Vec<Option<u32>> {
+        let mut values = vec![None; self.temps];
+        for inst in &self.insts {
+            if let IrInst::Const { dst, ref value, .. } = *inst {
+                if value.significant_bits() <= 32 {
+                    values[dst] = Some(value.bits() as u32);
+                }
+            }
+        }
+        values
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "; ntlang: {}", self.source)?;
+        write!(f, "func {}({})", self.name, self.vars.params.join(", "))?;
+        if let Some(&IrInst::Ret { ty, .. }) = self.insts.last() {
+            write!(f, " -> {}", ty)?;
+        }
+        writeln!(f, " {{")?;
+        for inst in &self.insts {
+            write!(f, "    ")?;
+            match *inst {
+                IrInst::Const { dst, ty, ref value } => writeln!(f, "%{} = const.{} {}", dst, ty, value)?,
+                IrInst::Load { dst, ty, var } => writeln!(f, "%{} = load.{} {}", dst, ty, self.vars.vars[var])?,
+                IrInst::Store { var, ty, src } => writeln!(f, "store.{} {}, %{}", ty, self.vars.vars[var], src)?,
+                IrInst::Unary { dst, ty, op, src } => writeln!(f, "%{} = {}.{} %{}", dst, op, ty, src)?,
+                IrInst::Binary { dst, ty, op, lhs, rhs } => {
+                    writeln!(f, "%{} = {}.{} %{}, %{}", dst, op, ty, lhs, rhs)?
+                }
+                IrInst::Ret { ty, src } => writeln!(f, "ret.{} %{}", ty, src)?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+struct IrGen<'a> {
+    vars: &'a VarTableSt,
+    width: u32,
+    temps: usize,
+    insts: Vec<IrInst>,
+}
+
+impl<'a> IrGen<'a> {
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        self.temps - 1
+    }
+
+    /// Lowers a statement whose value is not needed.
+    fn stmt(&mut self, node: &ParseNode) {
+        match node.type_ {
+            ParseNodeType::Seq => {
+                for stmt in &node.stmts {
+                    self.stmt(stmt);
+                }
+            }
+            ParseNodeType::Assign => {
+                let src = self.expr(node.left.as_ref().unwrap());
+                let var = self.vars.slot(&node.name);
+                self.insts.push(IrInst::Store { var, ty: IrType::unsigned(self.width), src });
+            }
+            _ => {
+                self.expr(node);
+            }
+        }
+    }
+
+    /// Lowers `node` and returns the temporary holding its value.
+    fn expr(&mut self, node: &ParseNode) -> Temp {
+        let ty = IrType::unsigned(self.width);
+        match node.type_ {
+            ParseNodeType::Literal => {
+                let dst = self.temp();
+                let value = node.value.resize(self.width);
+                self.insts.push(IrInst::Const { dst, ty, value });
+                dst
+            }
+            ParseNodeType::Ident => {
+                let dst = self.temp();
+                let var = self.vars.slot(&node.name);
+                self.insts.push(IrInst::Load { dst, ty, var });
+                dst
+            }
+            ParseNodeType::Assign => {
+                self.stmt(node);
+                let dst = self.temp();
+                let var = self.vars.slot(&node.name);
+                self.insts.push(IrInst::Load { dst, ty, var });
+                dst
+            }
+            ParseNodeType::Seq => {
+                let (last, init) = node.stmts.split_last().unwrap();
+                for stmt in init {
+                    self.stmt(stmt);
+                }
+                self.expr(last)
+            }
+            ParseNodeType::Oper1 => {
+                let src = self.expr(node.left.as_ref().unwrap());
+                let op = match node.oper {
+                    ParseOperator::Minus => IrUnOp::Neg,
+                    ParseOperator::BitNot => IrUnOp::Not,
+                    _ => return src,
+                };
+                let dst = self.temp();
+                self.insts.push(IrInst::Unary { dst, ty, op, src });
+                dst
+            }
+            ParseNodeType::Oper2 => {
+                let lhs = self.expr(node.left.as_ref().unwrap());
+                let rhs = self.expr(node.right.as_ref().unwrap());
+                let (op, ty) = match node.oper {
+                    ParseOperator::Plus => (IrBinOp::Add, ty),
+                    ParseOperator::Minus => (IrBinOp::Sub, ty),
+                    ParseOperator::Mult => (IrBinOp::Mul, ty),
+                    ParseOperator::Div => (IrBinOp::Div, ty),
+                    ParseOperator::ShiftLeft => (IrBinOp::Shl, ty),
+                    ParseOperator::ShiftRight => (IrBinOp::Shr, ty),
+                    ParseOperator::ArithShiftRight => (IrBinOp::Shr, IrType::signed(self.width)),
+                    ParseOperator::BitAnd => (IrBinOp::And, ty),
+                    ParseOperator::BitOr => (IrBinOp::Or, ty),
+                    ParseOperator::BitXor => (IrBinOp::Xor, ty),
+                    ParseOperator::BitNot => unreachable!(),
+                };
+                let dst = self.temp();
+                self.insts.push(IrInst::Binary { dst, ty, op, lhs, rhs });
+                dst
+            }
+            ParseNodeType::None => {
+                let dst = self.temp();
+                self.insts.push(IrInst::Const { dst, ty, value: Word::zero(self.width) });
+                dst
+            }
+        }
+    }
+}
+
+/// Lowers `node` to a function computing at `cp.width` bits and returning
+/// its value signed when results print as signed. The tree is simplified
+/// first when `cp.optimize` is set; the parameters are still those of
+/// `node`, even if simplification dropped every read of one.
+pub fn lower(node: &ParseNode, cp: &Config) -> IrFunction {
+    let vars = VarTableSt::new(node);
+    let optimized;
+    let body = if cp.optimize {
+        optimized = opt::optimize(node, cp.width).0;
+        &optimized
+    } else {
+        node
+    };
+    let (temps, insts) = {
+        let mut gen = IrGen {
+            vars: &vars,
+            width: cp.width,
+            temps: 0,
+            insts: Vec::new(),
+        };
+        let src = gen.expr(body);
+        let ty = IrType {
+            width: cp.width,
+            signed: cp.unsigned_int,
+        };
+        gen.insts.push(IrInst::Ret { ty, src });
+        (gen.temps, gen.insts)
+    };
+    IrFunction {
+        name: "ntlang_expr".to_string(),
+        source: cp.expression.replace('\n', " "),
+        span: node.span,
+        vars,
+        temps,
+        insts,
+    }
+}
+
+struct Legalizer {
+    width: u32,
+    /// The register width instructions are rewritten to, 32 or 64.
+    reg: u32,
+    temps: usize,
+    insts: Vec<IrInst>,
+}
+
+impl Legalizer {
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        self.temps - 1
+    }
+
+    fn constant(&mut self, value: u64) -> Temp {
+        let dst = self.temp();
+        let ty = IrType::unsigned(self.reg);
+        self.insts.push(IrInst::Const { dst, ty, value: Word::new(u128::from(value), self.reg) });
+        dst
+    }
+
+    fn binary(&mut self, op: IrBinOp, signed: bool, lhs: Temp, rhs: Temp) -> Temp {
+        let dst = self.temp();
+        let ty = IrType { width: self.reg, signed };
+        self.insts.push(IrInst::Binary { dst, ty, op, lhs, rhs });
+        dst
+    }
+
+    /// `op` applied to `lhs` and a constant, as the next instruction.
+    fn binary_imm(&mut self, op: IrBinOp, signed: bool, lhs: Temp, value: u64) -> Temp {
+        let rhs = self.constant(value);
+        self.binary(op, signed, lhs, rhs)
+    }
+
+    /// Clears the bits of `src` above the width.
+    fn mask(&mut self, src: Temp) -> Temp {
+        self.binary_imm(IrBinOp::And, false, src, (1 << self.width) - 1)
+    }
+}
+
+/// Rewrites `func` into instructions on the narrowest register that holds
+/// its width, 32 or 64 bits; a function of exactly 32 or 64 bits, or wider,
+/// is returned unchanged. Every temporary then holds its narrow value
+/// zero-extended: parameters are masked when loaded, and so is every result
+/// that can carry into the upper bits. Shift counts need no change, since a
+/// count between the width and the register's moves the narrow value out
+/// of the register too. The left operand of an arithmetic shift is moved to
+/// the top of the register as soon as it is computed, so the sign bit is
+/// where the register's `sar` looks for it. `ret` keeps its type.
+pub fn legalize(func: &IrFunction) -> IrFunction {
+    let width = func.insts.iter().fold(u32::MAX, |width, inst| match *inst {
+        IrInst::Const { ty, .. }
+        | IrInst::Load { ty, .. }
+        | IrInst::Store { ty, .. }
+        | IrInst::Unary { ty, .. }
+        | IrInst::Binary { ty, .. } => width.min(ty.width),
+        IrInst::Ret { .. } => width,
+    });
+    if width == 32 || width >= 64 {
+        return func.clone();
+    }
+    let reg = if width < 32 { 32 } else { 64 };
+
+    let mut to_top = vec![false; func.temps];
+    for inst in &func.insts {
+        if let IrInst::Binary { ty, op: IrBinOp::Shr, lhs, .. } = *inst {
+            to_top[lhs] = ty.signed;
+        }
+    }
+    let top = u64::from(reg - width);
+    let params = func.vars.params.len();
+
+    let mut gen = Legalizer {
+        width,
+        reg,
+        temps: 0,
+        insts: Vec::new(),
+    };
+    let mut map = vec![0; func.temps];
+    for inst in &func.insts {
+        let ty = IrType::unsigned(reg);
+        let value = match *inst {
+            IrInst::Const { ref value, .. } => gen.constant(value.bits() as u64),
+            IrInst::Load { var, .. } => {
+                let dst = gen.temp();
+                gen.insts.push(IrInst::Load { dst, ty, var });
+                if var < params {
+                    gen.mask(dst)
+                } else {
+                    dst
+                }
+            }
+            IrInst::Store { var, src, .. } => {
+                gen.insts.push(IrInst::Store { var, ty, src: map[src] });
+                continue;
+            }
+            IrInst::Unary { op, src, .. } => {
+                let dst = gen.temp();
+                gen.insts.push(IrInst::Unary { dst, ty, op, src: map[src] });
+                gen.mask(dst)
+            }
+            IrInst::Binary { ty, op, lhs, rhs, .. } => {
+                let (lhs, rhs) = (map[lhs], map[rhs]);
+                match op {
+                    IrBinOp::Add | IrBinOp::Sub | IrBinOp::Mul => {
+                        let dst = gen.binary(op, false, lhs, rhs);
+                        gen.mask(dst)
+                    }
+                    IrBinOp::Shl => {
+                        let dst = gen.binary(op, false, lhs, rhs);
+                        gen.mask(dst)
+                    }
+                    IrBinOp::Shr if ty.signed => {
+                        let dst = gen.binary(op, true, lhs, rhs);
+                        gen.binary_imm(IrBinOp::Shr, false, dst, top)
+                    }
+                    IrBinOp::Div | IrBinOp::Shr | IrBinOp::And | IrBinOp::Or | IrBinOp::Xor => {
+                        gen.binary(op, ty.signed, lhs, rhs)
+                    }
+                }
+            }
+            IrInst::Ret { ty, src } => {
+                gen.insts.push(IrInst::Ret { ty, src: map[src] });
+                continue;
+            }
+        };
+        let dst = inst.dst().unwrap();
+        map[dst] = if to_top[dst] {
+            gen.binary_imm(IrBinOp::Shl, false, value, top)
+        } else {
+            value
+        };
+    }
+
+    IrFunction {
+        name: func.name.clone(),
+        source: func.source.clone(),
+        span: func.span,
+        vars: func.vars.clone(),
+        temps: gen.temps,
+        insts: gen.insts,
+    }
+}
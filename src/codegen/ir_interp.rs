@@ -0,0 +1,87 @@
+//! An interpreter for `ir`, used to check lowering against `eval` without
+//! going through a backend.
+
+use std::fmt;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrType, IrUnOp};
+
+/// Why execution stopped before `ret`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrTrap {
+    DivisionByZero,
+}
+
+impl fmt::Display for IrTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IrTrap::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+fn mask(value: u64, width: u32) -> u64 {
+    if width >= 64 {
+        value
+    } else {
+        value & ((1u64 << width) - 1)
+    }
+}
+
+/// Sign-extends the low `width` bits of `value` to 64 bits.
+fn sign_extend(value: u64, width: u32) -> u64 {
+    let shift = 64 - width;
+    (((value << shift) as i64) >> shift) as u64
+}
+
+fn unary(op: IrUnOp, ty: IrType, a: u64) -> u64 {
+    let value = match op {
+        IrUnOp::Neg => a.wrapping_neg(),
+        IrUnOp::Not => !a,
+    };
+    mask(value, ty.width)
+}
+
+fn binary(op: IrBinOp, ty: IrType, a: u64, b: u64) -> Result<u64, IrTrap> {
+    // Past the width every bit is out, as at the width itself.
+    let count = b.min(u64::from(ty.width)) as u32;
+    let value = match op {
+        IrBinOp::Add => a.wrapping_add(b),
+        IrBinOp::Sub => a.wrapping_sub(b),
+        IrBinOp::Mul => a.wrapping_mul(b),
+        IrBinOp::Div if b == 0 => return Err(IrTrap::DivisionByZero),
+        IrBinOp::Div if ty.signed => {
+            (sign_extend(a, ty.width) as i64).wrapping_div(sign_extend(b, ty.width) as i64) as u64
+        }
+        IrBinOp::Div => a / b,
+        IrBinOp::Shl => a << count,
+        IrBinOp::Shr if ty.signed => ((sign_extend(a, ty.width) as i64) >> count) as u64,
+        IrBinOp::Shr => a >> count,
+        IrBinOp::And => a & b,
+        IrBinOp::Or => a | b,
+        IrBinOp::Xor => a ^ b,
+    };
+    Ok(mask(value, ty.width))
+}
+
+/// Runs `func` with its parameters set to `args` and returns the result
+/// extended to 64 bits the way `ret` specifies.
+pub fn run(func: &IrFunction, args: &[u32]) -> Result<u64, IrTrap> {
+    let mut vars = vec![0u64; func.vars.vars.len()];
+    for (var, &arg) in vars.iter_mut().zip(args) {
+        *var = arg as u64;
+    }
+    let mut temps = vec![0u64; func.temps];
+    for inst in &func.insts {
+        match *inst {
+            IrInst::Const { dst, ty, ref value } => temps[dst] = mask(value.bits() as u64, ty.width),
+            IrInst::Load { dst, ty, var } => temps[dst] = mask(vars[var], ty.width),
+            IrInst::Store { var, ty, src } => vars[var] = mask(temps[src], ty.width),
+            IrInst::Unary { dst, ty, op, src } => temps[dst] = unary(op, ty, temps[src]),
+            IrInst::Binary { dst, ty, op, lhs, rhs } => temps[dst] = binary(op, ty, temps[lhs], temps[rhs])?,
+            IrInst::Ret { ty, src } => {
+                let value = mask(temps[src], ty.width);
+                return Ok(if ty.signed { sign_extend(value, ty.width) } else { value });
+            }
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,196 @@
+//! An in-process JIT for evaluating one program on many inputs.
+//!
+//! `compile` lowers the program to IR and, on x86-64 Linux, encodes it into
+//! executable memory as a function taking the free variables' values. The
+//! code is the frame-slot scheme of `x86_64_enc::instruction`, so it is not
+//! clever, but it skips the tree walk and the variable lookups that
+//! dominate `eval`. Elsewhere, or if executable memory cannot be mapped,
+//! `JitFunction::call` falls back to `eval`.
+
+use codegen::{check_overflow, check_width, ir, CodegenError};
+use codegen::x86_64_enc::*;
+use config::{Config, Emit};
+use eval::{eval, EvalEnvSt, EvalError};
+use parse::ParseNode;
+use scan::Span;
+use word::Word;
+
+/// A compiled program. Call it with one value per parameter.
+pub struct JitFunction {
+    params: Vec<String>,
+    /// The whole program, reported for a zero divisor in native code.
+    span: Span,
+    native: Option<native::ExecMem>,
+    node: ParseNode,
+    width: u32,
+}
+
+impl JitFunction {
+    /// The free variables, in the order `call` takes their values.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Whether `call` runs machine code rather than `eval`.
+    pub fn is_native(&self) -> bool {
+        self.native.is_some()
+    }
+
+    /// Evaluates the program with the low `cp.width` bits of `inputs` bound
+    /// to `params()`, returning what `eval` would, zero-extended. A division
+    /// by zero in native code reports the span of the whole program, as the
+    /// machine code does not know which `/` failed.
+    pub fn call(&self, inputs: &[u32]) -> Result<u32, EvalError> {
+        assert_eq!(inputs.len(), self.params.len(), "one input per parameter");
+        match self.native {
+            Some(ref code) => {
+                let mut trapped = 0u8;
+                let value = code.call(inputs.as_ptr(), &mut trapped);
+                if trapped != 0 {
+                    Err(EvalError::DivisionByZero(self.span))
+                } else {
+                    Ok(value as u32)
+                }
+            }
+            None => {
+                let mut env = EvalEnvSt::new();
+                env.width = self.width;
+                for (param, &value) in self.params.iter().zip(inputs) {
+                    env.set(param, Word::new(u128::from(value), self.width));
+                }
+                eval(&mut env, &Some(&self.node)).map(|value| value.bits() as u32)
+            }
+        }
+    }
+}
+
+/// Compiles `node` for `cp.width` bits, at most 32 as for `Emit::Exe`,
+/// whose encoder it shares. The result is `eval`'s value zero-extended, so
+/// signedness does not apply; `cp.optimize` does, and `cp.overflow` must be
+/// `Overflow::Wrap`.
+pub fn compile(node: &ParseNode, cp: &Config) -> Result<JitFunction, CodegenError> {
+    check_width(node, cp, Emit::Exe)?;
+    check_overflow(node, cp)?;
+    let mut config = cp.clone();
+    config.unsigned_int = false;
+    let func = ir::legalize(&ir::lower(node, &config));
+
+    Ok(JitFunction {
+        params: func.vars.params.clone(),
+        span: func.span,
+        native: native::ExecMem::new(&encode(&func)),
+        node: node.clone(),
+        width: cp.width,
+    })
+}
+
+/// Encodes `func` as `u64 f(const u32 *inputs, u8 *trapped)` for the
+/// System V ABI. A zero divisor sets `*trapped` and returns 0.
+fn encode(func: &ir::IrFunction) -> Vec<u8> {
+    let vars = func.vars.vars.len();
+    let frame = (4 * (vars + func.temps) as i32 + 15) / 16 * 16;
+
+    let mut asm = Asm::new();
+    let div_by_zero = asm.label();
+    asm.push(RBP);
+    asm.mov(true, RBP, RSP);
+    asm.alu_imm(SUB, true, RSP, frame as u32);
+    for i in 0..func.vars.params.len() {
+        asm.load(false, RAX, RDI, 4 * i as i32);
+        asm.store(RBP, -4 * (i as i32 + 1), RAX);
+    }
+    for inst in &func.insts {
+        instruction(&mut asm, inst, vars, div_by_zero);
+    }
+    asm.leave();
+    asm.ret();
+
+    asm.bind(div_by_zero);
+    asm.store_byte_imm(RSI, 1);
+    asm.alu(XOR, false, RAX, RAX);
+    asm.leave();
+    asm.ret();
+    asm.finish()
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod native {
+    use std::mem;
+    use std::ptr;
+
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 0x02;
+    const MAP_ANONYMOUS: i32 = 0x20;
+    const PAGE_SIZE: usize = 4096;
+
+    extern "C" {
+        fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+        fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+        fn munmap(addr: *mut u8, len: usize) -> i32;
+    }
+
+    type Entry = extern "sysv64" fn(*const u32, *mut u8) -> u64;
+
+    /// Read-only, executable pages holding one function.
+    pub struct ExecMem {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    // The pages are never written after `new`.
+    unsafe impl Send for ExecMem {}
+    unsafe impl Sync for ExecMem {}
+
+    impl ExecMem {
+        /// Copies `code` into fresh pages and makes them executable, or
+        /// returns `None` if the system refuses.
+        pub fn new(code: &[u8]) -> Option<ExecMem> {
+            let len = code.len().div_ceil(PAGE_SIZE) * PAGE_SIZE;
+            unsafe {
+                let ptr = mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+                if ptr as isize == -1 {
+                    return None;
+                }
+                ptr::copy_nonoverlapping(code.as_ptr(), ptr, code.len());
+                if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                    munmap(ptr, len);
+                    return None;
+                }
+                Some(ExecMem { ptr, len })
+            }
+        }
+
+        pub fn call(&self, inputs: *const u32, trapped: *mut u8) -> u64 {
+            unsafe {
+                let entry: Entry = mem::transmute(self.ptr);
+                entry(inputs, trapped)
+            }
+        }
+    }
+
+    impl Drop for ExecMem {
+        fn drop(&mut self) {
+            unsafe {
+                munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+mod native {
+    /// Never constructed on this platform.
+    pub enum ExecMem {}
+
+    impl ExecMem {
+        pub fn new(_code: &[u8]) -> Option<ExecMem> {
+            None
+        }
+
+        pub fn call(&self, _inputs: *const u32, _trapped: *mut u8) -> u64 {
+            match *self {}
+        }
+    }
+}
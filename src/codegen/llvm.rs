@@ -0,0 +1,193 @@
+//! LLVM IR in textual (`.ll`) form.
+//!
+//! Each IR instruction becomes one LLVM instruction on its own `iN` type, so
+//! `add`, `sub` and `mul` wrap without `nsw`/`nuw` flags, and the type's
+//! signedness picks `lshr` or `ashr` and `udiv` or `sdiv`. A shift count of
+//! the width or more is poison in LLVM, so it is checked with `icmp` and a
+//! `select` picks the saturated result, and a zero divisor branches to
+//! `llvm.trap` rather than reaching undefined behaviour.
+//! Variables are plain SSA values: an assignment just rebinds the name.
+//! Parameters are named `%v.` and the variable, which cannot collide with a
+//! temporary or a label because NTLang names have no dots.
+//! LLVM has integers of any width, so this backend takes the IR unlegalized;
+//! parameters are passed as `i32` and truncated on entry, or as the result
+//! type when the width is above 32.
+
+use codegen::CodegenError;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrType, IrUnOp};
+
+struct LlvmGen {
+    lines: Vec<String>,
+    /// The LLVM value of each IR temporary: a constant or an SSA name.
+    temps: Vec<String>,
+    /// Current SSA value of each variable.
+    vars: Vec<String>,
+    next_temp: usize,
+    next_label: usize,
+    uses_div: bool,
+}
+
+/// The LLVM name of the parameter for variable `name`.
+fn param_name(name: &str) -> String {
+    format!("%v.{}", name)
+}
+
+fn int_type(ty: IrType) -> String {
+    format!("i{}", ty.width)
+}
+
+impl LlvmGen {
+    fn emit(&mut self, line: String) {
+        self.lines.push(format!("  {}", line));
+    }
+
+    fn temp(&mut self) -> String {
+        self.next_temp += 1;
+        format!("%t{}", self.next_temp)
+    }
+
+    /// Emits `op iN a, b` and returns the result.
+    fn binary(&mut self, op: &str, ty: IrType, a: &str, b: &str) -> String {
+        let t = self.temp();
+        self.emit(format!("{} = {} {} {}, {}", t, op, int_type(ty), a, b));
+        t
+    }
+
+    /// Emits a shift, which LLVM leaves poison for a count of the width or
+    /// more: `shl` and `lshr` select 0 then, and `ashr` shifts by one less.
+    fn shift(&mut self, op: &str, ty: IrType, a: &str, b: &str) -> String {
+        let width = u64::from(ty.width);
+        if b.starts_with(|c: char| c.is_ascii_digit()) {
+            // A constant too large for `u64` is far beyond any width.
+            let count = b.parse::<u64>().unwrap_or(u64::MAX);
+            return match op {
+                _ if count < width => self.binary(op, ty, a, b),
+                "ashr" => self.binary(op, ty, a, &(width - 1).to_string()),
+                _ => "0".to_string(),
+            };
+        }
+        let in_range = self.temp();
+        self.emit(format!("{} = icmp ult {} {}, {}", in_range, int_type(ty), b, width));
+        let ty_name = int_type(ty);
+        if op == "ashr" {
+            let count = self.temp();
+            self.emit(format!("{} = select i1 {}, {} {}, {} {}", count, in_range, ty_name, b, ty_name, width - 1));
+            self.binary(op, ty, a, &count)
+        } else {
+            let shifted = self.binary(op, ty, a, b);
+            let t = self.temp();
+            self.emit(format!("{} = select i1 {}, {} {}, {} 0", t, in_range, ty_name, shifted, ty_name));
+            t
+        }
+    }
+
+    fn gen(&mut self, inst: &IrInst) {
+        match *inst {
+            IrInst::Const { dst, ref value, .. } => self.temps[dst] = value.to_string(),
+            IrInst::Load { dst, var, .. } => self.temps[dst] = self.vars[var].clone(),
+            IrInst::Store { var, src, .. } => self.vars[var] = self.temps[src].clone(),
+            IrInst::Unary { dst, ty, op, src } => {
+                let a = self.temps[src].clone();
+                self.temps[dst] = match op {
+                    IrUnOp::Neg => self.binary("sub", ty, "0", &a),
+                    IrUnOp::Not => self.binary("xor", ty, &a, "-1"),
+                };
+            }
+            IrInst::Binary { dst, ty, op, lhs, rhs } => {
+                let a = self.temps[lhs].clone();
+                let b = self.temps[rhs].clone();
+                self.temps[dst] = match op {
+                    IrBinOp::Add => self.binary("add", ty, &a, &b),
+                    IrBinOp::Sub => self.binary("sub", ty, &a, &b),
+                    IrBinOp::Mul => self.binary("mul", ty, &a, &b),
+                    IrBinOp::Div => {
+                        self.uses_div = true;
+                        self.next_label += 1;
+                        let ok = format!("div{}", self.next_label);
+                        let is_zero = self.temp();
+                        self.emit(format!("{} = icmp eq {} {}, 0", is_zero, int_type(ty), b));
+                        self.emit(format!("br i1 {}, label %div_by_zero, label %{}", is_zero, ok));
+                        self.lines.push(format!("{}:", ok));
+                        self.binary(if ty.signed { "sdiv" } else { "udiv" }, ty, &a, &b)
+                    }
+                    IrBinOp::Shl | IrBinOp::Shr => {
+                        let op = match op {
+                            IrBinOp::Shl => "shl",
+                            _ if ty.signed => "ashr",
+                            _ => "lshr",
+                        };
+                        self.shift(op, ty, &a, &b)
+                    }
+                    IrBinOp::And => self.binary("and", ty, &a, &b),
+                    IrBinOp::Or => self.binary("or", ty, &a, &b),
+                    IrBinOp::Xor => self.binary("xor", ty, &a, &b),
+                };
+            }
+            IrInst::Ret { ty, src } => {
+                let value = self.temps[src].clone();
+                self.emit(format!("ret {} {}", int_type(ty), value));
+            }
+        }
+    }
+}
+
+/// Compiles `func` to a module defining `i{width} @ntlang_expr(i32 ...)`,
+/// one parameter per free variable, `i32` or `i{width}` if that is wider.
+/// The return value is marked `signext` or `zeroext` as the IR's `ret`
+/// specifies, so callers see it extended the same way `eval_print`
+/// interprets it.
+pub fn compile(func: &IrFunction) -> Result<String, CodegenError> {
+    let vars = &func.vars;
+    let mut gen = LlvmGen {
+        lines: Vec::new(),
+        temps: vec![String::new(); func.temps],
+        vars: vec!["0".to_string(); vars.vars.len()],
+        next_temp: 0,
+        next_label: 0,
+        uses_div: false,
+    };
+    let ret = match func.insts.last() {
+        Some(&IrInst::Ret { ty, .. }) => ty,
+        _ => unreachable!("IR functions end in ret"),
+    };
+    for (i, param) in vars.params.iter().enumerate() {
+        gen.vars[i] = param_name(param);
+        if ret.width < 32 {
+            let t = gen.temp();
+            gen.emit(format!("{} = trunc i32 {} to {}", t, param_name(param), int_type(ret)));
+            gen.vars[i] = t;
+        }
+    }
+
+    for inst in &func.insts {
+        gen.gen(inst);
+    }
+    if gen.uses_div {
+        gen.lines.push("div_by_zero:".to_string());
+        gen.emit("call void @llvm.trap()".to_string());
+        gen.emit("unreachable".to_string());
+    }
+
+    let ext = if ret.signed { "signext" } else { "zeroext" };
+    let param_type = if ret.width > 32 { int_type(ret) } else { "i32".to_string() };
+    let params: Vec<String> = vars.params.iter().map(|name| format!("{} {}", param_type, param_name(name))).collect();
+
+    let mut out = format!("; ntlang: {}\n", func.source);
+    out.push_str("; ModuleID = 'ntlang'\nsource_filename = \"ntlang\"\n\n");
+    out.push_str(&format!(
+        "define {} {} @{}({}) {{\nentry:\n",
+        ext,
+        int_type(ret),
+        func.name,
+        params.join(", ")
+    ));
+    for line in &gen.lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str("}\n");
+    if gen.uses_div {
+        out.push_str("\ndeclare void @llvm.trap() cold noreturn nounwind\n");
+    }
+    Ok(out)
+}
@@ -0,0 +1,187 @@
+//! Code generators that turn a `ParseNode` into source for another machine
+//! or language. Every backend starts from the same lowering to `ir`.
+//!
+//! Every generated function follows the same contract as `eval`: operations
+//! wrap at `Config::width` bits, `/` is unsigned and traps on a zero divisor,
+//! and the returned value is sign-extended when printing signed and
+//! zero-extended otherwise. Variables that are read before they are assigned
+//! become the function's parameters, in order of first use; only their low
+//! `Config::width` bits are used. The IR and LLVM take any width; the other
+//! targets compute in registers, so they stop at `max_width`.
+
+pub mod aarch64;
+pub mod c;
+pub mod elf;
+pub mod ir;
+pub mod jit;
+pub mod llvm;
+pub mod regalloc;
+pub mod riscv;
+pub mod wasm;
+pub mod x86_64;
+pub mod x86_64_enc;
+pub mod ir_interp;
+pub mod riscv_emu;
+pub mod wasm_interp;
+pub mod verify;
+
+use std::error::Error;
+use std::fmt;
+use config::{Config, Emit, Overflow};
+use word::MAX_WIDTH;
+use diag::Diagnostic;
+use scan::Span;
+use parse::{ParseNode, ParseNodeType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodegenError {
+    /// More free variables than the target passes in registers. The span
+    /// covers the whole program.
+    TooManyParams { count: usize, max: usize, span: Span },
+    /// A construct the selected target cannot express.
+    Unsupported { what: String, span: Span },
+}
+
+impl CodegenError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match *self {
+            CodegenError::TooManyParams { span, .. } => Diagnostic::new("C001", &self.to_string(), span)
+                .with_hint("assign some of the variables in the expression itself"),
+            CodegenError::Unsupported { span, .. } => Diagnostic::new("C002", &self.to_string(), span),
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodegenError::TooManyParams { count, max, .. } => write!(
+                f,
+                "expression has {} free variables but the target passes at most {} in registers",
+                count, max
+            ),
+            CodegenError::Unsupported { ref what, .. } => write!(f, "{} not supported by this target", what),
+        }
+    }
+}
+
+impl Error for CodegenError {}
+
+/// The widest `Config::width` `target` can generate code for: any for the
+/// IR and LLVM, 64 bits for the targets with 64-bit registers, and 32 for
+/// the rest. `Emit::Exe` shares the 32-bit encoder of the JIT.
+pub fn max_width(target: Emit) -> u32 {
+    match target {
+        Emit::Ir | Emit::Llvm => MAX_WIDTH,
+        Emit::Riscv64 | Emit::Aarch64 | Emit::X86_64 => 64,
+        _ => 32,
+    }
+}
+
+/// Rejects a `cp.width` above `max_width(target)`, which `ir::legalize`
+/// cannot fit in a register.
+pub fn check_width(node: &ParseNode, cp: &Config, target: Emit) -> Result<(), CodegenError> {
+    if cp.width > max_width(target) {
+        return Err(CodegenError::Unsupported {
+            what: format!("{}-bit arithmetic", cp.width),
+            span: node.span,
+        });
+    }
+    Ok(())
+}
+
+/// Rejects a `cp.overflow` other than `Overflow::Wrap`: generated code
+/// always wraps.
+pub fn check_overflow(node: &ParseNode, cp: &Config) -> Result<(), CodegenError> {
+    let what = match cp.overflow {
+        Overflow::Wrap => return Ok(()),
+        Overflow::Trap => "trapping",
+        Overflow::Saturate => "saturating",
+    };
+    Err(CodegenError::Unsupported {
+        what: format!("{} on overflow", what),
+        span: node.span,
+    })
+}
+
+/// Variables used by a program, in the order `eval` first touches them.
+#[derive(Clone)]
+pub struct VarTableSt {
+    /// Variables read before any assignment; these become parameters.
+    pub params: Vec<String>,
+    /// Every variable, parameters first.
+    pub vars: Vec<String>,
+}
+
+impl VarTableSt {
+    pub fn new(node: &ParseNode) -> Self {
+        let mut table = VarTableSt {
+            params: Vec::new(),
+            vars: Vec::new(),
+        };
+        let mut assigned = Vec::new();
+        table.collect(node, &mut assigned);
+        let mut vars = table.params.clone();
+        for name in assigned {
+            if !vars.contains(&name) {
+                vars.push(name);
+            }
+        }
+        table.vars = vars;
+        table
+    }
+
+    fn collect(&mut self, node: &ParseNode, assigned: &mut Vec<String>) {
+        if let Some(ref left) = node.left {
+            self.collect(left, assigned);
+        }
+        if let Some(ref right) = node.right {
+            self.collect(right, assigned);
+        }
+        for stmt in &node.stmts {
+            self.collect(stmt, assigned);
+        }
+        match node.type_ {
+            ParseNodeType::Ident if !assigned.contains(&node.name) && !self.params.contains(&node.name) => {
+                self.params.push(node.name.clone());
+            }
+            ParseNodeType::Assign if !assigned.contains(&node.name) => {
+                assigned.push(node.name.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Index of `name` in `vars`.
+    pub fn slot(&self, name: &str) -> usize {
+        self.vars.iter().position(|var| var == name).unwrap()
+    }
+}
+
+/// Generates code for `node` with the backend selected by `cp.emit`. This
+/// is text for every target except `Emit::Wasm` and `Emit::Exe`. All but
+/// `Emit::Ir` and `Emit::Llvm` get the IR legalized to their registers, and
+/// reject programs wider than `max_width`. No target honours `cp.overflow`.
+pub fn emit(node: &ParseNode, cp: &Config) -> Result<Vec<u8>, CodegenError> {
+    if let Some(target) = cp.emit {
+        check_overflow(node, cp)?;
+        check_width(node, cp, target)?;
+    }
+    let typed = ir::lower(node, cp);
+    let func = ir::legalize(&typed);
+    let code = match cp.emit {
+        Some(Emit::Ir) => typed.to_string(),
+        Some(Emit::Llvm) => llvm::compile(&typed)?,
+        Some(Emit::Riscv32) => riscv::compile(&func, 32)?.to_string(),
+        Some(Emit::Riscv64) => riscv::compile(&func, 64)?.to_string(),
+        Some(Emit::Aarch64) => aarch64::compile(&func)?,
+        Some(Emit::X86_64) => x86_64::compile(&func)?,
+        Some(Emit::C) => c::compile(&func, false)?,
+        Some(Emit::CDefine) => c::compile(&func, true)?,
+        Some(Emit::Wat) => wasm::compile(&func).to_string(),
+        Some(Emit::Wasm) => return Ok(wasm::compile(&func).encode()),
+        Some(Emit::Exe) => return elf::compile(&func, cp),
+        None => String::new(),
+    };
+    Ok(code.into_bytes())
+}
@@ -0,0 +1,174 @@
+//! Register allocation for the assembly backends.
+//!
+//! `sethi_ullman` reorders the IR so that of the two operands of each binary
+//! instruction, the one needing more registers is computed first; a tree
+//! then never holds more temporaries live at once than its Sethi-Ullman
+//! number. `linear_scan` walks the reordered instructions once and gives
+//! every temporary a register, or a spill slot when they are all taken.
+//!
+//! The reordered function no longer evaluates operands left to right, so it
+//! is only for backends that name operands by location; the C and
+//! WebAssembly backends, which rebuild expressions from the order
+//! temporaries are defined in, keep using `ir::lower`'s output.
+
+use std::fmt;
+use codegen::ir::{IrFunction, IrInst};
+
+/// Where a backend keeps a temporary: one of its registers, by index into
+/// its own register list, or a spill slot in the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loc {
+    Reg(usize),
+    Slot(usize),
+}
+
+/// The result of `linear_scan`.
+pub struct RegAllocSt {
+    /// The location of each temporary.
+    pub locs: Vec<Loc>,
+    /// Registers used, counting from the first.
+    pub registers: usize,
+    /// Registers the backend offered.
+    pub available: usize,
+    pub spill_slots: usize,
+}
+
+/// What a backend reports about its generated code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegStatsSt {
+    pub registers: usize,
+    pub available: usize,
+    pub spill_slots: usize,
+    /// Machine instructions, not counting labels or directives.
+    pub instructions: usize,
+}
+
+impl RegAllocSt {
+    pub fn stats(&self, instructions: usize) -> RegStatsSt {
+        RegStatsSt {
+            registers: self.registers,
+            available: self.available,
+            spill_slots: self.spill_slots,
+            instructions,
+        }
+    }
+}
+
+impl fmt::Display for RegStatsSt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "registers: {} of {}, spill slots: {}, instructions: {}",
+            self.registers, self.available, self.spill_slots, self.instructions
+        )
+    }
+}
+
+/// Returns `func` with the operands of each binary instruction computed
+/// neediest first. Operands are only swapped when both are computed by
+/// adjacent runs of instructions with no store among them, so the new order
+/// cannot change what any load sees; a division that traps still traps.
+pub fn sethi_ullman(func: &IrFunction) -> IrFunction {
+    // For each temporary: the index of the first instruction computing it,
+    // of the instruction defining it, the registers it needs, and whether
+    // its instructions may be moved as a block.
+    let mut start = vec![0; func.temps];
+    let mut end = vec![0; func.temps];
+    let mut need = vec![1; func.temps];
+    let mut movable = vec![true; func.temps];
+
+    let mut insts: Vec<IrInst> = Vec::with_capacity(func.insts.len());
+    for inst in &func.insts {
+        let at = insts.len();
+        match *inst {
+            IrInst::Const { dst, .. } | IrInst::Load { dst, .. } => start[dst] = at,
+            IrInst::Unary { dst, src, .. } => {
+                start[dst] = start[src];
+                need[dst] = need[src];
+                movable[dst] = movable[src] && end[src] + 1 == at;
+            }
+            IrInst::Binary { dst, lhs, rhs, .. } => {
+                let adjacent = end[lhs] + 1 == start[rhs] && end[rhs] + 1 == at;
+                movable[dst] = adjacent && movable[lhs] && movable[rhs];
+                if movable[dst] && need[rhs] > need[lhs] {
+                    insts[start[lhs]..at].rotate_left(start[rhs] - start[lhs]);
+                }
+                start[dst] = start[lhs].min(start[rhs]);
+                need[dst] = if need[lhs] == need[rhs] {
+                    need[lhs] + 1
+                } else {
+                    need[lhs].max(need[rhs])
+                };
+            }
+            IrInst::Store { .. } | IrInst::Ret { .. } => {}
+        }
+        if let Some(dst) = inst.dst() {
+            end[dst] = at;
+        }
+        insts.push(inst.clone());
+    }
+
+    IrFunction {
+        name: func.name.clone(),
+        source: func.source.clone(),
+        span: func.span,
+        vars: func.vars.clone(),
+        temps: func.temps,
+        insts,
+    }
+}
+
+/// Assigns each temporary the lowest free of `regs` registers, or a spill
+/// slot when they are all taken. Operands are released before the result is
+/// placed, so an instruction's result may share a register with one of its
+/// operands.
+pub fn linear_scan(func: &IrFunction, regs: usize) -> RegAllocSt {
+    let mut last_use = vec![None; func.temps];
+    for (i, inst) in func.insts.iter().enumerate() {
+        for src in inst.srcs() {
+            last_use[src] = Some(i);
+        }
+    }
+
+    let mut locs = vec![Loc::Reg(0); func.temps];
+    let mut reg_busy = vec![false; regs];
+    let mut slot_busy: Vec<bool> = Vec::new();
+    let mut registers = 0;
+    for (i, inst) in func.insts.iter().enumerate() {
+        for src in inst.srcs() {
+            if last_use[src] == Some(i) {
+                match locs[src] {
+                    Loc::Reg(reg) => reg_busy[reg] = false,
+                    Loc::Slot(slot) => slot_busy[slot] = false,
+                }
+            }
+        }
+        if let Some(dst) = inst.dst() {
+            locs[dst] = match reg_busy.iter().position(|&busy| !busy) {
+                Some(reg) => {
+                    registers = registers.max(reg + 1);
+                    Loc::Reg(reg)
+                }
+                None => match slot_busy.iter().position(|&busy| !busy) {
+                    Some(slot) => Loc::Slot(slot),
+                    None => {
+                        slot_busy.push(false);
+                        Loc::Slot(slot_busy.len() - 1)
+                    }
+                },
+            };
+            // A result nobody reads is dead as soon as it is written.
+            let busy = last_use[dst].is_some();
+            match locs[dst] {
+                Loc::Reg(reg) => reg_busy[reg] = busy,
+                Loc::Slot(slot) => slot_busy[slot] = busy,
+            }
+        }
+    }
+    RegAllocSt {
+        locs,
+        registers,
+        available: regs,
+        spill_slots: slot_busy.len(),
+    }
+}
@@ -0,0 +1,462 @@
+//! RV32IM and RV64IM assembly.
+//!
+//! IR temporaries live in `TEMPS` or, once those run out, in frame slots that
+//! are reloaded through `SCRATCH`. Variables live in the frame too, all
+//! addressed from `s0`; offsets beyond a 12-bit immediate are built with `li`
+//! and `add`. On RV64 the 32-bit `*w` instructions keep every value
+//! sign-extended, which matches `eval`'s 32-bit arithmetic; a function
+//! legalized to 64 bits uses the full-width instructions instead, which only
+//! RV64 has.
+
+use std::fmt;
+use codegen::CodegenError;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrType, IrUnOp, Temp};
+use codegen::regalloc::{self, Loc, RegStatsSt};
+
+/// An integer register, by number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reg(pub u8);
+
+pub const ZERO: Reg = Reg(0);
+pub const SP: Reg = Reg(2);
+pub const S0: Reg = Reg(8);
+pub const A0: Reg = Reg(10);
+
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ABI_NAMES[self.0 as usize])
+    }
+}
+
+/// Registers for IR temporaries, in allocation order.
+const TEMPS: [Reg; 5] = [Reg(5), Reg(6), Reg(7), Reg(28), Reg(29)];
+/// Hold spilled operands while an instruction uses them.
+const SCRATCH: [Reg; 2] = [Reg(30), Reg(31)];
+/// Parameters arrive in `a0`-`a7`.
+pub const MAX_PARAMS: usize = 8;
+/// Holds a shift count's range check; the prologue has stored every
+/// parameter by then.
+const CHECK: Reg = Reg(11);
+
+const DIV_BY_ZERO: &str = ".Ldiv_by_zero";
+
+/// Whether `imm` fits the signed 12-bit immediate of the I- and S-type
+/// instructions.
+pub fn fits_imm12(imm: i64) -> bool {
+    (-2048..2048).contains(&imm)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RvOp {
+    Add,
+    Sub,
+    Mul,
+    Divu,
+    Sll,
+    Srl,
+    Sra,
+    And,
+    Or,
+    Xor,
+    Addw,
+    Subw,
+    Mulw,
+    Divuw,
+    Sllw,
+    Srlw,
+    Sraw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RvImmOp {
+    Addi,
+    Sltiu,
+    Andi,
+    Xori,
+    Slli,
+    Srli,
+    Srai,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RvInst {
+    Label(String),
+    Li(Reg, i64),
+    Mv(Reg, Reg),
+    /// `op rd, rs1, rs2`
+    Op(RvOp, Reg, Reg, Reg),
+    /// `op rd, rs1, imm`
+    OpImm(RvImmOp, Reg, Reg, i64),
+    /// `lw rd, offset(base)`
+    Lw(Reg, i64, Reg),
+    Ld(Reg, i64, Reg),
+    /// `sw rs, offset(base)`
+    Sw(Reg, i64, Reg),
+    Sd(Reg, i64, Reg),
+    Beqz(Reg, String),
+    Ebreak,
+    Ret,
+}
+
+impl fmt::Display for RvOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for RvImmOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for RvInst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RvInst::Label(ref label) => write!(f, "{}:", label),
+            RvInst::Li(rd, imm) => write!(f, "    li {}, {}", rd, imm),
+            RvInst::Mv(rd, rs) => write!(f, "    mv {}, {}", rd, rs),
+            RvInst::Op(RvOp::Sub, rd, ZERO, rs) => write!(f, "    neg {}, {}", rd, rs),
+            RvInst::Op(RvOp::Subw, rd, ZERO, rs) => write!(f, "    negw {}, {}", rd, rs),
+            RvInst::Op(op, rd, rs1, rs2) => write!(f, "    {} {}, {}, {}", op, rd, rs1, rs2),
+            RvInst::OpImm(RvImmOp::Xori, rd, rs, -1) => write!(f, "    not {}, {}", rd, rs),
+            RvInst::OpImm(op, rd, rs, imm) => write!(f, "    {} {}, {}, {}", op, rd, rs, imm),
+            RvInst::Lw(rd, offset, base) => write!(f, "    lw {}, {}({})", rd, offset, base),
+            RvInst::Ld(rd, offset, base) => write!(f, "    ld {}, {}({})", rd, offset, base),
+            RvInst::Sw(rs, offset, base) => write!(f, "    sw {}, {}({})", rs, offset, base),
+            RvInst::Sd(rs, offset, base) => write!(f, "    sd {}, {}({})", rs, offset, base),
+            RvInst::Beqz(rs, ref label) => write!(f, "    beqz {}, {}", rs, label),
+            RvInst::Ebreak => write!(f, "    ebreak"),
+            RvInst::Ret => write!(f, "    ret"),
+        }
+    }
+}
+
+/// A generated function. Parameters arrive in `a0`, `a1`, ... in the order
+/// of `params`; the result is returned in `a0`.
+pub struct RvFunction {
+    pub name: String,
+    pub xlen: u32,
+    pub source: String,
+    pub params: Vec<String>,
+    pub insts: Vec<RvInst>,
+    pub stats: RegStatsSt,
+}
+
+impl fmt::Display for RvFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# ntlang: {}", self.source)?;
+        writeln!(f, "# {}", self.stats)?;
+        for (i, param) in self.params.iter().enumerate() {
+            writeln!(f, "# {} = {}", Reg(A0.0 + i as u8), param)?;
+        }
+        writeln!(f, "    .text")?;
+        writeln!(f, "    .globl {}", self.name)?;
+        writeln!(f, "    .type {}, @function", self.name)?;
+        writeln!(f, "{}:", self.name)?;
+        for inst in &self.insts {
+            writeln!(f, "{}", inst)?;
+        }
+        writeln!(f, "    .size {}, .-{}", self.name, self.name)
+    }
+}
+
+struct RvGen<'a> {
+    xlen: u32,
+    /// Whether the function computes in 64-bit registers.
+    wide: bool,
+    locs: &'a [Loc],
+    /// Frame slot of the first spilled temporary.
+    spill_base: usize,
+    /// The value of each temporary assigned a constant.
+    constants: Vec<Option<u32>>,
+    insts: Vec<RvInst>,
+    uses_div: bool,
+}
+
+impl<'a> RvGen<'a> {
+    fn emit(&mut self, inst: RvInst) {
+        self.insts.push(inst);
+    }
+
+    fn slot_size(&self) -> i64 {
+        self.xlen as i64 / 8
+    }
+
+    /// Offset from `s0` of frame slot `slot`; variables come first, then
+    /// spilled temporaries. The word just below `s0` holds the caller's `s0`.
+    fn slot_offset(&self, slot: usize) -> i64 {
+        -self.slot_size() * (slot as i64 + 2)
+    }
+
+    /// `rd = rs + imm`, through `scratch` if `imm` does not fit `addi`.
+    fn add_imm(&mut self, rd: Reg, rs: Reg, imm: i64, scratch: Reg) {
+        if fits_imm12(imm) {
+            self.emit(RvInst::OpImm(RvImmOp::Addi, rd, rs, imm));
+        } else {
+            self.emit(RvInst::Li(scratch, imm));
+            self.emit(RvInst::Op(RvOp::Add, rd, rs, scratch));
+        }
+    }
+
+    /// Loads the word at `offset(base)`; a far offset is added up in `rd`.
+    fn load(&mut self, rd: Reg, offset: i64, base: Reg) {
+        let (offset, base) = if fits_imm12(offset) {
+            (offset, base)
+        } else {
+            self.add_imm(rd, base, offset, rd);
+            (0, rd)
+        };
+        let inst = if self.xlen == 64 { RvInst::Ld(rd, offset, base) } else { RvInst::Lw(rd, offset, base) };
+        self.emit(inst);
+    }
+
+    /// Stores `rs` at `offset(base)`; a far offset is added up in
+    /// `SCRATCH[1]`, which is never the register stored.
+    fn store(&mut self, rs: Reg, offset: i64, base: Reg) {
+        let (offset, base) = if fits_imm12(offset) {
+            (offset, base)
+        } else {
+            assert!(rs != SCRATCH[1]);
+            self.add_imm(SCRATCH[1], base, offset, SCRATCH[1]);
+            (0, SCRATCH[1])
+        };
+        let inst = if self.xlen == 64 { RvInst::Sd(rs, offset, base) } else { RvInst::Sw(rs, offset, base) };
+        self.emit(inst);
+    }
+
+    /// Returns the register holding `temp`, reloading it into `scratch` if
+    /// it was spilled.
+    fn read(&mut self, temp: Temp, scratch: Reg) -> Reg {
+        match self.locs[temp] {
+            Loc::Reg(i) => TEMPS[i],
+            Loc::Slot(slot) => {
+                let offset = self.slot_offset(self.spill_base + slot);
+                self.load(scratch, offset, S0);
+                scratch
+            }
+        }
+    }
+
+    /// Returns the register to compute `temp` into; `write` then stores it
+    /// if `temp` is spilled.
+    fn dest(&self, temp: Temp) -> Reg {
+        match self.locs[temp] {
+            Loc::Reg(i) => TEMPS[i],
+            Loc::Slot(_) => SCRATCH[0],
+        }
+    }
+
+    fn write(&mut self, temp: Temp, rd: Reg) {
+        if let Loc::Slot(slot) = self.locs[temp] {
+            let offset = self.slot_offset(self.spill_base + slot);
+            self.store(rd, offset, S0);
+        }
+    }
+
+    /// Picks the 32-bit form of `op` on RV64, unless the function is wide.
+    fn op32(&self, op: RvOp) -> RvOp {
+        if self.xlen == 32 || self.wide {
+            return op;
+        }
+        match op {
+            RvOp::Add => RvOp::Addw,
+            RvOp::Sub => RvOp::Subw,
+            RvOp::Mul => RvOp::Mulw,
+            RvOp::Divu => RvOp::Divuw,
+            RvOp::Sll => RvOp::Sllw,
+            RvOp::Srl => RvOp::Srlw,
+            RvOp::Sra => RvOp::Sraw,
+            _ => op,
+        }
+    }
+
+    fn gen(&mut self, inst: &IrInst) {
+        match *inst {
+            IrInst::Const { dst, ref value, .. } => {
+                let rd = self.dest(dst);
+                let imm = if self.wide { value.bits() as u64 as i64 } else { value.bits() as u32 as i32 as i64 };
+                self.emit(RvInst::Li(rd, imm));
+                self.write(dst, rd);
+            }
+            IrInst::Load { dst, var, .. } => {
+                let rd = self.dest(dst);
+                let offset = self.slot_offset(var);
+                self.load(rd, offset, S0);
+                self.write(dst, rd);
+            }
+            IrInst::Store { var, src, .. } => {
+                let rs = self.read(src, SCRATCH[0]);
+                let offset = self.slot_offset(var);
+                self.store(rs, offset, S0);
+            }
+            IrInst::Unary { dst, op, src, .. } => {
+                let rs = self.read(src, SCRATCH[0]);
+                let rd = self.dest(dst);
+                match op {
+                    IrUnOp::Neg => {
+                        let op = self.op32(RvOp::Sub);
+                        self.emit(RvInst::Op(op, rd, ZERO, rs));
+                    }
+                    IrUnOp::Not => self.emit(RvInst::OpImm(RvImmOp::Xori, rd, rs, -1)),
+                }
+                self.write(dst, rd);
+            }
+            IrInst::Binary { dst, ty, op, lhs, rhs } => {
+                let rs1 = self.read(lhs, SCRATCH[0]);
+                let rs2 = self.read(rhs, SCRATCH[1]);
+                let rd = self.dest(dst);
+                let op = match op {
+                    IrBinOp::Add => RvOp::Add,
+                    IrBinOp::Sub => RvOp::Sub,
+                    IrBinOp::Mul => RvOp::Mul,
+                    IrBinOp::Div => {
+                        self.uses_div = true;
+                        self.emit(RvInst::Beqz(rs2, DIV_BY_ZERO.to_string()));
+                        RvOp::Divu
+                    }
+                    IrBinOp::Shl | IrBinOp::Shr if self.constants[rhs].is_none_or(|n| n >= ty.width) => {
+                        self.shift(op, ty, rd, rs1, rs2);
+                        self.write(dst, rd);
+                        return;
+                    }
+                    IrBinOp::Shl => RvOp::Sll,
+                    IrBinOp::Shr if ty.signed => RvOp::Sra,
+                    IrBinOp::Shr => RvOp::Srl,
+                    IrBinOp::And => RvOp::And,
+                    IrBinOp::Or => RvOp::Or,
+                    IrBinOp::Xor => RvOp::Xor,
+                };
+                let op = self.op32(op);
+                self.emit(RvInst::Op(op, rd, rs1, rs2));
+                self.write(dst, rd);
+            }
+            IrInst::Ret { ty, src } => {
+                let rs = self.read(src, SCRATCH[0]);
+                self.normalize(A0, rs, ty);
+            }
+        }
+    }
+
+    /// `rd = rs1 op rs2` for a shift at `ty`, which gives 0, or copies of
+    /// the sign bit when signed, for a count of the width or more. The
+    /// hardware takes the count mod the width, so `CHECK` masks the result
+    /// or saturates the count.
+    fn shift(&mut self, op: IrBinOp, ty: IrType, rd: Reg, rs1: Reg, rs2: Reg) {
+        self.emit(RvInst::OpImm(RvImmOp::Sltiu, CHECK, rs2, i64::from(ty.width)));
+        if ty.signed {
+            self.emit(RvInst::OpImm(RvImmOp::Addi, CHECK, CHECK, -1));
+            self.emit(RvInst::Op(RvOp::Or, CHECK, CHECK, rs2));
+            let op = self.op32(RvOp::Sra);
+            self.emit(RvInst::Op(op, rd, rs1, CHECK));
+        } else {
+            self.emit(RvInst::Op(RvOp::Sub, CHECK, ZERO, CHECK));
+            let op = self.op32(if op == IrBinOp::Shl { RvOp::Sll } else { RvOp::Srl });
+            self.emit(RvInst::Op(op, rd, rs1, rs2));
+            self.emit(RvInst::Op(RvOp::And, rd, rd, CHECK));
+        }
+    }
+
+    /// Reduces `rs` to `ty.width` bits in `rd`, sign- or zero-extending to
+    /// the register width.
+    fn normalize(&mut self, rd: Reg, rs: Reg, ty: IrType) {
+        let shift = (self.xlen - ty.width) as i64;
+        if ty.signed {
+            // 32-bit results are already sign-extended on RV64.
+            if shift != 0 && ty.width != 32 {
+                self.emit(RvInst::OpImm(RvImmOp::Slli, rd, rs, shift));
+                self.emit(RvInst::OpImm(RvImmOp::Srai, rd, rd, shift));
+            } else {
+                self.emit(RvInst::Mv(rd, rs));
+            }
+        } else if shift == 0 {
+            self.emit(RvInst::Mv(rd, rs));
+        } else if ty.width <= 11 {
+            self.emit(RvInst::OpImm(RvImmOp::Andi, rd, rs, (1 << ty.width) - 1));
+        } else {
+            self.emit(RvInst::OpImm(RvImmOp::Slli, rd, rs, shift));
+            self.emit(RvInst::OpImm(RvImmOp::Srli, rd, rd, shift));
+        }
+    }
+}
+
+/// Compiles `func` to an RV32IM (`xlen` 32) or RV64IM (`xlen` 64) function.
+/// Only RV64 takes a function wider than 32 bits.
+pub fn compile(func: &IrFunction, xlen: u32) -> Result<RvFunction, CodegenError> {
+    let vars = &func.vars;
+    if func.width() > xlen {
+        return Err(CodegenError::Unsupported {
+            what: format!("{}-bit arithmetic", func.width()),
+            span: func.span,
+        });
+    }
+    if vars.params.len() > MAX_PARAMS {
+        return Err(CodegenError::TooManyParams {
+            count: vars.params.len(),
+            max: MAX_PARAMS,
+            span: func.span,
+        });
+    }
+
+    let func = &regalloc::sethi_ullman(func);
+    let alloc = regalloc::linear_scan(func, TEMPS.len());
+    let mut gen = RvGen {
+        xlen,
+        wide: func.width() > 32,
+        locs: &alloc.locs,
+        spill_base: vars.vars.len(),
+        constants: func.constants(),
+        insts: Vec::new(),
+        uses_div: false,
+    };
+
+    // The frame holds the caller's s0, one slot per variable and one per
+    // spilled temporary.
+    let slots = vars.vars.len() + alloc.spill_slots;
+    let frame = if slots == 0 {
+        0
+    } else {
+        let size = gen.slot_size() * (slots as i64 + 1);
+        (size + 15) / 16 * 16
+    };
+    if frame > 0 {
+        gen.add_imm(SP, SP, -frame, SCRATCH[0]);
+        let saved = frame - gen.slot_size();
+        gen.store(S0, saved, SP);
+        gen.add_imm(S0, SP, frame, SCRATCH[0]);
+        for i in 0..vars.params.len() {
+            let offset = gen.slot_offset(i);
+            gen.store(Reg(A0.0 + i as u8), offset, S0);
+        }
+    }
+
+    for inst in &func.insts {
+        gen.gen(inst);
+    }
+
+    if frame > 0 {
+        let saved = frame - gen.slot_size();
+        gen.load(S0, saved, SP);
+        gen.add_imm(SP, SP, frame, SCRATCH[0]);
+    }
+    gen.emit(RvInst::Ret);
+    if gen.uses_div {
+        gen.emit(RvInst::Label(DIV_BY_ZERO.to_string()));
+        gen.emit(RvInst::Ebreak);
+    }
+
+    let instructions = gen.insts.iter().filter(|inst| !matches!(inst, RvInst::Label(_))).count();
+    Ok(RvFunction {
+        name: func.name.clone(),
+        xlen,
+        source: func.source.clone(),
+        params: vars.params.clone(),
+        insts: gen.insts,
+        stats: alloc.stats(instructions),
+    })
+}
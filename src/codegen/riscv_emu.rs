@@ -0,0 +1,239 @@
+//! A small RV32IM/RV64IM interpreter for the code in `riscv`, used to check
+//! generated functions without hardware.
+
+use std::collections::HashMap;
+use std::fmt;
+use codegen::riscv::{fits_imm12, Reg, RvFunction, RvImmOp, RvInst, RvOp, A0, SP};
+
+const STACK_SIZE: usize = 64 * 1024;
+const MAX_STEPS: usize = 1_000_000;
+
+/// Why execution stopped before `ret`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RvTrap {
+    Ebreak,
+    BadAddress(u64),
+    UnknownLabel(String),
+    StepLimit,
+    /// An immediate the instruction cannot encode, which an assembler
+    /// would reject.
+    BadImmediate(String),
+}
+
+impl fmt::Display for RvTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RvTrap::Ebreak => write!(f, "ebreak"),
+            RvTrap::BadAddress(addr) => write!(f, "bad address 0x{:x}", addr),
+            RvTrap::UnknownLabel(ref label) => write!(f, "unknown label {}", label),
+            RvTrap::StepLimit => write!(f, "step limit exceeded"),
+            RvTrap::BadImmediate(ref inst) => write!(f, "unencodable immediate: {}", inst.trim()),
+        }
+    }
+}
+
+/// Machine state: registers hold XLEN-bit values zero-extended into `u64`,
+/// and memory is a single stack whose top is the initial `sp`.
+pub struct RvMachineSt {
+    pub xlen: u32,
+    pub regs: [u64; 32],
+    pub mem: Vec<u8>,
+}
+
+impl RvMachineSt {
+    pub fn new(xlen: u32) -> Self {
+        let mut machine = RvMachineSt {
+            xlen,
+            regs: [0; 32],
+            mem: vec![0; STACK_SIZE],
+        };
+        machine.regs[SP.0 as usize] = STACK_SIZE as u64;
+        machine
+    }
+
+    fn get(&self, reg: Reg) -> u64 {
+        self.regs[reg.0 as usize]
+    }
+
+    fn set(&mut self, reg: Reg, value: u64) {
+        if reg.0 != 0 {
+            self.regs[reg.0 as usize] = self.truncate(value);
+        }
+    }
+
+    fn truncate(&self, value: u64) -> u64 {
+        if self.xlen == 32 {
+            value & 0xFFFF_FFFF
+        } else {
+            value
+        }
+    }
+
+    /// Sign-extends an XLEN-bit register value to 64 bits.
+    fn signed(&self, value: u64) -> i64 {
+        if self.xlen == 32 {
+            value as u32 as i32 as i64
+        } else {
+            value as i64
+        }
+    }
+
+    fn address(&self, base: Reg, offset: i64, size: usize) -> Result<usize, RvTrap> {
+        let addr = self.truncate(self.get(base).wrapping_add(offset as u64));
+        if addr as usize + size > self.mem.len() {
+            return Err(RvTrap::BadAddress(addr));
+        }
+        Ok(addr as usize)
+    }
+
+    fn load(&self, base: Reg, offset: i64, size: usize) -> Result<u64, RvTrap> {
+        let addr = self.address(base, offset, size)?;
+        let mut value = 0u64;
+        for i in (0..size).rev() {
+            value = value << 8 | self.mem[addr + i] as u64;
+        }
+        // Loads sign-extend, as `lw` and `ld` do.
+        let shift = 64 - 8 * size as u32;
+        Ok(((value << shift) as i64 >> shift) as u64)
+    }
+
+    fn store(&mut self, base: Reg, offset: i64, size: usize, value: u64) -> Result<(), RvTrap> {
+        let addr = self.address(base, offset, size)?;
+        for i in 0..size {
+            self.mem[addr + i] = (value >> (8 * i)) as u8;
+        }
+        Ok(())
+    }
+
+    fn op(&self, op: RvOp, a: u64, b: u64) -> u64 {
+        let shamt = (b & (self.xlen as u64 - 1)) as u32;
+        let a32 = a as u32;
+        let b32 = b as u32;
+        // Results of the `*w` forms are sign-extended from 32 bits.
+        let w = |value: u32| value as i32 as i64 as u64;
+        match op {
+            RvOp::Add => a.wrapping_add(b),
+            RvOp::Sub => a.wrapping_sub(b),
+            RvOp::Mul => a.wrapping_mul(b),
+            RvOp::Divu => {
+                if self.truncate(b) == 0 {
+                    u64::MAX
+                } else {
+                    self.truncate(a) / self.truncate(b)
+                }
+            }
+            RvOp::Sll => a << shamt,
+            RvOp::Srl => self.truncate(a) >> shamt,
+            RvOp::Sra => (self.signed(a) >> shamt) as u64,
+            RvOp::And => a & b,
+            RvOp::Or => a | b,
+            RvOp::Xor => a ^ b,
+            RvOp::Addw => w(a32.wrapping_add(b32)),
+            RvOp::Subw => w(a32.wrapping_sub(b32)),
+            RvOp::Mulw => w(a32.wrapping_mul(b32)),
+            RvOp::Divuw => w(a32.checked_div(b32).unwrap_or(u32::MAX)),
+            RvOp::Sllw => w(a32 << (b32 & 31)),
+            RvOp::Srlw => w(a32 >> (b32 & 31)),
+            RvOp::Sraw => w(((a32 as i32) >> (b32 & 31)) as u32),
+        }
+    }
+
+    fn op_imm(&self, op: RvImmOp, a: u64, imm: i64) -> u64 {
+        match op {
+            RvImmOp::Addi => self.op(RvOp::Add, a, imm as u64),
+            RvImmOp::Sltiu => (self.truncate(a) < self.truncate(imm as u64)) as u64,
+            RvImmOp::Andi => a & imm as u64,
+            RvImmOp::Xori => a ^ imm as u64,
+            RvImmOp::Slli => self.op(RvOp::Sll, a, imm as u64),
+            RvImmOp::Srli => self.op(RvOp::Srl, a, imm as u64),
+            RvImmOp::Srai => self.op(RvOp::Sra, a, imm as u64),
+        }
+    }
+
+    /// Checks that `inst` could be assembled: 12-bit signed immediates and
+    /// offsets, and shift amounts below XLEN.
+    fn check_immediate(&self, inst: &RvInst) -> Result<(), RvTrap> {
+        let ok = match *inst {
+            RvInst::OpImm(RvImmOp::Slli, _, _, imm)
+            | RvInst::OpImm(RvImmOp::Srli, _, _, imm)
+            | RvInst::OpImm(RvImmOp::Srai, _, _, imm) => (0..i64::from(self.xlen)).contains(&imm),
+            RvInst::OpImm(_, _, _, imm)
+            | RvInst::Lw(_, imm, _)
+            | RvInst::Ld(_, imm, _)
+            | RvInst::Sw(_, imm, _)
+            | RvInst::Sd(_, imm, _) => fits_imm12(imm),
+            _ => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(RvTrap::BadImmediate(inst.to_string()))
+        }
+    }
+
+    /// Calls `func` with `args` in `a0`, `a1`, ... and returns `a0` at `ret`.
+    pub fn call(&mut self, func: &RvFunction, args: &[u64]) -> Result<u64, RvTrap> {
+        let mut labels = HashMap::new();
+        for (i, inst) in func.insts.iter().enumerate() {
+            if let RvInst::Label(ref label) = *inst {
+                labels.insert(label.clone(), i);
+            }
+        }
+        for (i, &arg) in args.iter().enumerate() {
+            self.set(Reg(A0.0 + i as u8), arg);
+        }
+
+        let size = self.xlen as usize / 8;
+        let mut pc = 0;
+        for _ in 0..MAX_STEPS {
+            let inst = match func.insts.get(pc) {
+                Some(inst) => inst,
+                None => return Err(RvTrap::BadAddress(pc as u64)),
+            };
+            pc += 1;
+            self.check_immediate(inst)?;
+            match *inst {
+                RvInst::Label(_) => {}
+                RvInst::Li(rd, imm) => self.set(rd, imm as u64),
+                RvInst::Mv(rd, rs) => {
+                    let value = self.get(rs);
+                    self.set(rd, value);
+                }
+                RvInst::Op(op, rd, rs1, rs2) => {
+                    let value = self.op(op, self.get(rs1), self.get(rs2));
+                    self.set(rd, value);
+                }
+                RvInst::OpImm(op, rd, rs, imm) => {
+                    let value = self.op_imm(op, self.get(rs), imm);
+                    self.set(rd, value);
+                }
+                RvInst::Lw(rd, offset, base) => {
+                    let value = self.load(base, offset, 4)?;
+                    self.set(rd, value);
+                }
+                RvInst::Ld(rd, offset, base) => {
+                    let value = self.load(base, offset, size)?;
+                    self.set(rd, value);
+                }
+                RvInst::Sw(rs, offset, base) => self.store(base, offset, 4, self.get(rs))?,
+                RvInst::Sd(rs, offset, base) => self.store(base, offset, size, self.get(rs))?,
+                RvInst::Beqz(rs, ref label) => {
+                    if self.get(rs) == 0 {
+                        pc = match labels.get(label) {
+                            Some(&target) => target,
+                            None => return Err(RvTrap::UnknownLabel(label.clone())),
+                        };
+                    }
+                }
+                RvInst::Ebreak => return Err(RvTrap::Ebreak),
+                RvInst::Ret => return Ok(self.get(A0)),
+            }
+        }
+        Err(RvTrap::StepLimit)
+    }
+}
+
+/// Runs `func` on a fresh machine.
+pub fn run(func: &RvFunction, args: &[u64]) -> Result<u64, RvTrap> {
+    RvMachineSt::new(func.xlen).call(func, args)
+}
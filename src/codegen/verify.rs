@@ -0,0 +1,141 @@
+//! Cross-checks generated code against `eval` by running it in an
+//! emulator on the same inputs.
+
+use std::fmt;
+use codegen::{check_overflow, check_width, ir, ir_interp, riscv, riscv_emu, wasm, wasm_interp, CodegenError};
+use config::{Config, Emit};
+use eval::{eval, eval_format, EvalEnvSt};
+use parse::ParseNode;
+use word::Word;
+
+/// Inputs tried for free variables; chosen to hit sign and carry edges.
+/// Narrower programs see their low bits.
+const SAMPLES: [u32; 8] = [0, 1, 2, 7, 0x7FFF_FFFF, 0x8000_0000, 0xFFFF_FFFF, 0x1234_5678];
+
+/// One evaluation of the program on a fixed set of inputs.
+pub struct VerifyCaseSt {
+    /// Values for `VerifyReportSt::params`, in order.
+    pub inputs: Vec<u32>,
+    /// What `eval` computed, or `None` if it reported an error.
+    pub expected: Option<Word>,
+    /// Per target: the returned register, or `None` if the code trapped.
+    pub actual: Vec<(&'static str, Option<u64>)>,
+    /// The register value each target must return, keyed like `actual`.
+    pub wanted: Vec<Option<u64>>,
+}
+
+impl VerifyCaseSt {
+    pub fn ok(&self) -> bool {
+        self.actual.iter().zip(&self.wanted).all(|(&(_, actual), wanted)| actual == *wanted)
+    }
+}
+
+pub struct VerifyReportSt {
+    pub params: Vec<String>,
+    pub cases: Vec<VerifyCaseSt>,
+    base: Config,
+}
+
+impl VerifyReportSt {
+    pub fn ok(&self) -> bool {
+        self.cases.iter().all(|case| case.ok())
+    }
+}
+
+impl fmt::Display for VerifyReportSt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for case in &self.cases {
+            for (param, value) in self.params.iter().zip(&case.inputs) {
+                let value = Word::new(u128::from(*value), self.base.width);
+                write!(f, "{}={} ", param, eval_format(&self.base, &value))?;
+            }
+            match case.expected {
+                Some(ref value) => write!(f, "eval {}", eval_format(&self.base, value))?,
+                None => write!(f, "eval error")?,
+            }
+            for &(target, actual) in &case.actual {
+                match actual {
+                    Some(value) => write!(f, ", {} {:#x}", target, value)?,
+                    None => write!(f, ", {} trap", target)?,
+                }
+            }
+            writeln!(f, ": {}", if case.ok() { "ok" } else { "MISMATCH" })?;
+        }
+        Ok(())
+    }
+}
+
+/// The register value a generated function must return for `value`: the low
+/// `cp.width` bits, extended to `xlen` bits the way the result is printed.
+pub fn expected_result(value: &Word, cp: &Config, xlen: u32) -> u64 {
+    let mask = if cp.width >= 64 { u64::MAX } else { (1u64 << cp.width) - 1 };
+    let mut bits = value.bits() as u64 & mask;
+    if cp.unsigned_int && bits >> (cp.width - 1) & 1 == 1 {
+        bits |= !mask;
+    }
+    if xlen == 32 {
+        bits & 0xFFFF_FFFF
+    } else {
+        bits
+    }
+}
+
+/// Evaluates `node` with `eval` and with every emulated backend, once per
+/// input vector, and records whether the results agree. The IR interpreter
+/// runs the IR as lowered, the other targets their legalized code.
+pub fn verify(node: &ParseNode, cp: &Config) -> Result<VerifyReportSt, CodegenError> {
+    // RV32 and WebAssembly compute in 32-bit registers.
+    check_width(node, cp, Emit::Riscv32)?;
+    check_overflow(node, cp)?;
+    let func = ir::lower(node, cp);
+    let legal = ir::legalize(&func);
+    let vars = &func.vars;
+    let rv32 = riscv::compile(&legal, 32)?;
+    let rv64 = riscv::compile(&legal, 64)?;
+    let module = wasm::compile(&legal);
+    let wasm_bytes = module.encode();
+    let wasm_bits = if module.result == wasm::WasmType::I32 { 32 } else { 64 };
+
+    let runs = if vars.params.is_empty() { 1 } else { SAMPLES.len() };
+    let mut cases = Vec::new();
+    for i in 0..runs {
+        let inputs: Vec<u32> = (0..vars.params.len())
+            .map(|j| SAMPLES[(i + 3 * j) % SAMPLES.len()])
+            .collect();
+
+        let mut env = EvalEnvSt::new();
+        env.width = cp.width;
+        for (param, &value) in vars.params.iter().zip(&inputs) {
+            env.set(param, Word::new(u128::from(value), cp.width));
+        }
+        let expected = eval(&mut env, &Some(node)).ok();
+
+        // RV64 passes 32-bit arguments sign-extended, as its ABI requires.
+        let args32: Vec<u64> = inputs.iter().map(|&v| v as u64).collect();
+        let args64: Vec<u64> = inputs.iter().map(|&v| v as i32 as i64 as u64).collect();
+        let actual = vec![
+            ("ir", ir_interp::run(&func, &inputs).ok()),
+            ("rv32", riscv_emu::run(&rv32, &args32).ok()),
+            ("rv64", riscv_emu::run(&rv64, &args64).ok()),
+            ("wasm", wasm_interp::run(&wasm_bytes, &module.name, &args32).ok()),
+        ];
+        let wanted = vec![
+            expected.as_ref().map(|value| expected_result(value, cp, 64)),
+            expected.as_ref().map(|value| expected_result(value, cp, 32)),
+            expected.as_ref().map(|value| expected_result(value, cp, 64)),
+            expected.as_ref().map(|value| expected_result(value, cp, wasm_bits)),
+        ];
+        cases.push(VerifyCaseSt {
+            inputs,
+            expected,
+            actual,
+            wanted,
+        });
+    }
+
+    Ok(VerifyReportSt {
+        params: vars.params.clone(),
+        cases,
+        base: cp.clone(),
+    })
+}
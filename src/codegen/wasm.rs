@@ -0,0 +1,364 @@
+//! WebAssembly: a one-function module, as WAT text or as binary bytes.
+//!
+//! The function computes on `i32`, whose `div_u` traps on a zero divisor, so
+//! most IR operators map one-to-one. Shifts take their count mod 32, so a
+//! variable count is kept in an extra local while `select` picks 0, or a
+//! count of 31 for `shr_s`, when it is 32 or more; a constant count is
+//! checked here. Every variable is a local; parameters come first. The
+//! result is `i32`, except for unsigned 32-bit output where it is
+//! zero-extended to `i64` so JavaScript sees the value rather than a
+//! negative number.
+
+use std::fmt;
+use codegen::VarTableSt;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrUnOp};
+
+const MAGIC: [u8; 4] = [0x00, 0x61, 0x73, 0x6D];
+const VERSION: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+
+pub const SECTION_TYPE: u8 = 1;
+pub const SECTION_FUNCTION: u8 = 3;
+pub const SECTION_EXPORT: u8 = 7;
+pub const SECTION_CODE: u8 = 10;
+
+pub const FUNC_TYPE: u8 = 0x60;
+
+/// The WAT name of the shift count local, which no variable can have.
+const COUNT_LOCAL: &str = "ntlang.count";
+pub const EXPORT_FUNC: u8 = 0x00;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WasmType {
+    I32,
+    I64,
+}
+
+impl WasmType {
+    pub fn code(self) -> u8 {
+        match self {
+            WasmType::I32 => 0x7F,
+            WasmType::I64 => 0x7E,
+        }
+    }
+}
+
+impl fmt::Display for WasmType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WasmInst {
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Const(i32),
+    Drop,
+    Select,
+    I32LtU,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I64ExtendI32U,
+    End,
+}
+
+impl WasmInst {
+    pub fn opcode(self) -> u8 {
+        match self {
+            WasmInst::LocalGet(_) => 0x20,
+            WasmInst::LocalSet(_) => 0x21,
+            WasmInst::LocalTee(_) => 0x22,
+            WasmInst::I32Const(_) => 0x41,
+            WasmInst::Drop => 0x1A,
+            WasmInst::Select => 0x1B,
+            WasmInst::I32LtU => 0x49,
+            WasmInst::I32Add => 0x6A,
+            WasmInst::I32Sub => 0x6B,
+            WasmInst::I32Mul => 0x6C,
+            WasmInst::I32DivU => 0x6E,
+            WasmInst::I32And => 0x71,
+            WasmInst::I32Or => 0x72,
+            WasmInst::I32Xor => 0x73,
+            WasmInst::I32Shl => 0x74,
+            WasmInst::I32ShrS => 0x75,
+            WasmInst::I32ShrU => 0x76,
+            WasmInst::I64ExtendI32U => 0xAD,
+            WasmInst::End => 0x0B,
+        }
+    }
+
+    fn mnemonic(self) -> &'static str {
+        match self {
+            WasmInst::LocalGet(_) => "local.get",
+            WasmInst::LocalSet(_) => "local.set",
+            WasmInst::LocalTee(_) => "local.tee",
+            WasmInst::I32Const(_) => "i32.const",
+            WasmInst::Drop => "drop",
+            WasmInst::Select => "select",
+            WasmInst::I32LtU => "i32.lt_u",
+            WasmInst::I32Add => "i32.add",
+            WasmInst::I32Sub => "i32.sub",
+            WasmInst::I32Mul => "i32.mul",
+            WasmInst::I32DivU => "i32.div_u",
+            WasmInst::I32And => "i32.and",
+            WasmInst::I32Or => "i32.or",
+            WasmInst::I32Xor => "i32.xor",
+            WasmInst::I32Shl => "i32.shl",
+            WasmInst::I32ShrS => "i32.shr_s",
+            WasmInst::I32ShrU => "i32.shr_u",
+            WasmInst::I64ExtendI32U => "i64.extend_i32_u",
+            WasmInst::End => "end",
+        }
+    }
+}
+
+/// A module exporting one function, `name`, whose locals are
+/// `vars.vars` with the first `vars.params.len()` of them as parameters,
+/// then the shift count local if `uses_count`.
+pub struct WasmModule {
+    pub name: String,
+    pub source: String,
+    pub vars: VarTableSt,
+    pub uses_count: bool,
+    pub result: WasmType,
+    /// The function body, including its final `end`.
+    pub body: Vec<WasmInst>,
+}
+
+impl fmt::Display for WasmModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, ";; ntlang: {}", self.source)?;
+        writeln!(f, "(module")?;
+        write!(f, "  (func ${} (export \"{}\")", self.name, self.name)?;
+        for param in &self.vars.params {
+            write!(f, " (param ${} i32)", param)?;
+        }
+        writeln!(f, " (result {})", self.result)?;
+        for local in &self.vars.vars[self.vars.params.len()..] {
+            writeln!(f, "    (local ${} i32)", local)?;
+        }
+        if self.uses_count {
+            writeln!(f, "    (local ${} i32)", COUNT_LOCAL)?;
+        }
+        for &inst in &self.body[..self.body.len() - 1] {
+            match inst {
+                WasmInst::LocalGet(i) | WasmInst::LocalSet(i) | WasmInst::LocalTee(i) => {
+                    let name = self.vars.vars.get(i as usize).map_or(COUNT_LOCAL, |name| name.as_str());
+                    writeln!(f, "    {} ${}", inst.mnemonic(), name)?
+                }
+                WasmInst::I32Const(value) => writeln!(f, "    {} {}", inst.mnemonic(), value)?,
+                _ => writeln!(f, "    {}", inst.mnemonic())?,
+            }
+        }
+        writeln!(f, "  )")?;
+        writeln!(f, ")")
+    }
+}
+
+/// Appends `value` as unsigned LEB128.
+pub fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Appends `value` as signed LEB128.
+pub fn write_i32(out: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_u32(out, contents.len() as u32);
+    out.extend_from_slice(contents);
+}
+
+impl WasmModule {
+    /// Encodes the module in the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION);
+
+        let mut types = Vec::new();
+        write_u32(&mut types, 1);
+        types.push(FUNC_TYPE);
+        write_u32(&mut types, self.vars.params.len() as u32);
+        for _ in &self.vars.params {
+            types.push(WasmType::I32.code());
+        }
+        write_u32(&mut types, 1);
+        types.push(self.result.code());
+        write_section(&mut out, SECTION_TYPE, &types);
+
+        let mut funcs = Vec::new();
+        write_u32(&mut funcs, 1);
+        write_u32(&mut funcs, 0);
+        write_section(&mut out, SECTION_FUNCTION, &funcs);
+
+        let mut exports = Vec::new();
+        write_u32(&mut exports, 1);
+        write_name(&mut exports, &self.name);
+        exports.push(EXPORT_FUNC);
+        write_u32(&mut exports, 0);
+        write_section(&mut out, SECTION_EXPORT, &exports);
+
+        let mut body = Vec::new();
+        let locals = (self.vars.vars.len() - self.vars.params.len() + self.uses_count as usize) as u32;
+        if locals == 0 {
+            write_u32(&mut body, 0);
+        } else {
+            write_u32(&mut body, 1);
+            write_u32(&mut body, locals);
+            body.push(WasmType::I32.code());
+        }
+        for &inst in &self.body {
+            body.push(inst.opcode());
+            match inst {
+                WasmInst::LocalGet(i) | WasmInst::LocalSet(i) | WasmInst::LocalTee(i) => write_u32(&mut body, i),
+                WasmInst::I32Const(value) => write_i32(&mut body, value),
+                _ => {}
+            }
+        }
+        let mut code = Vec::new();
+        write_u32(&mut code, 1);
+        write_u32(&mut code, body.len() as u32);
+        code.extend_from_slice(&body);
+        write_section(&mut out, SECTION_CODE, &code);
+        out
+    }
+}
+
+/// Appends `shift` with the value and count on the stack, giving 0, or
+/// copies of the sign bit for `shr_s`, when the count is 32 or more.
+/// Returns whether the count went through local `count`.
+fn saturating_shift(body: &mut Vec<WasmInst>, shift: WasmInst, count: u32) -> bool {
+    if let Some(&WasmInst::I32Const(n)) = body.last() {
+        if (n as u32) < 32 {
+            body.push(shift);
+        } else if shift == WasmInst::I32ShrS {
+            *body.last_mut().unwrap() = WasmInst::I32Const(31);
+            body.push(shift);
+        } else {
+            body.pop();
+            body.push(WasmInst::Drop);
+            body.push(WasmInst::I32Const(0));
+        }
+        return false;
+    }
+    let in_range = |limit| [WasmInst::LocalGet(count), WasmInst::I32Const(limit), WasmInst::I32LtU, WasmInst::Select];
+    body.push(WasmInst::LocalTee(count));
+    if shift == WasmInst::I32ShrS {
+        body.push(WasmInst::I32Const(31));
+        body.extend_from_slice(&in_range(31));
+        body.push(shift);
+    } else {
+        body.extend_from_slice(&[shift, WasmInst::I32Const(0)]);
+        body.extend_from_slice(&in_range(32));
+    }
+    true
+}
+
+/// Compiles `func` to a module. The IR's temporaries form a stack (see
+/// `ir`), so they map straight onto the wasm operand stack; only variables
+/// need locals.
+pub fn compile(func: &IrFunction) -> WasmModule {
+    let uses = func.use_counts();
+    let count = func.vars.vars.len() as u32;
+    let mut uses_count = false;
+    let mut body = Vec::new();
+    let mut result = WasmType::I32;
+    for inst in &func.insts {
+        match *inst {
+            IrInst::Const { ref value, .. } => body.push(WasmInst::I32Const(value.bits() as u32 as i32)),
+            IrInst::Load { var, .. } => body.push(WasmInst::LocalGet(var as u32)),
+            IrInst::Store { var, .. } => body.push(WasmInst::LocalSet(var as u32)),
+            IrInst::Unary { op, .. } => {
+                // There is no `i32.neg`, and the operand is already on the
+                // stack, so negate by multiplying with -1.
+                body.push(WasmInst::I32Const(-1));
+                body.push(match op {
+                    IrUnOp::Neg => WasmInst::I32Mul,
+                    IrUnOp::Not => WasmInst::I32Xor,
+                });
+            }
+            IrInst::Binary { ty, op, .. } => match op {
+                IrBinOp::Add => body.push(WasmInst::I32Add),
+                IrBinOp::Sub => body.push(WasmInst::I32Sub),
+                IrBinOp::Mul => body.push(WasmInst::I32Mul),
+                IrBinOp::Div => body.push(WasmInst::I32DivU),
+                IrBinOp::Shl | IrBinOp::Shr => {
+                    let shift = match op {
+                        IrBinOp::Shl => WasmInst::I32Shl,
+                        _ if ty.signed => WasmInst::I32ShrS,
+                        _ => WasmInst::I32ShrU,
+                    };
+                    uses_count |= saturating_shift(&mut body, shift, count);
+                }
+                IrBinOp::And => body.push(WasmInst::I32And),
+                IrBinOp::Or => body.push(WasmInst::I32Or),
+                IrBinOp::Xor => body.push(WasmInst::I32Xor),
+            },
+            IrInst::Ret { ty, .. } => {
+                if ty.width < 32 {
+                    if ty.signed {
+                        let shift = 32 - ty.width as i32;
+                        body.push(WasmInst::I32Const(shift));
+                        body.push(WasmInst::I32Shl);
+                        body.push(WasmInst::I32Const(shift));
+                        body.push(WasmInst::I32ShrS);
+                    } else {
+                        body.push(WasmInst::I32Const(((1u32 << ty.width) - 1) as i32));
+                        body.push(WasmInst::I32And);
+                    }
+                } else if !ty.signed {
+                    body.push(WasmInst::I64ExtendI32U);
+                    result = WasmType::I64;
+                }
+            }
+        }
+        if let Some(dst) = inst.dst() {
+            if uses[dst] == 0 {
+                body.push(WasmInst::Drop);
+            }
+        }
+    }
+    body.push(WasmInst::End);
+
+    WasmModule {
+        name: func.name.clone(),
+        source: func.source.clone(),
+        vars: func.vars.clone(),
+        uses_count,
+        result,
+        body,
+    }
+}
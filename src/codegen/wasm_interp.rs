@@ -0,0 +1,344 @@
+//! A small WebAssembly interpreter for the modules `wasm` encodes. It decodes
+//! the binary itself rather than trusting `WasmModule`, so it checks the
+//! encoder as well as the generated code.
+
+use std::fmt;
+use codegen::wasm::{EXPORT_FUNC, FUNC_TYPE, SECTION_CODE, SECTION_EXPORT, SECTION_FUNCTION, SECTION_TYPE};
+
+/// Why a module could not be run to completion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WasmTrap {
+    /// The bytes are not a module this interpreter understands.
+    Malformed(&'static str),
+    NoExport(String),
+    IntegerDivideByZero,
+    Unreachable,
+}
+
+impl fmt::Display for WasmTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WasmTrap::Malformed(what) => write!(f, "malformed module: {}", what),
+            WasmTrap::NoExport(ref name) => write!(f, "no exported function {}", name),
+            WasmTrap::IntegerDivideByZero => write!(f, "integer divide by zero"),
+            WasmTrap::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Val {
+    I32(u32),
+    I64(u64),
+}
+
+const I32: u8 = 0x7F;
+const I64: u8 = 0x7E;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, WasmTrap> {
+        let byte = *self.bytes.get(self.pos).ok_or(WasmTrap::Malformed("unexpected end"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], WasmTrap> {
+        if self.pos + len > self.bytes.len() {
+            return Err(WasmTrap::Malformed("unexpected end"));
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn u32(&mut self) -> Result<u32, WasmTrap> {
+        let mut value = 0u64;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return if value > u32::MAX as u64 {
+                    Err(WasmTrap::Malformed("integer too large"))
+                } else {
+                    Ok(value as u32)
+                };
+            }
+        }
+        Err(WasmTrap::Malformed("integer representation too long"))
+    }
+
+    fn s64(&mut self, bits: u32) -> Result<i64, WasmTrap> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                if bits < 64 && (value < -(1 << (bits - 1)) || value >= 1 << (bits - 1)) {
+                    return Err(WasmTrap::Malformed("integer too large"));
+                }
+                return Ok(value);
+            }
+            if shift >= bits.div_ceil(7) * 7 {
+                return Err(WasmTrap::Malformed("integer representation too long"));
+            }
+        }
+    }
+
+    fn value_type(&mut self) -> Result<u8, WasmTrap> {
+        match self.byte()? {
+            ty @ I32 | ty @ I64 => Ok(ty),
+            _ => Err(WasmTrap::Malformed("unsupported value type")),
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+struct FuncType {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+struct Func<'a> {
+    type_index: usize,
+    locals: Vec<u8>,
+    code: &'a [u8],
+}
+
+/// The parts of a decoded module the interpreter needs.
+struct Module<'a> {
+    types: Vec<FuncType>,
+    funcs: Vec<Func<'a>>,
+    exports: Vec<(String, usize)>,
+}
+
+fn decode<'a>(bytes: &'a [u8]) -> Result<Module<'a>, WasmTrap> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.bytes(4)? != b"\0asm" {
+        return Err(WasmTrap::Malformed("bad magic"));
+    }
+    if r.bytes(4)? != [1, 0, 0, 0] {
+        return Err(WasmTrap::Malformed("unsupported version"));
+    }
+
+    let mut module = Module {
+        types: Vec::new(),
+        funcs: Vec::new(),
+        exports: Vec::new(),
+    };
+    let mut func_types = Vec::new();
+    let mut last_id = 0;
+    while !r.done() {
+        let id = r.byte()?;
+        let len = r.u32()? as usize;
+        let mut s = Reader { bytes: r.bytes(len)?, pos: 0 };
+        if id != 0 {
+            if id <= last_id {
+                return Err(WasmTrap::Malformed("sections out of order"));
+            }
+            last_id = id;
+        }
+        match id {
+            0 => continue,
+            SECTION_TYPE => {
+                for _ in 0..s.u32()? {
+                    if s.byte()? != FUNC_TYPE {
+                        return Err(WasmTrap::Malformed("expected a function type"));
+                    }
+                    let params = (0..s.u32()?).map(|_| s.value_type()).collect::<Result<_, _>>()?;
+                    let results = (0..s.u32()?).map(|_| s.value_type()).collect::<Result<_, _>>()?;
+                    module.types.push(FuncType { params, results });
+                }
+            }
+            SECTION_FUNCTION => {
+                for _ in 0..s.u32()? {
+                    let index = s.u32()? as usize;
+                    if index >= module.types.len() {
+                        return Err(WasmTrap::Malformed("unknown type"));
+                    }
+                    func_types.push(index);
+                }
+            }
+            SECTION_EXPORT => {
+                for _ in 0..s.u32()? {
+                    let len = s.u32()? as usize;
+                    let name = String::from_utf8(s.bytes(len)?.to_vec())
+                        .map_err(|_| WasmTrap::Malformed("export name is not UTF-8"))?;
+                    let kind = s.byte()?;
+                    let index = s.u32()? as usize;
+                    if kind == EXPORT_FUNC {
+                        module.exports.push((name, index));
+                    }
+                }
+            }
+            SECTION_CODE => {
+                if s.u32()? as usize != func_types.len() {
+                    return Err(WasmTrap::Malformed("function and code section counts differ"));
+                }
+                for &type_index in &func_types {
+                    let size = s.u32()? as usize;
+                    let mut body = Reader { bytes: s.bytes(size)?, pos: 0 };
+                    let mut locals = Vec::new();
+                    for _ in 0..body.u32()? {
+                        let count = body.u32()?;
+                        let ty = body.value_type()?;
+                        locals.extend((0..count).map(|_| ty));
+                    }
+                    module.funcs.push(Func {
+                        type_index,
+                        locals,
+                        code: &body.bytes[body.pos..],
+                    });
+                }
+            }
+            _ => return Err(WasmTrap::Malformed("unsupported section")),
+        }
+        if !s.done() {
+            return Err(WasmTrap::Malformed("section size mismatch"));
+        }
+    }
+    if module.funcs.len() != func_types.len() {
+        return Err(WasmTrap::Malformed("function and code section counts differ"));
+    }
+    Ok(module)
+}
+
+fn pop(stack: &mut Vec<Val>) -> Result<Val, WasmTrap> {
+    stack.pop().ok_or(WasmTrap::Malformed("operand stack underflow"))
+}
+
+fn pop_i32(stack: &mut Vec<Val>) -> Result<u32, WasmTrap> {
+    match pop(stack)? {
+        Val::I32(value) => Ok(value),
+        _ => Err(WasmTrap::Malformed("type mismatch")),
+    }
+}
+
+/// Executes one function body of the subset `wasm` emits (plus `nop` and
+/// `unreachable`), up to its final `end`.
+fn execute(func: &Func, ty: &FuncType, args: &[u64]) -> Result<Val, WasmTrap> {
+    let mut locals: Vec<Val> = ty
+        .params
+        .iter()
+        .zip(args)
+        .map(|(&ty, &arg)| if ty == I32 { Val::I32(arg as u32) } else { Val::I64(arg) })
+        .collect();
+    locals.extend(func.locals.iter().map(|&ty| if ty == I32 { Val::I32(0) } else { Val::I64(0) }));
+
+    let mut r = Reader { bytes: func.code, pos: 0 };
+    let mut stack = Vec::new();
+    loop {
+        match r.byte()? {
+            0x00 => return Err(WasmTrap::Unreachable),
+            0x01 => {}
+            0x0B => break,
+            0x1A => {
+                pop(&mut stack)?;
+            }
+            op @ 0x20..=0x22 => {
+                let index = r.u32()? as usize;
+                if index >= locals.len() {
+                    return Err(WasmTrap::Malformed("unknown local"));
+                }
+                if op == 0x20 {
+                    stack.push(locals[index]);
+                } else {
+                    let value = pop(&mut stack)?;
+                    if std::mem::discriminant(&value) != std::mem::discriminant(&locals[index]) {
+                        return Err(WasmTrap::Malformed("type mismatch"));
+                    }
+                    locals[index] = value;
+                    if op == 0x22 {
+                        stack.push(value);
+                    }
+                }
+            }
+            0x1B => {
+                let cond = pop_i32(&mut stack)?;
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                if std::mem::discriminant(&a) != std::mem::discriminant(&b) {
+                    return Err(WasmTrap::Malformed("type mismatch"));
+                }
+                stack.push(if cond != 0 { a } else { b });
+            }
+            0x41 => stack.push(Val::I32(r.s64(32)? as u32)),
+            0x42 => stack.push(Val::I64(r.s64(64)? as u64)),
+            0x49 => {
+                let b = pop_i32(&mut stack)?;
+                let a = pop_i32(&mut stack)?;
+                stack.push(Val::I32((a < b) as u32));
+            }
+            op @ 0x6A..=0x78 => {
+                let b = pop_i32(&mut stack)?;
+                let a = pop_i32(&mut stack)?;
+                let value = match op {
+                    0x6A => a.wrapping_add(b),
+                    0x6B => a.wrapping_sub(b),
+                    0x6C => a.wrapping_mul(b),
+                    0x6E => a.checked_div(b).ok_or(WasmTrap::IntegerDivideByZero)?,
+                    0x70 => a.checked_rem(b).ok_or(WasmTrap::IntegerDivideByZero)?,
+                    0x71 => a & b,
+                    0x72 => a | b,
+                    0x73 => a ^ b,
+                    0x74 => a.wrapping_shl(b),
+                    0x75 => (a as i32).wrapping_shr(b) as u32,
+                    0x76 => a.wrapping_shr(b),
+                    0x77 => a.rotate_left(b % 32),
+                    0x78 => a.rotate_right(b % 32),
+                    _ => return Err(WasmTrap::Malformed("unsupported opcode")),
+                };
+                stack.push(Val::I32(value));
+            }
+            0xAC => {
+                let value = pop_i32(&mut stack)?;
+                stack.push(Val::I64(value as i32 as i64 as u64));
+            }
+            0xAD => {
+                let value = pop_i32(&mut stack)?;
+                stack.push(Val::I64(value as u64));
+            }
+            _ => return Err(WasmTrap::Malformed("unsupported opcode")),
+        }
+    }
+    if !r.done() {
+        return Err(WasmTrap::Malformed("code after the final end"));
+    }
+
+    let result = pop(&mut stack)?;
+    let result_type = if let Val::I32(_) = result { I32 } else { I64 };
+    if !stack.is_empty() || ty.results != [result_type] {
+        return Err(WasmTrap::Malformed("result does not match the function type"));
+    }
+    Ok(result)
+}
+
+/// Decodes `bytes` and calls the exported function `name` with `args`,
+/// truncated to the parameter types. The result is zero-extended to `u64`.
+pub fn run(bytes: &[u8], name: &str, args: &[u64]) -> Result<u64, WasmTrap> {
+    let module = decode(bytes)?;
+    let index = match module.exports.iter().find(|&(export, _)| export == name) {
+        Some(&(_, index)) => index,
+        None => return Err(WasmTrap::NoExport(name.to_string())),
+    };
+    let func = module.funcs.get(index).ok_or(WasmTrap::Malformed("unknown function"))?;
+    let ty = &module.types[func.type_index];
+    if ty.params.len() != args.len() {
+        return Err(WasmTrap::Malformed("wrong number of arguments"));
+    }
+    match execute(func, ty, args)? {
+        Val::I32(value) => Ok(value as u64),
+        Val::I64(value) => Ok(value),
+    }
+}
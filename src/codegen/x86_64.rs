@@ -0,0 +1,310 @@
+//! x86-64 assembly (GAS, Intel syntax) for the System V ABI.
+//!
+//! Uses the same scheme as the other assembly backends: IR temporaries in
+//! registers or, once those run out, in frame slots used directly as memory
+//! operands, and variables in the frame below `rbp`. All arithmetic is on
+//! 32-bit registers, which wraps like `eval`, or on 64-bit registers with
+//! 8-byte slots for a function legalized to 64 bits. `eax` and `edx` are
+//! kept free for `div`, spilled results and checking shift counts, and
+//! `ecx` for shift counts.
+
+use codegen::CodegenError;
+use codegen::ir::{IrBinOp, IrFunction, IrInst, IrUnOp, Temp};
+use codegen::regalloc::{self, Loc};
+
+const NAMES32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d", "r13d",
+    "r14d", "r15d",
+];
+
+const NAMES64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
+
+const AX: usize = 0;
+const CX: usize = 1;
+const DX: usize = 2;
+
+/// Parameter registers, in order.
+const PARAMS: [usize; 6] = [7, 6, 2, 1, 8, 9];
+/// Registers for IR temporaries, in allocation order.
+const TEMPS: [usize; 6] = [8, 9, 10, 11, 6, 7];
+pub const MAX_PARAMS: usize = 6;
+
+const DIV_BY_ZERO: &str = ".Ldiv_by_zero";
+
+struct X64Gen<'a> {
+    /// Whether the function computes in 64-bit registers.
+    wide: bool,
+    locs: &'a [Loc],
+    /// Frame slot of the first spilled temporary.
+    spill_base: usize,
+    /// The value of each temporary assigned a constant.
+    constants: Vec<Option<u32>>,
+    lines: Vec<String>,
+    uses_div: bool,
+}
+
+impl<'a> X64Gen<'a> {
+    fn emit(&mut self, line: String) {
+        self.lines.push(format!("    {}", line));
+    }
+
+    /// The name of register `reg` at the width the function computes in.
+    fn name(&self, reg: usize) -> &'static str {
+        if self.wide {
+            NAMES64[reg]
+        } else {
+            NAMES32[reg]
+        }
+    }
+
+    fn slot_size(&self) -> usize {
+        if self.wide {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// The memory operand for frame slot `slot`; variables come first, then
+    /// spilled temporaries.
+    fn slot(&self, slot: usize) -> String {
+        let size = if self.wide { "qword" } else { "dword" };
+        format!("{} ptr [rbp - {}]", size, self.slot_size() * (slot + 1))
+    }
+
+    /// The register or memory operand holding `temp`.
+    fn operand(&self, temp: Temp) -> String {
+        match self.locs[temp] {
+            Loc::Reg(i) => self.name(TEMPS[i]).to_string(),
+            Loc::Slot(slot) => self.slot(self.spill_base + slot),
+        }
+    }
+
+    /// The register to compute `temp` in: its own, or `eax` if it is
+    /// spilled, in which case `write` stores it.
+    fn dest(&self, temp: Temp) -> &'static str {
+        match self.locs[temp] {
+            Loc::Reg(i) => self.name(TEMPS[i]),
+            Loc::Slot(_) => self.name(AX),
+        }
+    }
+
+    fn write(&mut self, temp: Temp, rd: &str) {
+        if let Loc::Slot(_) = self.locs[temp] {
+            let dst = self.operand(temp);
+            self.emit(format!("mov {}, {}", dst, rd));
+        }
+    }
+
+    fn mov(&mut self, rd: &str, src: &str) {
+        if rd != src {
+            self.emit(format!("mov {}, {}", rd, src));
+        }
+    }
+
+    fn gen(&mut self, inst: &IrInst) {
+        match *inst {
+            IrInst::Const { dst, ref value, .. } => {
+                let value = value.bits() as u64;
+                let rd = self.operand(dst);
+                if value > i32::MAX as u64 && rd.contains("qword") {
+                    // A memory operand takes a sign-extended 32-bit immediate.
+                    let ax = self.name(AX);
+                    self.emit(format!("mov {}, {}", ax, value));
+                    self.emit(format!("mov {}, {}", rd, ax));
+                } else {
+                    self.emit(format!("mov {}, {}", rd, value));
+                }
+            }
+            IrInst::Load { dst, var, .. } => {
+                let rd = self.dest(dst);
+                let src = self.slot(var);
+                self.emit(format!("mov {}, {}", rd, src));
+                self.write(dst, rd);
+            }
+            IrInst::Store { var, src, .. } => {
+                let mut rs = self.operand(src);
+                if let Loc::Slot(_) = self.locs[src] {
+                    let ax = self.name(AX);
+                    self.emit(format!("mov {}, {}", ax, rs));
+                    rs = ax.to_string();
+                }
+                let dst = self.slot(var);
+                self.emit(format!("mov {}, {}", dst, rs));
+            }
+            IrInst::Unary { dst, op, src, .. } => {
+                let rd = self.dest(dst);
+                let src = self.operand(src);
+                self.mov(rd, &src);
+                match op {
+                    IrUnOp::Neg => self.emit(format!("neg {}", rd)),
+                    IrUnOp::Not => self.emit(format!("not {}", rd)),
+                }
+                self.write(dst, rd);
+            }
+            IrInst::Binary { dst, ty, op, lhs, rhs } => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                let rd = self.dest(dst);
+                let (ax, cx, dx) = (self.name(AX), self.name(CX), self.name(DX));
+                match op {
+                    IrBinOp::Div => {
+                        // NTLang's `/` is unsigned, so this is `div` rather than `idiv`.
+                        self.uses_div = true;
+                        if let Loc::Slot(_) = self.locs[rhs] {
+                            self.emit(format!("cmp {}, 0", r));
+                        } else {
+                            self.emit(format!("test {}, {}", r, r));
+                        }
+                        self.emit(format!("jz {}", DIV_BY_ZERO));
+                        self.mov(ax, &l);
+                        self.emit("xor edx, edx".to_string());
+                        self.emit(format!("div {}", r));
+                        self.mov(rd, ax);
+                    }
+                    IrBinOp::Shl | IrBinOp::Shr => {
+                        let mnemonic = match op {
+                            IrBinOp::Shl => "shl",
+                            _ if ty.signed => "sar",
+                            _ => "shr",
+                        };
+                        // The count must be in `cl`, which is never a temporary.
+                        // The hardware takes it mod the width, so one of the
+                        // width or more becomes the width less one for `sar`
+                        // and makes the others give 0.
+                        self.emit(format!("mov {}, {}", cx, r));
+                        let checked = self.constants[rhs].is_none_or(|n| n >= ty.width);
+                        if checked && ty.signed {
+                            self.emit(format!("mov edx, {}", ty.width - 1));
+                            self.emit(format!("cmp {}, {}", cx, ty.width));
+                            self.emit(format!("cmovae {}, {}", cx, dx));
+                        }
+                        self.mov(rd, &l);
+                        self.emit(format!("{} {}, cl", mnemonic, rd));
+                        if checked && !ty.signed {
+                            self.emit("xor edx, edx".to_string());
+                            self.emit(format!("cmp {}, {}", cx, ty.width));
+                            self.emit(format!("cmovae {}, {}", rd, dx));
+                        }
+                    }
+                    _ => {
+                        let mnemonic = match op {
+                            IrBinOp::Add => "add",
+                            IrBinOp::Sub => "sub",
+                            IrBinOp::Mul => "imul",
+                            IrBinOp::And => "and",
+                            IrBinOp::Or => "or",
+                            IrBinOp::Xor => "xor",
+                            _ => unreachable!(),
+                        };
+                        if rd == r && rd != l {
+                            // The result register holds the right operand;
+                            // work in `eax` so it survives until used.
+                            self.mov(ax, &l);
+                            self.emit(format!("{} {}, {}", mnemonic, ax, r));
+                            self.mov(rd, ax);
+                        } else {
+                            self.mov(rd, &l);
+                            self.emit(format!("{} {}, {}", mnemonic, rd, r));
+                        }
+                    }
+                }
+                self.write(dst, rd);
+            }
+            IrInst::Ret { ty, src } if self.wide => {
+                let src = self.operand(src);
+                self.mov("rax", &src);
+                let shift = 64 - ty.width;
+                if shift > 0 {
+                    self.emit(format!("shl rax, {}", shift));
+                    let mnemonic = if ty.signed { "sar" } else { "shr" };
+                    self.emit(format!("{} rax, {}", mnemonic, shift));
+                }
+            }
+            IrInst::Ret { ty, src } => {
+                let src = self.operand(src);
+                self.mov("eax", &src);
+                let shift = 32 - ty.width;
+                if ty.signed {
+                    if shift > 0 {
+                        self.emit(format!("shl eax, {}", shift));
+                        self.emit(format!("sar eax, {}", shift));
+                    }
+                    self.emit("movsxd rax, eax".to_string());
+                } else if shift > 0 {
+                    self.emit(format!("and eax, {}", (1u32 << ty.width) - 1));
+                }
+            }
+        }
+    }
+}
+
+/// Compiles `func` to an x86-64 function. Parameters arrive in `edi`,
+/// `esi`, `edx`, `ecx`, `r8d` and `r9d`, or the whole of `rdi`, `rsi`, ...
+/// above 32 bits; the result is returned in `rax`, extended to 64 bits as
+/// the IR's `ret` specifies. A zero divisor executes `ud2`.
+pub fn compile(func: &IrFunction) -> Result<String, CodegenError> {
+    let vars = &func.vars;
+    if vars.params.len() > MAX_PARAMS {
+        return Err(CodegenError::TooManyParams {
+            count: vars.params.len(),
+            max: MAX_PARAMS,
+            span: func.span,
+        });
+    }
+
+    let func = &regalloc::sethi_ullman(func);
+    let alloc = regalloc::linear_scan(func, TEMPS.len());
+    let mut gen = X64Gen {
+        wide: func.width() > 32,
+        locs: &alloc.locs,
+        spill_base: vars.vars.len(),
+        constants: func.constants(),
+        lines: Vec::new(),
+        uses_div: false,
+    };
+
+    let frame = (gen.slot_size() * (vars.vars.len() + alloc.spill_slots)).div_ceil(16) * 16;
+    if frame > 0 {
+        gen.emit("push rbp".to_string());
+        gen.emit("mov rbp, rsp".to_string());
+        gen.emit(format!("sub rsp, {}", frame));
+        for (i, &reg) in PARAMS.iter().take(vars.params.len()).enumerate() {
+            let (slot, reg) = (gen.slot(i), gen.name(reg));
+            gen.emit(format!("mov {}, {}", slot, reg));
+        }
+    }
+
+    for inst in &func.insts {
+        gen.gen(inst);
+    }
+
+    if frame > 0 {
+        gen.emit("leave".to_string());
+    }
+    gen.emit("ret".to_string());
+    if gen.uses_div {
+        gen.lines.push(format!("{}:", DIV_BY_ZERO));
+        gen.emit("ud2".to_string());
+    }
+
+    // Labels are the only lines `emit` did not indent.
+    let instructions = gen.lines.iter().filter(|line| line.starts_with(' ')).count();
+    let name = &func.name;
+    let mut out = format!("# ntlang: {}\n", func.source);
+    out.push_str(&format!("# {}\n", alloc.stats(instructions)));
+    for (i, param) in vars.params.iter().enumerate() {
+        out.push_str(&format!("# {} = {}\n", gen.name(PARAMS[i]), param));
+    }
+    out.push_str("    .intel_syntax noprefix\n");
+    out.push_str(&format!("    .text\n    .globl {}\n    .type {}, @function\n{}:\n", name, name, name));
+    for line in &gen.lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str(&format!("    .size {}, .-{}\n", name, name));
+    // The code never needs an executable stack.
+    out.push_str("    .section .note.GNU-stack,\"\",@progbits\n");
+    Ok(out)
+}
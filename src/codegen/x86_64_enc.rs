@@ -0,0 +1,350 @@
+//! A minimal x86-64 machine-code encoder, for the backends that produce
+//! code without an assembler: `elf` and `jit`.
+
+use codegen::ir::{IrBinOp, IrInst, IrUnOp};
+
+pub const RAX: u8 = 0;
+pub const RCX: u8 = 1;
+pub const RDX: u8 = 2;
+pub const RSP: u8 = 4;
+pub const RBP: u8 = 5;
+pub const RSI: u8 = 6;
+pub const RDI: u8 = 7;
+pub const R8: u8 = 8;
+pub const R9: u8 = 9;
+
+/// The eight classic ALU operations. Each is the `/digit` extension of
+/// the `0x81` immediate group, and `op << 3 | 1` is its `r/m, reg` opcode.
+pub const ADD: u8 = 0;
+pub const OR: u8 = 1;
+pub const AND: u8 = 4;
+pub const SUB: u8 = 5;
+pub const XOR: u8 = 6;
+pub const CMP: u8 = 7;
+
+/// `/digit` extensions of the `0xC1`/`0xD3` shift and `0xF7` unary groups.
+pub const SHL: u8 = 4;
+pub const SHR: u8 = 5;
+pub const SAR: u8 = 7;
+pub const NOT: u8 = 2;
+pub const NEG: u8 = 3;
+pub const DIV: u8 = 6;
+pub const IDIV: u8 = 7;
+
+/// Condition codes for `jcc`.
+pub const CC_B: u8 = 0x2;
+pub const CC_AE: u8 = 0x3;
+pub const CC_E: u8 = 0x4;
+pub const CC_NE: u8 = 0x5;
+pub const CC_BE: u8 = 0x6;
+pub const CC_A: u8 = 0x7;
+pub const CC_NS: u8 = 0x9;
+
+/// A position in the code, bound with `Asm::bind`.
+pub type Label = usize;
+
+/// A minimal x86-64 encoder. Memory operands are always `[base + disp32]`,
+/// which rules out `rsp` and `r12` as bases, and branches always take a
+/// 32-bit displacement.
+pub struct Asm {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    /// Positions of 32-bit displacements and the labels they refer to.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Default for Asm {
+    fn default() -> Self {
+        Asm::new()
+    }
+}
+
+impl Asm {
+    pub fn new() -> Self {
+        Asm {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    pub fn imm32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.imm32(0);
+    }
+
+    pub fn rex(&mut self, wide: bool, reg: u8, rm: u8) {
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | rm >> 3;
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    /// `op reg, rm` with both operands registers.
+    pub fn op_rr(&mut self, opcode: &[u8], wide: bool, reg: u8, rm: u8) {
+        self.rex(wide, reg, rm);
+        self.bytes(opcode);
+        self.code.push(0xC0 | (reg & 7) << 3 | rm & 7);
+    }
+
+    /// `op reg, [base + disp]`.
+    pub fn op_rm(&mut self, opcode: &[u8], wide: bool, reg: u8, base: u8, disp: i32) {
+        assert!(base & 7 != RSP, "rsp and r12 need a SIB byte");
+        self.rex(wide, reg, base);
+        self.bytes(opcode);
+        self.code.push(0x80 | (reg & 7) << 3 | base & 7);
+        self.imm32(disp as u32);
+    }
+
+    /// `mov dst, src`
+    pub fn mov(&mut self, wide: bool, dst: u8, src: u8) {
+        self.op_rr(&[0x89], wide, src, dst);
+    }
+
+    /// `mov dst, [base + disp]`
+    pub fn load(&mut self, wide: bool, dst: u8, base: u8, disp: i32) {
+        self.op_rm(&[0x8B], wide, dst, base, disp);
+    }
+
+    /// `mov [base + disp], src`
+    pub fn store(&mut self, base: u8, disp: i32, src: u8) {
+        self.op_rm(&[0x89], false, src, base, disp);
+    }
+
+    /// `movzx dst, byte [base + disp]`
+    pub fn load_byte(&mut self, dst: u8, base: u8, disp: i32) {
+        self.op_rm(&[0x0F, 0xB6], false, dst, base, disp);
+    }
+
+    /// `mov byte [base], src`, for `src` one of `al`, `cl`, `dl` or `bl`.
+    pub fn store_byte(&mut self, base: u8, src: u8) {
+        self.op_rm(&[0x88], false, src, base, 0);
+    }
+
+    /// `mov byte [base], value`
+    pub fn store_byte_imm(&mut self, base: u8, value: u8) {
+        self.op_rm(&[0xC6], false, 0, base, 0);
+        self.code.push(value);
+    }
+
+    /// `mov dword [base + disp], value`
+    pub fn store_imm(&mut self, base: u8, disp: i32, value: u32) {
+        self.op_rm(&[0xC7], false, 0, base, disp);
+        self.imm32(value);
+    }
+
+    /// `mov dst, value`, zero-extending into the full register.
+    pub fn mov_imm(&mut self, dst: u8, value: u32) {
+        self.rex(false, 0, dst);
+        self.code.push(0xB8 + (dst & 7));
+        self.imm32(value);
+    }
+
+    /// `lea dst, [base + disp]`
+    pub fn lea(&mut self, dst: u8, base: u8, disp: i32) {
+        self.op_rm(&[0x8D], true, dst, base, disp);
+    }
+
+    /// `lea dst, [rip + label]`
+    pub fn lea_label(&mut self, dst: u8, label: Label) {
+        self.rex(true, dst, 0);
+        self.bytes(&[0x8D, (dst & 7) << 3 | 0x05]);
+        self.rel32(label);
+    }
+
+    /// `op dst, src` for one of the ALU operations.
+    pub fn alu(&mut self, op: u8, wide: bool, dst: u8, src: u8) {
+        self.op_rr(&[op << 3 | 1], wide, src, dst);
+    }
+
+    /// `op dst, value`
+    pub fn alu_imm(&mut self, op: u8, wide: bool, dst: u8, value: u32) {
+        self.op_rr(&[0x81], wide, op, dst);
+        self.imm32(value);
+    }
+
+    /// `op [base + disp], value`
+    pub fn alu_mem_imm(&mut self, op: u8, wide: bool, base: u8, disp: i32, value: u32) {
+        self.op_rm(&[0x81], wide, op, base, disp);
+        self.imm32(value);
+    }
+
+    pub fn shift_cl(&mut self, ext: u8, dst: u8) {
+        self.op_rr(&[0xD3], false, ext, dst);
+    }
+
+    pub fn shift_imm(&mut self, ext: u8, wide: bool, dst: u8, count: u8) {
+        self.op_rr(&[0xC1], wide, ext, dst);
+        self.code.push(count);
+    }
+
+    /// Group 3 operation `ext` (`not`, `neg`, `div`, `idiv`) on `dst`.
+    pub fn unary(&mut self, ext: u8, wide: bool, dst: u8) {
+        self.op_rr(&[0xF7], wide, ext, dst);
+    }
+
+    /// `imul dst, src`
+    pub fn imul(&mut self, wide: bool, dst: u8, src: u8) {
+        self.op_rr(&[0x0F, 0xAF], wide, dst, src);
+    }
+
+    /// `test a, b`
+    pub fn test(&mut self, wide: bool, a: u8, b: u8) {
+        self.op_rr(&[0x85], wide, b, a);
+    }
+
+    /// `cmovcc dst, src`
+    pub fn cmov(&mut self, cc: u8, wide: bool, dst: u8, src: u8) {
+        self.op_rr(&[0x0F, 0x40 | cc], wide, dst, src);
+    }
+
+    /// `movsxd dst, src`
+    pub fn movsxd(&mut self, dst: u8, src: u8) {
+        self.op_rr(&[0x63], true, dst, src);
+    }
+
+    pub fn inc(&mut self, wide: bool, dst: u8) {
+        self.op_rr(&[0xFF], wide, 0, dst);
+    }
+
+    pub fn dec(&mut self, wide: bool, dst: u8) {
+        self.op_rr(&[0xFF], wide, 1, dst);
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push(0xE9);
+        self.rel32(label);
+    }
+
+    pub fn jcc(&mut self, cc: u8, label: Label) {
+        self.bytes(&[0x0F, 0x80 | cc]);
+        self.rel32(label);
+    }
+
+    pub fn call(&mut self, label: Label) {
+        self.code.push(0xE8);
+        self.rel32(label);
+    }
+
+    pub fn ret(&mut self) {
+        self.code.push(0xC3);
+    }
+
+    pub fn push(&mut self, reg: u8) {
+        self.rex(false, 0, reg);
+        self.code.push(0x50 + (reg & 7));
+    }
+
+    pub fn leave(&mut self) {
+        self.code.push(0xC9);
+    }
+
+    pub fn syscall(&mut self) {
+        self.bytes(&[0x0F, 0x05]);
+    }
+
+    /// Resolves every branch and returns the code.
+    pub fn finish(mut self) -> Vec<u8> {
+        for &(pos, label) in &self.fixups {
+            let target = self.labels[label].expect("unbound label");
+            let rel = target as i64 - (pos as i64 + 4);
+            self.code[pos..pos + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+        self.code
+    }
+}
+
+/// Appends the code for one IR instruction, keeping every variable and
+/// temporary in a 4-byte slot below `rbp`: variables first, then
+/// temporaries. Clobbers `rax`, `rcx` and `rdx`; `ret` leaves the result in
+/// `rax`, extended to 64 bits. A zero divisor jumps to `div_by_zero`.
+pub fn instruction(asm: &mut Asm, inst: &IrInst, vars: usize, div_by_zero: Label) {
+    let slot = |index: usize| -4 * (index as i32 + 1);
+    let temp = |temp: usize| slot(vars + temp);
+    match *inst {
+        IrInst::Const { dst, ref value, .. } => asm.store_imm(RBP, temp(dst), value.bits() as u32),
+        IrInst::Load { dst, var, .. } => {
+            asm.load(false, RAX, RBP, slot(var));
+            asm.store(RBP, temp(dst), RAX);
+        }
+        IrInst::Store { var, src, .. } => {
+            asm.load(false, RAX, RBP, temp(src));
+            asm.store(RBP, slot(var), RAX);
+        }
+        IrInst::Unary { dst, op, src, .. } => {
+            asm.load(false, RAX, RBP, temp(src));
+            asm.unary(if op == IrUnOp::Neg { NEG } else { NOT }, false, RAX);
+            asm.store(RBP, temp(dst), RAX);
+        }
+        IrInst::Binary { dst, ty, op, lhs, rhs } => {
+            asm.load(false, RAX, RBP, temp(lhs));
+            asm.load(false, RCX, RBP, temp(rhs));
+            match op {
+                IrBinOp::Add => asm.alu(ADD, false, RAX, RCX),
+                IrBinOp::Sub => asm.alu(SUB, false, RAX, RCX),
+                IrBinOp::Mul => asm.imul(false, RAX, RCX),
+                IrBinOp::And => asm.alu(AND, false, RAX, RCX),
+                IrBinOp::Or => asm.alu(OR, false, RAX, RCX),
+                IrBinOp::Xor => asm.alu(XOR, false, RAX, RCX),
+                // Shifts by `cl` take the count modulo 32, so one of 32 or
+                // more becomes 31 for `sar` and makes the others give 0.
+                IrBinOp::Shr if ty.signed => {
+                    asm.mov_imm(RDX, 31);
+                    asm.alu_imm(CMP, false, RCX, 32);
+                    asm.cmov(CC_AE, false, RCX, RDX);
+                    asm.shift_cl(SAR, RAX);
+                }
+                IrBinOp::Shl | IrBinOp::Shr => {
+                    asm.shift_cl(if op == IrBinOp::Shl { SHL } else { SHR }, RAX);
+                    asm.alu(XOR, false, RDX, RDX);
+                    asm.alu_imm(CMP, false, RCX, 32);
+                    asm.cmov(CC_AE, false, RAX, RDX);
+                }
+                IrBinOp::Div => {
+                    asm.test(false, RCX, RCX);
+                    asm.jcc(CC_E, div_by_zero);
+                    if ty.signed {
+                        // cdq
+                        asm.bytes(&[0x99]);
+                        asm.unary(IDIV, false, RCX);
+                    } else {
+                        asm.alu(XOR, false, RDX, RDX);
+                        asm.unary(DIV, false, RCX);
+                    }
+                }
+            }
+            asm.store(RBP, temp(dst), RAX);
+        }
+        IrInst::Ret { ty, src } => {
+            asm.load(false, RAX, RBP, temp(src));
+            if ty.width < 32 {
+                if ty.signed {
+                    asm.shift_imm(SHL, false, RAX, (32 - ty.width) as u8);
+                    asm.shift_imm(SAR, false, RAX, (32 - ty.width) as u8);
+                } else {
+                    asm.alu_imm(AND, false, RAX, (1u32 << ty.width) - 1);
+                }
+            }
+            if ty.signed {
+                asm.movsxd(RAX, RAX);
+            }
+        }
+    }
+}
@@ -1,8 +1,88 @@
+use word::MAX_WIDTH;
+
+/// Selects how binary operators are grouped by the parser.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grammar {
+    /// Legacy NTLang semantics: every binary operator has the same
+    /// precedence and expressions fold strictly left to right.
+    Flat,
+    /// C operator precedence and associativity.
+    Precedence,
+}
+
+/// Selects what `eval` does when `+`, `*`, `<<` or a binary or unary `-`
+/// produces a result outside the range of the width: the signed range when
+/// decimal results print as signed, otherwise the unsigned one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Keep the low bits of the result.
+    Wrap,
+    /// Fail with `EvalError::Overflow`.
+    Trap,
+    /// Clamp the result to the nearest end of the range.
+    Saturate,
+}
+
+/// Selects a code generator for `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    /// The three-address IR every backend is generated from.
+    Ir,
+    /// RV32IM assembly.
+    Riscv32,
+    /// RV64IM assembly.
+    Riscv64,
+    /// AArch64 assembly.
+    Aarch64,
+    /// x86-64 assembly for the System V ABI.
+    X86_64,
+    /// A C function.
+    C,
+    /// A C function-like macro.
+    CDefine,
+    /// Textual LLVM IR.
+    Llvm,
+    /// A WebAssembly module in the text format.
+    Wat,
+    /// A WebAssembly module in the binary format.
+    Wasm,
+    /// A static Linux x86-64 executable that prints the result.
+    Exe,
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub expression: String,
     pub base: u32,
+    /// Bits per value, 1 to `word::MAX_WIDTH`.
     pub width: u32,
+    /// Set when decimal results print as signed; `-u` clears it.
     pub unsigned_int: bool,
+    pub grammar: Grammar,
+    /// Only `eval` honours it; code generation, `verify` and the JIT accept
+    /// only `Overflow::Wrap`.
+    pub overflow: Overflow,
+    pub emit: Option<Emit>,
+    /// File to write `--emit` output to instead of stdout; executables
+    /// are made executable.
+    pub output: Option<String>,
+    /// Check generated code against `eval` instead of printing a result.
+    pub verify: bool,
+    /// Simplify the tree with `opt` before generating code.
+    pub optimize: bool,
+    /// Print what `opt` changes instead of a result.
+    pub explain_opt: bool,
+    /// Print the N/Z/C/V flags of each binary operation, then the result
+    /// and the final flags.
+    pub flags: bool,
+    /// Print each node's evaluation, in post-order, then the result.
+    pub trace: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
 }
 
 impl Config {
@@ -12,10 +92,21 @@ impl Config {
             base: 10,
             width: 32,
             unsigned_int: true,
+            grammar: Grammar::Precedence,
+            overflow: Overflow::Wrap,
+            emit: None,
+            output: None,
+            verify: false,
+            optimize: true,
+            explain_opt: false,
+            flags: false,
+            trace: false,
         }
     }
 
-    pub fn parse_args(args: Vec<String>) -> Config {
+    /// Builds a `Config` from command line arguments. An empty `expression`
+    /// means no `-e` was given.
+    pub fn parse_args(args: Vec<String>) -> Result<Config, String> {
         let mut config = Config::new();
         let mut i = 1;
         while i < args.len() {
@@ -25,8 +116,7 @@ impl Config {
                         config.expression = args[i + 1].clone();
                         i += 1;
                     } else {
-                        println!("Error: Missing argument for -e");
-                        return Config::new();
+                        return Err("Missing argument for -e".to_string());
                     }
                 }
                 "-b" => {
@@ -35,12 +125,10 @@ impl Config {
                             config.base = val;
                             i += 1;
                         } else {
-                            println!("Error: Invalid argument for -b");
-                            return Config::new();
+                            return Err("Invalid argument for -b".to_string());
                         }
                     } else {
-                        println!("Error: Missing argument for -b");
-                        return Config::new();
+                        return Err("Missing argument for -b".to_string());
                     }
                 }
                 "-u" => {
@@ -48,25 +136,96 @@ impl Config {
                 }
                 "-w" => {
                     if i + 1 < args.len() {
-                        if let Ok(val) = args[i + 1].parse::<u32>() {
+                        if let Some(val) = args[i + 1].parse::<u32>().ok().filter(|val| (1..=MAX_WIDTH).contains(val)) {
                             config.width = val;
                             i += 1;
                         } else {
-                            println!("Error: Invalid argument for -w");
-                            return Config::new();
+                            return Err("Invalid argument for -w".to_string());
                         }
                     } else {
-                        println!("Error: Missing argument for -w");
-                        return Config::new();
+                        return Err("Missing argument for -w".to_string());
+                    }
+                }
+                "-g" => {
+                    if i + 1 < args.len() {
+                        match args[i + 1].as_str() {
+                            "flat" => config.grammar = Grammar::Flat,
+                            "prec" => config.grammar = Grammar::Precedence,
+                            _ => {
+                                return Err("Invalid argument for -g".to_string());
+                            }
+                        }
+                        i += 1;
+                    } else {
+                        return Err("Missing argument for -g".to_string());
+                    }
+                }
+                "--overflow" => {
+                    if i + 1 < args.len() {
+                        config.overflow = match args[i + 1].as_str() {
+                            "wrap" => Overflow::Wrap,
+                            "trap" => Overflow::Trap,
+                            "saturate" => Overflow::Saturate,
+                            _ => return Err("Invalid argument for --overflow".to_string()),
+                        };
+                        i += 1;
+                    } else {
+                        return Err("Missing argument for --overflow".to_string());
+                    }
+                }
+                "--emit" => {
+                    if i + 1 < args.len() {
+                        config.emit = match args[i + 1].as_str() {
+                            "riscv" | "riscv32" => Some(Emit::Riscv32),
+                            "riscv64" => Some(Emit::Riscv64),
+                            "aarch64" | "arm64" => Some(Emit::Aarch64),
+                            "x86_64" | "x86-64" => Some(Emit::X86_64),
+                            "c" => Some(Emit::C),
+                            "c-define" => Some(Emit::CDefine),
+                            "ir" => Some(Emit::Ir),
+                            "llvm" => Some(Emit::Llvm),
+                            "wat" => Some(Emit::Wat),
+                            "wasm" => Some(Emit::Wasm),
+                            "exe" => Some(Emit::Exe),
+                            _ => return Err("Invalid argument for --emit".to_string()),
+                        };
+                        i += 1;
+                    } else {
+                        return Err("Missing argument for --emit".to_string());
                     }
                 }
+                "-o" => {
+                    if i + 1 < args.len() {
+                        config.output = Some(args[i + 1].clone());
+                        i += 1;
+                    } else {
+                        return Err("Missing argument for -o".to_string());
+                    }
+                }
+                "--verify" => {
+                    config.verify = true;
+                }
+                "-O0" => {
+                    config.optimize = false;
+                }
+                "-O" | "-O1" => {
+                    config.optimize = true;
+                }
+                "--explain-opt" => {
+                    config.explain_opt = true;
+                }
+                "--flags" => {
+                    config.flags = true;
+                }
+                "--trace" => {
+                    config.trace = true;
+                }
                 _ => {
-                    println!("Error: Unknown argument {}", args[i]);
-                    return Config::new();
+                    return Err(format!("Unknown argument {}", args[i]));
                 }
             }
             i += 1;
         }
-        config
+        Ok(config)
     }
 }
@@ -0,0 +1,65 @@
+use scan::Span;
+
+/// A user-facing error message tied to a range of the input expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: &str, span: Span) -> Self {
+        Diagnostic {
+            code,
+            message: message.to_string(),
+            span,
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: &str) -> Self {
+        self.hint = Some(hint.to_string());
+        self
+    }
+}
+
+/// Renders `diag` against the expression it was produced for, e.g.
+///
+/// ```text
+/// error[P001]: bad operand
+///  --> 1:5
+///   |
+/// 1 | 3 << + 1
+///   |      ^
+///   = hint: expected operand after `<<`
+/// ```
+pub fn render(source: &str, diag: &Diagnostic) -> String {
+    let start = diag.span.start;
+    let end = diag.span.end;
+    let line = source.lines().nth(start.line - 1).unwrap_or("");
+    let gutter = " ".repeat(start.line.to_string().len());
+
+    // Underline to the end of the span, or the end of the line if the span
+    // continues past it. Empty spans (such as end of input) get one caret.
+    let line_chars = line.chars().count();
+    let last_col = if end.line == start.line { end.col } else { line_chars + 1 };
+    let width = if last_col > start.col { last_col - start.col } else { 1 };
+
+    let mut out = String::new();
+    out.push_str(&format!("error[{}]: {}\n", diag.code, diag.message));
+    out.push_str(&format!("{}--> {}:{}\n", gutter, start.line, start.col));
+    out.push_str(&format!("{} |\n", gutter));
+    out.push_str(&format!("{} | {}\n", start.line, line));
+    out.push_str(&format!(
+        "{} | {}{}\n",
+        gutter,
+        " ".repeat(start.col - 1),
+        "^".repeat(width)
+    ));
+    if let Some(ref hint) = diag.hint {
+        out.push_str(&format!("{} = hint: {}\n", gutter, hint));
+    }
+    out
+}
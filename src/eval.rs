@@ -1,173 +1,420 @@
-use std::process;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use parse::{ParseOperator,ParseNodeType,ParseNode};
+use scan::Span;
+use word::Word;
+use config::Overflow;
+use opt::{format_expr, symbol};
 use Config;
+use diag::Diagnostic;
 
-pub fn eval_error(err: &str) {
-    println!("eval_error: {}", err);
-    process::exit(-1);
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The span is that of the divisor.
+    DivisionByZero(Span),
+    InvalidUnaryOperator(Span),
+    InvalidBinaryOperator(Span),
+    /// A variable was read before any assignment to it.
+    UndefinedVariable(String, Span),
+    /// Under `Overflow::Trap`, the subexpression, as source text, whose
+    /// result is out of range.
+    Overflow(String, Span),
 }
 
-pub fn eval(pt: &Option<&ParseNode>) -> u32 {
-    match pt {
-        Some(pt) => {
-            match pt.type_ {
-                ParseNodeType::Literal => {
-                    let a = pt.value;;
-                    let b = pt.value as u32;;
-                    pt.value as u32
-                },
-                ParseNodeType::Oper1 => {
-                    let v1 = eval(&pt.left.as_ref().map(|boxed| &**boxed));
-                    match pt.oper {
-                        ParseOperator::Plus => v1,
-                        ParseOperator::Minus => {
-                            -(v1 as i32) as u32
-                        },
-                        ParseOperator::BitNot => !v1,
-                        _ => {
-                            eval_error("Invalid unary operator");
-                            0
-                        }
-                    }
-                }
-                ParseNodeType::Oper2 => {
-                    let v1 = eval(&pt.left.as_ref().map(|boxed| &**boxed));
-                    let v2 = eval(&pt.right.as_ref().map(|boxed| &**boxed));
-                    match pt.oper {
-                        ParseOperator::Plus => v1 + v2,
-                        ParseOperator::Minus => v1 - v2,
-                        ParseOperator::Mult => v1 * v2,
-                        ParseOperator::Div => {
-                            if v2 == 0 {
-                                eval_error("Division by zero");
-                                0
-                            } else {
-                                v1 / v2
-                            }
-                        }
-                        ParseOperator::ShiftRight => v1 >> v2,
-                        ParseOperator::ShiftLeft => v1 << v2,
-                        ParseOperator::ArithShiftRight => {
-                            let c = ((v1 as i32) >> v2) as u32;
-                            ((v1 as i32) >> v2) as u32
-                        },
-                        ParseOperator::BitAnd => v1 & v2,
-                        ParseOperator::BitOr => v1 | v2,
-                        ParseOperator::BitXor => v1 ^ v2,
-                        _ => {
-                            eval_error("Invalid binary operator");
-                            0
-                        }
-                    }
-                }
-                ParseNodeType::None => 0,
+impl EvalError {
+    pub fn span(&self) -> Span {
+        match *self {
+            EvalError::DivisionByZero(span)
+            | EvalError::InvalidUnaryOperator(span)
+            | EvalError::InvalidBinaryOperator(span)
+            | EvalError::UndefinedVariable(_, span)
+            | EvalError::Overflow(_, span) => span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::new(self.code(), &self.to_string(), self.span());
+        match *self {
+            EvalError::DivisionByZero(_) => diag.with_hint("the right operand of `/` evaluates to 0"),
+            EvalError::UndefinedVariable(ref name, _) => {
+                diag.with_hint(&format!("assign it first, e.g. `{} = 0; ...`", name))
             }
+            EvalError::Overflow(..) => diag.with_hint("the exact result is outside the range of the width"),
+            _ => diag,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match *self {
+            EvalError::DivisionByZero(_) => "E001",
+            EvalError::InvalidUnaryOperator(_) => "E002",
+            EvalError::InvalidBinaryOperator(_) => "E003",
+            EvalError::UndefinedVariable(..) => "E004",
+            EvalError::Overflow(..) => "E005",
         }
-        None => 0,
     }
 }
 
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::DivisionByZero(_) => write!(f, "Division by zero"),
+            EvalError::InvalidUnaryOperator(_) => write!(f, "Invalid unary operator"),
+            EvalError::InvalidBinaryOperator(_) => write!(f, "Invalid binary operator"),
+            EvalError::UndefinedVariable(ref name, _) => write!(f, "Undefined variable: {}", name),
+            EvalError::Overflow(ref expr, _) => write!(f, "Overflow in {}", expr),
+        }
+    }
+}
 
-pub fn eval_print(cp: &Config, value: u32) {
-    let mut str = String::new();
-    let mut i = 0;
+impl Error for EvalError {}
 
-    let n_bit_value = mask_value(value, cp.width);
-    let sign = is_negative(n_bit_value, cp.width, cp.unsigned_int);
+/// The status flags an ARM ALU sets for a binary operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    /// The top bit of the result.
+    pub n: bool,
+    /// The result is zero.
+    pub z: bool,
+    /// The carry out of `+`; no borrow out of `-`; the last bit a shift
+    /// moves out.
+    pub c: bool,
+    /// Signed overflow of `+` or `-`.
+    pub v: bool,
+}
 
-    match cp.base {
-        10 => convert_to_decimal(n_bit_value, &mut str, &mut i, sign, cp.width),
-        2 => str = convert_to_binary(value, cp.width),
-        16 => str = convert_to_hexadecimal(value, cp.width),
-        _ => {}
+impl Flags {
+    /// The flags for `v1 oper v2` producing `value`. C and V are clear for
+    /// the operators whose ARM instructions do not set them, and C for a
+    /// shift by zero.
+    pub fn of(oper: &ParseOperator, v1: &Word, v2: &Word, value: &Word) -> Flags {
+        let width = v1.width();
+        let (c, v) = match *oper {
+            ParseOperator::Plus => {
+                let sum = v1.add(v2);
+                let carry = v1.resize(width + 1).add(&v2.resize(width + 1)).bit(width);
+                (carry, v1.is_negative() == v2.is_negative() && sum.is_negative() != v1.is_negative())
+            }
+            ParseOperator::Minus => {
+                let diff = v1.sub(v2);
+                let borrow = v1.resize(width + 1).sub(&v2.resize(width + 1)).bit(width);
+                (!borrow, v1.is_negative() != v2.is_negative() && diff.is_negative() != v1.is_negative())
+            }
+            // Shifting one bit wider keeps the last bit moved out, which is
+            // a shifted-in one when the count exceeds the width.
+            ParseOperator::ShiftLeft => (v1.resize(width + 1).shl(&v2.resize(width + 1)).bit(width), false),
+            ParseOperator::ShiftRight | ParseOperator::ArithShiftRight => {
+                let (wide, shift): (Word, fn(&Word, &Word) -> Word) = if *oper == ParseOperator::ShiftRight {
+                    (v1.resize(width + 1), Word::shr)
+                } else {
+                    (v1.sign_extend(width + 1), Word::sar)
+                };
+                let wide = wide.shl(&Word::new(1, width + 1));
+                (shift(&wide, &v2.resize(width + 1)).bit(0), false)
+            }
+            _ => (false, false),
+        };
+        Flags {
+            n: value.is_negative(),
+            z: value.is_zero(),
+            c,
+            v,
+        }
     }
-    println!("{}", str);
 }
 
-fn mask_value(value: u32, width: u32) -> u32 {
-    if width != 32 {
-        value & ((1 << width) - 1)
-    } else {
-        value
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "N={} Z={} C={} V={}", self.n as u8, self.z as u8, self.c as u8, self.v as u8)
     }
 }
 
-fn is_negative(n_bit_value: u32, width: u32, unsigned_int: bool) -> bool {
-    if unsigned_int {
-        n_bit_value & (1 << (width - 1)) != 0
-    } else {
-        false
-    }
+/// A node `eval` evaluated, recorded when `EvalEnvSt::steps` is set.
+pub struct EvalStepSt {
+    /// Nodes above this one in the tree.
+    pub depth: usize,
+    /// The operator, `name =` for an assignment, `;` for a sequence, the
+    /// name of a variable, or empty for a literal.
+    pub label: String,
+    pub expr: String,
+    /// The values of the children, left to right.
+    pub operands: Vec<Word>,
+    pub value: Word,
+    /// Set for binary operations only.
+    pub flags: Option<Flags>,
 }
 
-use std::mem;
+/// Variable bindings, kept across statements and, in the REPL, across lines,
+/// and the width and overflow policy every value is computed with.
+pub struct EvalEnvSt {
+    pub table: HashMap<String, Word>,
+    /// Bits per value, 1 to `word::MAX_WIDTH`.
+    pub width: u32,
+    pub overflow: Overflow,
+    /// Whether `overflow` checks the signed range rather than the unsigned.
+    pub signed: bool,
+    /// If set, each node is appended once evaluated, so in post-order.
+    pub steps: Option<Vec<EvalStepSt>>,
+}
 
-fn convert_to_decimal(mut n_bit_value: u32, str: &mut String, _i: &mut usize, sign: bool, width: u32) {
-    if sign {
-        if width != 32 {
-            let mask = (1 << width) - 1;
-            n_bit_value ^= mask;
-            n_bit_value += 1;
-        } else {
-            n_bit_value = !n_bit_value + 1;
-        }
+impl Default for EvalEnvSt {
+    fn default() -> Self {
+        EvalEnvSt::new()
     }
+}
 
-    if n_bit_value == 0 {
-        str.push('0');
-        return;
+impl EvalEnvSt {
+    pub fn new() -> Self {
+        EvalEnvSt {
+            table: HashMap::new(),
+            width: 32,
+            overflow: Overflow::Wrap,
+            signed: true,
+            steps: None,
+        }
     }
 
-    let mut digits = Vec::new(); // Store individual digits temporarily
-
-    while n_bit_value != 0 {
-        let remainder = n_bit_value % 10;
-        digits.push(std::char::from_digit(remainder, 10).unwrap()); // Push each digit as a character
-        n_bit_value /= 10;
+    /// Switches to the width and overflow policy selected in `cp`.
+    pub fn configure(&mut self, cp: &Config) {
+        self.width = cp.width;
+        self.overflow = cp.overflow;
+        self.signed = cp.unsigned_int;
     }
 
-    if sign {
-        digits.push('-');
+    /// The value of `name` at the current width; a value assigned at a
+    /// different width is truncated or zero-extended.
+    pub fn get(&self, name: &str) -> Option<Word> {
+        self.table.get(name).map(|value| value.resize(self.width))
     }
 
-    // Reverse the order of digits and collect them into the string
-    for digit in digits.into_iter().rev() {
-        str.push(digit);
+    pub fn set(&mut self, name: &str, value: Word) {
+        self.table.insert(name.to_string(), value);
     }
 }
 
-fn convert_to_binary(value: u32, width: u32) -> String {
-    let mut binary_str = String::new();
-    for j in 0..width {
-        let mask = 1 << j;
-        let bit = if (value & mask) != 0 { '1' } else { '0' };
-        binary_str.push(bit);
-    }
-    format!("0b{}", binary_str.chars().rev().collect::<String>())
+/// Evaluates `pt` at `env.width` bits, masking after every operation; `+`,
+/// `-`, `*` and `<<` follow `env.overflow`. A shift by the width or more
+/// moves every bit out: `<<` and `>>` give 0, and `>-` the sign bit in every
+/// position.
+pub fn eval(env: &mut EvalEnvSt, pt: &Option<&ParseNode>) -> Result<Word, EvalError> {
+    eval_at(env, pt, 0)
 }
 
+fn eval_at(env: &mut EvalEnvSt, pt: &Option<&ParseNode>, depth: usize) -> Result<Word, EvalError> {
+    let width = env.width;
+    match pt {
+        Some(pt) => {
+            let tracing = env.steps.is_some();
+            let mut operands = Vec::new();
+            let mut flags = None;
+            let value = match pt.type_ {
+                ParseNodeType::Literal => pt.value.resize(width),
+                ParseNodeType::Oper1 => {
+                    let v1 = eval_at(env, &pt.left.as_deref(), depth + 1)?;
+                    if tracing {
+                        operands.push(v1.clone());
+                    }
+                    match pt.oper {
+                        ParseOperator::Plus => v1,
+                        ParseOperator::Minus => arith(env, pt, Word::sub, &Word::zero(width), &v1)?,
+                        ParseOperator::BitNot => v1.not(),
+                        _ => return Err(EvalError::InvalidUnaryOperator(pt.span)),
+                    }
+                }
+                ParseNodeType::Oper2 => {
+                    let v1 = eval_at(env, &pt.left.as_deref(), depth + 1)?;
+                    let v2 = eval_at(env, &pt.right.as_deref(), depth + 1)?;
+                    let value = match pt.oper {
+                        ParseOperator::Plus => arith(env, pt, Word::add, &v1, &v2)?,
+                        ParseOperator::Minus => arith(env, pt, Word::sub, &v1, &v2)?,
+                        ParseOperator::Mult => arith(env, pt, Word::mul, &v1, &v2)?,
+                        ParseOperator::Div => match v1.checked_div(&v2) {
+                            Some(v) => v,
+                            None => {
+                                let divisor = pt.right.as_ref().unwrap();
+                                return Err(EvalError::DivisionByZero(divisor.span));
+                            }
+                        },
+                        ParseOperator::ShiftRight => v1.shr(&v2),
+                        ParseOperator::ShiftLeft => arith(env, pt, Word::shl, &v1, &v2)?,
+                        ParseOperator::ArithShiftRight => v1.sar(&v2),
+                        ParseOperator::BitAnd => v1.and(&v2),
+                        ParseOperator::BitOr => v1.or(&v2),
+                        ParseOperator::BitXor => v1.xor(&v2),
+                        _ => return Err(EvalError::InvalidBinaryOperator(pt.span)),
+                    };
+                    if tracing {
+                        flags = Some(Flags::of(&pt.oper, &v1, &v2, &value));
+                        operands = vec![v1, v2];
+                    }
+                    value
+                }
+                ParseNodeType::Ident => match env.get(&pt.name) {
+                    Some(value) => value,
+                    None => return Err(EvalError::UndefinedVariable(pt.name.clone(), pt.span)),
+                },
+                ParseNodeType::Assign => {
+                    let v1 = eval_at(env, &pt.left.as_deref(), depth + 1)?;
+                    env.set(&pt.name, v1.clone());
+                    if tracing {
+                        operands.push(v1.clone());
+                    }
+                    v1
+                }
+                ParseNodeType::Seq => {
+                    let mut value = Word::zero(width);
+                    for stmt in &pt.stmts {
+                        value = eval_at(env, &Some(stmt), depth + 1)?;
+                        if tracing {
+                            operands.push(value.clone());
+                        }
+                    }
+                    value
+                }
+                ParseNodeType::None => Word::zero(width),
+            };
+            if let Some(ref mut steps) = env.steps {
+                steps.push(EvalStepSt {
+                    depth,
+                    label: label(pt),
+                    expr: format_expr(pt),
+                    operands,
+                    value: value.clone(),
+                    flags,
+                });
+            }
+            Ok(value)
+        }
+        None => Ok(Word::zero(width)),
+    }
+}
 
+/// How a trace names `pt`; see `EvalStepSt::label`.
+fn label(pt: &ParseNode) -> String {
+    match pt.type_ {
+        ParseNodeType::Oper1 | ParseNodeType::Oper2 => symbol(&pt.oper).to_string(),
+        ParseNodeType::Assign => format!("{} =", pt.name),
+        ParseNodeType::Seq => ";".to_string(),
+        ParseNodeType::Ident => pt.name.clone(),
+        _ => String::new(),
+    }
+}
 
-fn convert_to_hexadecimal(value: u32, width: u32) -> String {
-    let mut hex_str = String::new();
-    let mut value = if width != 32 {
-        let mask = (1 << width) - 1;
-        value & mask
+/// Applies `op` to `v1` and `v2` under `env.overflow`. The exact result is
+/// computed at more than twice the width, which no product or shift of two
+/// operands exceeds, and then checked against the range.
+fn arith(
+    env: &EvalEnvSt,
+    pt: &ParseNode,
+    op: fn(&Word, &Word) -> Word,
+    v1: &Word,
+    v2: &Word,
+) -> Result<Word, EvalError> {
+    if env.overflow == Overflow::Wrap {
+        return Ok(op(v1, v2));
+    }
+    let wide = 2 * env.width + 2;
+    let extend = |v: &Word| if env.signed { v.sign_extend(wide) } else { v.resize(wide) };
+    // A shift count is not a value in the range, and never exceeds the width.
+    let rhs = if pt.oper == ParseOperator::ShiftLeft {
+        Word::new(u128::from(v2.as_shift()), wide)
     } else {
-        value
+        extend(v2)
     };
+    let exact = op(&extend(v1), &rhs);
+    let value = exact.resize(env.width);
+    if extend(&value) == exact {
+        return Ok(value);
+    }
+    match env.overflow {
+        Overflow::Wrap => Ok(value),
+        Overflow::Trap => Err(EvalError::Overflow(format_expr(pt), pt.span)),
+        Overflow::Saturate if exact.is_negative() => Ok(Word::min_value(env.width, env.signed)),
+        Overflow::Saturate => Ok(Word::max_value(env.width, env.signed)),
+    }
+}
 
-    for j in (0..width).step_by(4) {
-        let mask = 0xF << j;
-        let hex_digit = (value & mask) >> j;
+/// The steps and result of `trace`.
+pub struct EvalTraceSt {
+    pub steps: Vec<EvalStepSt>,
+    pub value: Word,
+    /// The flags of the last binary operation, or `None` if there was none.
+    pub flags: Option<Flags>,
+    base: Config,
+}
 
-        let digit_char = match hex_digit {
-            0..=9 => (hex_digit as u8 + b'0') as char,
-            10..=15 => (hex_digit as u8 - 10 + b'A') as char,
-            _ => panic!("Invalid hex digit"),
-        };
-        hex_str.push(digit_char);
+/// With `Config::trace`, one line per step: the label, the operands and
+/// the value, or just the value of a literal, indented by depth. Otherwise,
+/// with `Config::flags`, one line per binary operation with its source
+/// text. Flags follow when `Config::flags` is set.
+impl fmt::Display for EvalTraceSt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            let indent = 2 * step.depth;
+            if self.base.trace && step.label.is_empty() {
+                write!(f, "{:indent$}{}", "", eval_format(&self.base, &step.value), indent = indent)?;
+            } else if self.base.trace {
+                write!(f, "{:indent$}{}", "", step.label, indent = indent)?;
+                for operand in &step.operands {
+                    write!(f, " {}", eval_format(&self.base, operand))?;
+                }
+                write!(f, " -> {}", eval_format(&self.base, &step.value))?;
+            } else if step.flags.is_some() {
+                write!(f, "{:indent$}{}", "", step.expr, indent = indent)?;
+            } else {
+                continue;
+            }
+            match step.flags {
+                Some(flags) if self.base.flags => writeln!(f, "  {}", flags)?,
+                _ => writeln!(f)?,
+            }
+        }
+        writeln!(f, "result: {}", eval_format(&self.base, &self.value))?;
+        if self.base.flags {
+            match self.flags {
+                Some(flags) => writeln!(f, "flags: {}", flags)?,
+                None => writeln!(f, "flags: none")?,
+            }
+        }
+        Ok(())
     }
+}
+
+/// Evaluates `pt` with no variables defined, as configured by `cp`, and
+/// records each node's evaluation.
+pub fn trace(pt: &ParseNode, cp: &Config) -> Result<EvalTraceSt, EvalError> {
+    let mut env = EvalEnvSt::new();
+    env.configure(cp);
+    env.steps = Some(Vec::new());
+    let value = eval(&mut env, &Some(pt))?;
+    let steps = env.steps.unwrap_or_default();
+    Ok(EvalTraceSt {
+        flags: steps.iter().rev().find_map(|step| step.flags),
+        steps,
+        value,
+        base: cp.clone(),
+    })
+}
 
-    format!("0x{}", hex_str.chars().rev().collect::<String>())
-}
\ No newline at end of file
+pub fn eval_print(cp: &Config, value: &Word) {
+    println!("{}", eval_format(cp, value));
+}
+
+/// Formats `value` in the base, width and signedness selected by `cp`.
+/// Binary and hexadecimal show every digit of the width; decimal is signed
+/// when `cp.unsigned_int` is set.
+pub fn eval_format(cp: &Config, value: &Word) -> String {
+    let value = value.resize(cp.width);
+    match cp.base {
+        10 if cp.unsigned_int && value.is_negative() => format!("-{}", value.neg()),
+        10 => value.to_string(),
+        2 => format!("0b{:0>1$}", value.to_str_radix(2), cp.width as usize),
+        16 => format!(
+            "0x{:0>1$}",
+            value.to_str_radix(16).to_uppercase(),
+            cp.width.div_ceil(4) as usize
+        ),
+        _ => String::new(),
+    }
+}
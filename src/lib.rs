@@ -0,0 +1,150 @@
+//! NTLang: a small expression language over fixed-width integers.
+//!
+//! The pipeline is `scan` -> `parse` -> `eval`. `evaluate` runs all three
+//! stages; the individual stages are public for tools that need the token
+//! table or the parse tree. `compile` replaces `eval` with one of the code
+//! generators in `codegen`, which first simplifies the tree with `opt`.
+
+pub mod config;
+pub mod diag;
+pub mod scan;
+pub mod parse;
+pub mod word;
+pub mod eval;
+pub mod opt;
+pub mod codegen;
+pub mod repl;
+
+use std::fmt;
+
+pub use config::{Config, Emit, Grammar, Overflow};
+pub use diag::{render, Diagnostic};
+pub use scan::{ScanError, ScanTableSt, ScanToken, ScanTokenSt, SourcePos, Span};
+pub use parse::{ParseError, ParseNode, ParseNodeType, ParseOperator, ParseTableSt};
+pub use eval::{EvalEnvSt, EvalError, Flags};
+pub use word::Word;
+pub use codegen::CodegenError;
+
+/// An error from any stage of the pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Scan(ScanError),
+    Parse(ParseError),
+    Eval(EvalError),
+    Codegen(CodegenError),
+}
+
+impl Error {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match *self {
+            Error::Scan(ref err) => err.diagnostic(),
+            Error::Parse(ref err) => err.diagnostic(),
+            Error::Eval(ref err) => err.diagnostic(),
+            Error::Codegen(ref err) => err.diagnostic(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Scan(ref err) => err.fmt(f),
+            Error::Parse(ref err) => err.fmt(f),
+            Error::Eval(ref err) => err.fmt(f),
+            Error::Codegen(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ScanError> for Error {
+    fn from(err: ScanError) -> Self {
+        Error::Scan(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(err: EvalError) -> Self {
+        Error::Eval(err)
+    }
+}
+
+impl From<CodegenError> for Error {
+    fn from(err: CodegenError) -> Self {
+        Error::Codegen(err)
+    }
+}
+
+/// Scans `expr` into a token table.
+pub fn tokenize(expr: &str) -> Result<ScanTableSt, Error> {
+    let mut scan_table = ScanTableSt::new();
+    scan_table.scan(expr)?;
+    Ok(scan_table)
+}
+
+/// Scans and parses `expr` with the grammar selected in `cp`.
+pub fn parse(expr: &str, cp: &Config) -> Result<ParseNode, Error> {
+    let mut scan_table = tokenize(expr)?;
+    let mut parse_table = ParseTableSt::new();
+    parse_table.grammar = cp.grammar;
+    parse_table.width = cp.width;
+    Ok(parse_table.parse_program(&mut scan_table)?)
+}
+
+/// Scans, parses and evaluates `expr` with no variables defined. Use
+/// `eval::eval_format` to render the result the way the command line tool does.
+pub fn evaluate(expr: &str, cp: &Config) -> Result<Word, Error> {
+    evaluate_in(expr, cp, &mut EvalEnvSt::new())
+}
+
+/// Like `evaluate`, but reads and assigns variables in `env`, which is
+/// switched to `cp.width` and `cp.overflow`.
+pub fn evaluate_in(expr: &str, cp: &Config, env: &mut EvalEnvSt) -> Result<Word, Error> {
+    let parse_node = parse(expr, cp)?;
+    env.configure(cp);
+    Ok(eval::eval(env, &Some(&parse_node))?)
+}
+
+/// Scans, parses and evaluates `expr` like `evaluate`, recording each node's
+/// evaluation and the status flags of each binary operation; see
+/// `eval::trace`.
+pub fn trace(expr: &str, cp: &Config) -> Result<eval::EvalTraceSt, Error> {
+    let parse_node = parse(expr, cp)?;
+    Ok(eval::trace(&parse_node, cp)?)
+}
+
+/// Scans and parses `expr`, then generates code for it with the backend
+/// selected by `cp.emit`. The code is text except for `Emit::Wasm` and
+/// `Emit::Exe`.
+pub fn compile(expr: &str, cp: &Config) -> Result<Vec<u8>, Error> {
+    let parse_node = parse(expr, cp)?;
+    Ok(codegen::emit(&parse_node, cp)?)
+}
+
+/// Scans and parses `expr`, then compiles it for repeated evaluation; see
+/// `codegen::jit`.
+pub fn jit(expr: &str, cp: &Config) -> Result<codegen::jit::JitFunction, Error> {
+    let parse_node = parse(expr, cp)?;
+    Ok(codegen::jit::compile(&parse_node, cp)?)
+}
+
+/// Scans and parses `expr`, then simplifies it as `compile` would and reports
+/// each rewrite; see `opt::explain`.
+pub fn explain_opt(expr: &str, cp: &Config) -> Result<opt::OptReportSt, Error> {
+    let parse_node = parse(expr, cp)?;
+    Ok(opt::explain(&parse_node, cp.width))
+}
+
+/// Scans and parses `expr`, then runs the generated code in an emulator and
+/// compares it with `eval`; see `codegen::verify`.
+pub fn verify(expr: &str, cp: &Config) -> Result<codegen::verify::VerifyReportSt, Error> {
+    let parse_node = parse(expr, cp)?;
+    Ok(codegen::verify::verify(&parse_node, cp)?)
+}
@@ -1,26 +1,114 @@
-mod config;
-mod scan;
-mod parse;
-mod eval;
+extern crate ntlang;
+
 use std::env;
-use config::Config;
-use scan::ScanTableSt;
-use parse::{ParseTableSt};
-use eval::{eval, eval_print};
-fn main() {
+use std::fs;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::process;
+use ntlang::{Config, Emit, Error};
+use ntlang::diag::render;
+use ntlang::eval::eval_print;
+
+// Exit codes, one per pipeline stage.
+const EXIT_USAGE_ERROR: i32 = 1;
+const EXIT_SCAN_ERROR: i32 = 2;
+const EXIT_PARSE_ERROR: i32 = 3;
+const EXIT_EVAL_ERROR: i32 = 4;
+const EXIT_CODEGEN_ERROR: i32 = 5;
+const EXIT_VERIFY_MISMATCH: i32 = 6;
+
+/// Writes generated code to the `-o` file, or else to stdout unless it is
+/// binary and stdout is a terminal.
+fn write_code(config: &Config, code: &[u8]) -> io::Result<()> {
+    let binary = config.emit == Some(Emit::Wasm) || config.emit == Some(Emit::Exe);
+    match config.output {
+        Some(ref path) => {
+            fs::write(path, code)?;
+            if config.emit == Some(Emit::Exe) {
+                set_executable(path)?;
+            }
+            Ok(())
+        }
+        None if binary && io::stdout().is_terminal() => Err(io::Error::other(
+            "refusing to write binary output to a terminal; use -o <path>",
+        )),
+        None => io::stdout().write_all(code),
+    }
+}
 
-    let config = Config::parse_args(env::args().collect());
+#[cfg(unix)]
+fn set_executable(path: &str) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
 
-    let mut scan_table = ScanTableSt::new();
-    scan_table.scan(&config.expression);
+#[cfg(not(unix))]
+fn set_executable(_path: &str) -> io::Result<()> {
+    Ok(())
+}
 
-    let mut parse_table = ParseTableSt::new();
-    let mut parse_node = parse_table.parse_program(&mut scan_table);
+fn main() {
 
-    let value = eval(&parse_node.as_ref());
-    eval_print(&config,value);
+    let mut config = match Config::parse_args(env::args().collect()) {
+        Ok(config) => config,
+        Err(msg) => {
+            println!("Error: {}", msg);
+            process::exit(EXIT_USAGE_ERROR);
+        }
+    };
 
+    if config.output.is_some() && config.emit.is_none() {
+        println!("Error: -o requires --emit");
+        process::exit(EXIT_USAGE_ERROR);
+    }
 
-}
+    // Without -e, read expressions interactively.
+    if config.expression.is_empty() {
+        if config.emit.is_some() || config.verify || config.explain_opt || config.flags || config.trace {
+            println!("Error: --emit, --verify, --explain-opt, --flags and --trace require -e");
+            process::exit(EXIT_USAGE_ERROR);
+        }
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        if let Err(err) = ntlang::repl::run(&mut config, stdin.lock(), &mut stdout.lock()) {
+            println!("Error: {}", err);
+            process::exit(EXIT_USAGE_ERROR);
+        }
+        return;
+    }
 
+    let result = if config.explain_opt {
+        ntlang::explain_opt(&config.expression, &config).map(|report| print!("{}", report))
+    } else if config.verify {
+        ntlang::verify(&config.expression, &config).map(|report| {
+            print!("{}", report);
+            if !report.ok() {
+                process::exit(EXIT_VERIFY_MISMATCH);
+            }
+        })
+    } else if config.emit.is_some() {
+        ntlang::compile(&config.expression, &config).map(|code| {
+            if let Err(err) = write_code(&config, &code) {
+                println!("Error: {}", err);
+                process::exit(EXIT_USAGE_ERROR);
+            }
+        })
+    } else if config.flags || config.trace {
+        ntlang::trace(&config.expression, &config).map(|report| print!("{}", report))
+    } else {
+        ntlang::evaluate(&config.expression, &config).map(|value| eval_print(&config, &value))
+    };
 
+    match result {
+        Ok(()) => {}
+        Err(err) => {
+            print!("{}", render(&config.expression, &err.diagnostic()));
+            process::exit(match err {
+                Error::Scan(_) => EXIT_SCAN_ERROR,
+                Error::Parse(_) => EXIT_PARSE_ERROR,
+                Error::Eval(_) => EXIT_EVAL_ERROR,
+                Error::Codegen(_) => EXIT_CODEGEN_ERROR,
+            });
+        }
+    }
+}
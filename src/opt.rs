@@ -0,0 +1,338 @@
+//! Constant folding and algebraic simplification over the parse tree.
+//!
+//! `optimize` rewrites a tree bottom-up until no rule applies. Constant
+//! subtrees are folded with `eval` itself, so folding cannot drift from the
+//! evaluator; a constant division by zero is left alone so that it still
+//! traps. An identity that drops an operand, such as `x * 0 = 0`, only fires
+//! when the dropped operand has no effect: no assignment and no division by
+//! anything but a nonzero literal. Variable reads count as effect-free, as
+//! generated code takes free variables as parameters.
+//!
+//! Rewrites hold at one width, the one the program is evaluated at: folding
+//! and identities such as `x & ~0 = x` depend on it.
+
+use std::fmt;
+use eval::{eval, EvalEnvSt};
+use parse::{ParseNode, ParseNodeType, ParseOperator};
+use scan::Span;
+use word::Word;
+
+/// One rewrite: the subtree at `span` went from `before` to `after`.
+pub struct OptStepSt {
+    pub span: Span,
+    /// The identity applied, e.g. `x * 2^k = x << k`.
+    pub rule: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+/// What `explain` found for a whole program.
+pub struct OptReportSt {
+    pub before: String,
+    pub after: String,
+    /// Node counts before and after.
+    pub nodes: (usize, usize),
+    pub steps: Vec<OptStepSt>,
+}
+
+impl fmt::Display for OptReportSt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.steps.is_empty() {
+            writeln!(f, "no simplifications")?;
+        }
+        for step in &self.steps {
+            writeln!(
+                f,
+                "{}:{}: {}  =>  {}  [{}]",
+                step.span.start.line, step.span.start.col, step.before, step.after, step.rule
+            )?;
+        }
+        writeln!(f, "result: {}", self.after)?;
+        writeln!(f, "nodes: {} -> {}", self.nodes.0, self.nodes.1)
+    }
+}
+
+/// Returns the tree simplified for evaluation at `width` bits and the
+/// rewrites that produced it, innermost first.
+pub fn optimize(node: &ParseNode, width: u32) -> (ParseNode, Vec<OptStepSt>) {
+    let mut steps = Vec::new();
+    let node = simplify(node.clone(), width, &mut steps);
+    (node, steps)
+}
+
+/// Optimizes `node` for `width` bits and reports every rewrite.
+pub fn explain(node: &ParseNode, width: u32) -> OptReportSt {
+    let (optimized, steps) = optimize(node, width);
+    OptReportSt {
+        before: format_expr(node),
+        after: format_expr(&optimized),
+        nodes: (size(node), size(&optimized)),
+        steps,
+    }
+}
+
+/// Renders `node` as source text, parenthesising every compound operand.
+pub fn format_expr(node: &ParseNode) -> String {
+    match node.type_ {
+        ParseNodeType::Literal => {
+            let value = &node.value;
+            if value.significant_bits() <= 16 {
+                value.to_string()
+            } else {
+                format!("0x{}", value.to_str_radix(16).to_uppercase())
+            }
+        }
+        ParseNodeType::Ident => node.name.clone(),
+        ParseNodeType::Oper1 => {
+            let x = node.left.as_ref().unwrap();
+            if x.type_ == ParseNodeType::Oper1 {
+                format!("{}({})", symbol(&node.oper), format_expr(x))
+            } else {
+                format!("{}{}", symbol(&node.oper), operand(x))
+            }
+        }
+        ParseNodeType::Oper2 => format!(
+            "{} {} {}",
+            operand(node.left.as_ref().unwrap()),
+            symbol(&node.oper),
+            operand(node.right.as_ref().unwrap())
+        ),
+        ParseNodeType::Assign => format!("{} = {}", node.name, format_expr(node.left.as_ref().unwrap())),
+        ParseNodeType::Seq => {
+            let stmts: Vec<String> = node.stmts.iter().map(format_expr).collect();
+            stmts.join("; ")
+        }
+        ParseNodeType::None => String::new(),
+    }
+}
+
+fn operand(node: &ParseNode) -> String {
+    match node.type_ {
+        ParseNodeType::Oper2 | ParseNodeType::Assign | ParseNodeType::Seq => format!("({})", format_expr(node)),
+        _ => format_expr(node),
+    }
+}
+
+/// The source spelling of `oper`.
+pub fn symbol(oper: &ParseOperator) -> &'static str {
+    match *oper {
+        ParseOperator::Plus => "+",
+        ParseOperator::Minus => "-",
+        ParseOperator::Mult => "*",
+        ParseOperator::Div => "/",
+        ParseOperator::ShiftRight => ">>",
+        ParseOperator::ShiftLeft => "<<",
+        ParseOperator::ArithShiftRight => ">-",
+        ParseOperator::BitAnd => "&",
+        ParseOperator::BitOr => "|",
+        ParseOperator::BitXor => "^",
+        ParseOperator::BitNot => "~",
+    }
+}
+
+fn size(node: &ParseNode) -> usize {
+    1 + node.left.as_ref().map_or(0, |left| size(left))
+        + node.right.as_ref().map_or(0, |right| size(right))
+        + node.stmts.iter().map(size).sum::<usize>()
+}
+
+fn simplify(mut node: ParseNode, width: u32, steps: &mut Vec<OptStepSt>) -> ParseNode {
+    node.left = node.left.take().map(|left| Box::new(simplify(*left, width, steps)));
+    node.right = node.right.take().map(|right| Box::new(simplify(*right, width, steps)));
+    node.stmts = node.stmts.drain(..).map(|stmt| simplify(stmt, width, steps)).collect();
+    while let Some((rule, next)) = rewrite(&node, width) {
+        steps.push(OptStepSt {
+            span: node.span,
+            rule,
+            before: format_expr(&node),
+            after: format_expr(&next),
+        });
+        node = next;
+    }
+    node
+}
+
+fn literal(value: Word, span: Span) -> ParseNode {
+    ParseNode {
+        type_: ParseNodeType::Literal,
+        value,
+        name: String::new(),
+        oper: ParseOperator::Plus,
+        left: None,
+        right: None,
+        stmts: Vec::new(),
+        span,
+    }
+}
+
+fn unary(oper: ParseOperator, operand: &ParseNode, span: Span) -> ParseNode {
+    ParseNode {
+        type_: ParseNodeType::Oper1,
+        value: Word::zero(32),
+        name: String::new(),
+        oper,
+        left: Some(Box::new(operand.clone())),
+        right: None,
+        stmts: Vec::new(),
+        span,
+    }
+}
+
+fn binary(oper: ParseOperator, left: &ParseNode, right: ParseNode, span: Span) -> ParseNode {
+    ParseNode {
+        type_: ParseNodeType::Oper2,
+        value: Word::zero(32),
+        name: String::new(),
+        oper,
+        left: Some(Box::new(left.clone())),
+        right: Some(Box::new(right)),
+        stmts: Vec::new(),
+        span,
+    }
+}
+
+/// The value of a literal at `width` bits.
+fn constant(node: &ParseNode, width: u32) -> Option<Word> {
+    match node.type_ {
+        ParseNodeType::Literal => Some(node.value.resize(width)),
+        _ => None,
+    }
+}
+
+/// Whether evaluating `node` at `width` bits can neither assign nor trap.
+fn pure(node: &ParseNode, width: u32) -> bool {
+    let children = node.left.as_ref().is_none_or(|left| pure(left, width))
+        && node.right.as_ref().is_none_or(|right| pure(right, width))
+        && node.stmts.iter().all(|stmt| pure(stmt, width));
+    match node.type_ {
+        ParseNodeType::Assign => false,
+        ParseNodeType::Oper2 if node.oper == ParseOperator::Div => {
+            children && constant(node.right.as_ref().unwrap(), width).is_some_and(|divisor| !divisor.is_zero())
+        }
+        _ => children,
+    }
+}
+
+/// Structural equality, ignoring spans.
+fn same(a: &ParseNode, b: &ParseNode) -> bool {
+    fn same_child(a: &Option<Box<ParseNode>>, b: &Option<Box<ParseNode>>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => same(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+    a.type_ == b.type_
+        && a.value == b.value
+        && a.name == b.name
+        && ((a.type_ != ParseNodeType::Oper1 && a.type_ != ParseNodeType::Oper2) || a.oper == b.oper)
+        && same_child(&a.left, &b.left)
+        && same_child(&a.right, &b.right)
+        && a.stmts.len() == b.stmts.len()
+        && a.stmts.iter().zip(&b.stmts).all(|(a, b)| same(a, b))
+}
+
+/// The exponent `k` if `value` is `2^k` with `k >= 1`, at the width of
+/// `value`.
+fn log2(value: &Option<Word>) -> Option<Word> {
+    match *value {
+        Some(ref value) if value.is_power_of_two() && value.trailing_zeros() >= 1 => {
+            Some(Word::new(u128::from(value.trailing_zeros()), value.width()))
+        }
+        _ => None,
+    }
+}
+
+/// Applies the first rule that matches at the root of `node`, whose
+/// children are already simplified.
+fn rewrite(node: &ParseNode, width: u32) -> Option<(&'static str, ParseNode)> {
+    let span = node.span;
+    match node.type_ {
+        ParseNodeType::Oper1 => {
+            let x = node.left.as_ref().unwrap();
+            if constant(x, width).is_some() {
+                return fold(node, width);
+            }
+            let nested = x.type_ == ParseNodeType::Oper1 && x.oper == node.oper;
+            match node.oper {
+                ParseOperator::Plus => Some(("+x = x", (**x).clone())),
+                ParseOperator::Minus if nested => Some(("-(-x) = x", (**x.left.as_ref().unwrap()).clone())),
+                ParseOperator::BitNot if nested => Some(("~~x = x", (**x.left.as_ref().unwrap()).clone())),
+                _ => None,
+            }
+        }
+        ParseNodeType::Oper2 => {
+            let x = node.left.as_ref().unwrap();
+            let y = node.right.as_ref().unwrap();
+            let (a, b) = (constant(x, width), constant(y, width));
+            if a.is_some() && b.is_some() {
+                return fold(node, width);
+            }
+            let keep_x = Some((**x).clone());
+            let keep_y = Some((**y).clone());
+            let zero = Some(literal(Word::zero(width), span));
+            let ones = Some(Word::ones(width));
+            let twin = same(x, y) && pure(x, width);
+            let (is_0, is_1) = (Some(Word::zero(width)), Some(Word::new(1, width)));
+            let (a_0, b_0, a_1, b_1) = (a == is_0, b == is_0, a == is_1, b == is_1);
+            let (log_a, log_b) = (log2(&a), log2(&b));
+            let (rule, next) = match node.oper {
+                ParseOperator::Plus if b_0 => ("x + 0 = x", keep_x),
+                ParseOperator::Plus if a_0 => ("0 + x = x", keep_y),
+                ParseOperator::Minus if b_0 => ("x - 0 = x", keep_x),
+                ParseOperator::Minus if twin => ("x - x = 0", zero),
+                ParseOperator::Minus if a_0 => ("0 - x = -x", Some(unary(ParseOperator::Minus, y, span))),
+                ParseOperator::Mult if b_1 => ("x * 1 = x", keep_x),
+                ParseOperator::Mult if a_1 => ("1 * x = x", keep_y),
+                ParseOperator::Mult if b_0 && pure(x, width) => ("x * 0 = 0", zero),
+                ParseOperator::Mult if a_0 && pure(y, width) => ("0 * x = 0", zero),
+                ParseOperator::Mult if log_b.is_some() => (
+                    "x * 2^k = x << k",
+                    Some(binary(ParseOperator::ShiftLeft, x, literal(log_b.clone().unwrap(), y.span), span)),
+                ),
+                ParseOperator::Mult if log_a.is_some() => (
+                    "2^k * x = x << k",
+                    Some(binary(ParseOperator::ShiftLeft, y, literal(log_a.clone().unwrap(), x.span), span)),
+                ),
+                ParseOperator::Div if b_1 => ("x / 1 = x", keep_x),
+                ParseOperator::Div if log_b.is_some() => (
+                    "x / 2^k = x >> k",
+                    Some(binary(ParseOperator::ShiftRight, x, literal(log_b.clone().unwrap(), y.span), span)),
+                ),
+                ParseOperator::ShiftLeft if b_0 => ("x << 0 = x", keep_x),
+                ParseOperator::ShiftRight if b_0 => ("x >> 0 = x", keep_x),
+                ParseOperator::ArithShiftRight if b_0 => ("x >- 0 = x", keep_x),
+                ParseOperator::ShiftLeft if a_0 && pure(y, width) => ("0 << x = 0", zero),
+                ParseOperator::ShiftRight if a_0 && pure(y, width) => ("0 >> x = 0", zero),
+                ParseOperator::ArithShiftRight if a_0 && pure(y, width) => ("0 >- x = 0", zero),
+                ParseOperator::BitAnd if b == ones => ("x & ~0 = x", keep_x),
+                ParseOperator::BitAnd if a == ones => ("~0 & x = x", keep_y),
+                ParseOperator::BitAnd if b_0 && pure(x, width) => ("x & 0 = 0", zero),
+                ParseOperator::BitAnd if a_0 && pure(y, width) => ("0 & x = 0", zero),
+                ParseOperator::BitAnd if twin => ("x & x = x", keep_x),
+                ParseOperator::BitOr if b_0 => ("x | 0 = x", keep_x),
+                ParseOperator::BitOr if a_0 => ("0 | x = x", keep_y),
+                ParseOperator::BitOr if b == ones && pure(x, width) => ("x | ~0 = ~0", Some(literal(Word::ones(width), span))),
+                ParseOperator::BitOr if a == ones && pure(y, width) => ("~0 | x = ~0", Some(literal(Word::ones(width), span))),
+                ParseOperator::BitOr if twin => ("x | x = x", keep_x),
+                ParseOperator::BitXor if b_0 => ("x ^ 0 = x", keep_x),
+                ParseOperator::BitXor if a_0 => ("0 ^ x = x", keep_y),
+                ParseOperator::BitXor if twin => ("x ^ x = 0", zero),
+                ParseOperator::BitXor if b == ones => ("x ^ ~0 = ~x", Some(unary(ParseOperator::BitNot, x, span))),
+                ParseOperator::BitXor if a == ones => ("~0 ^ x = ~x", Some(unary(ParseOperator::BitNot, y, span))),
+                _ => ("", None),
+            };
+            next.map(|next| (rule, next))
+        }
+        _ => None,
+    }
+}
+
+/// Replaces an operator applied to literals with its value, unless
+/// evaluating it fails.
+fn fold(node: &ParseNode, width: u32) -> Option<(&'static str, ParseNode)> {
+    let mut env = EvalEnvSt::new();
+    env.width = width;
+    let value = eval(&mut env, &Some(node)).ok()?;
+    Some(("fold constants", literal(value, node.span)))
+}
@@ -1,7 +1,11 @@
-use std::process;
-use scan::{ScanTableSt, ScanToken};
+use std::error::Error;
+use std::fmt;
+use scan::{ScanTableSt, ScanToken, Span};
+use config::Grammar;
+use diag::Diagnostic;
+use word::Word;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParseOperator {
     Plus,
     Minus,
@@ -16,120 +20,254 @@ pub enum ParseOperator {
     BitNot,
 }
 
-#[derive(Debug,Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParseNodeType{
     Literal,
     Oper1,
     Oper2,
+    /// A variable reference; the variable is `name`.
+    Ident,
+    /// `name = left`; evaluates to the assigned value.
+    Assign,
+    /// `stmts` evaluated in order, yielding the last one's value.
+    Seq,
     None
 }
 #[derive(Debug,Clone)]
 pub struct ParseNode {
-    pub(crate) type_: ParseNodeType,
-    pub(crate) value: i32,
-    pub(crate) oper : ParseOperator,
-    pub(crate) left: Option<Box<ParseNode>>,
-    pub(crate) right: Option<Box<ParseNode>>,
-}
-impl ParseNode {
-    fn new(value: i32) -> Self {
-        ParseNode {
-            type_: ParseNodeType::Literal,
-            value,
-            oper: ParseOperator::Plus,
-            left: None,
-            right: None,
+    pub type_: ParseNodeType,
+    /// A literal's value, at the width it was parsed with.
+    pub value: Word,
+    pub name: String,
+    pub oper: ParseOperator,
+    pub left: Option<Box<ParseNode>>,
+    pub right: Option<Box<ParseNode>>,
+    /// The statements of a `Seq`. They are kept in a list rather than a
+    /// chain of nodes so that long programs do not nest deeply.
+    pub stmts: Vec<ParseNode>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// No operand where one was required; `after` is the preceding token.
+    BadOperand { span: Span, after: Option<String> },
+    /// A `(` opened at `lparen` was never closed.
+    ExpectingRParen { span: Span, lparen: Span },
+    /// Input continues after a complete expression.
+    ExpectingEot { span: Span, found: String },
+    /// A literal needs more than `width` bits.
+    LiteralOverflow { span: Span, literal: String, width: u32 },
+    /// A `0x` or `0b` prefix with no digits after it.
+    MalformedLiteral { span: Span, literal: String },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match *self {
+            ParseError::BadOperand { span, .. }
+            | ParseError::ExpectingRParen { span, .. }
+            | ParseError::ExpectingEot { span, .. }
+            | ParseError::LiteralOverflow { span, .. }
+            | ParseError::MalformedLiteral { span, .. } => span,
         }
     }
 
+    pub fn diagnostic(&self) -> Diagnostic {
+        let (code, hint) = match *self {
+            ParseError::BadOperand { after: Some(ref prev), .. } => {
+                ("P001", format!("expected operand after `{}`", prev))
+            }
+            ParseError::BadOperand { after: None, .. } => {
+                ("P001", "expected a literal, a variable, `(`, `-` or `~`".to_string())
+            }
+            ParseError::ExpectingRParen { lparen, .. } => (
+                "P002",
+                format!("add `)` to close the `(` at {}:{}", lparen.start.line, lparen.start.col),
+            ),
+            ParseError::ExpectingEot { ref found, .. } => {
+                ("P003", format!("`{}` cannot follow a complete expression", found))
+            }
+            ParseError::LiteralOverflow { width, .. } => ("P004", format!("literals must fit in {} bits", width)),
+            ParseError::MalformedLiteral { ref literal, .. } => {
+                ("P005", format!("add at least one digit after `{}`", literal))
+            }
+        };
+        Diagnostic::new(code, &self.to_string(), self.span()).with_hint(&hint)
+    }
+}
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::BadOperand { .. } => write!(f, "Bad operand"),
+            ParseError::ExpectingRParen { .. } => write!(f, "Expecting ')'"),
+            ParseError::ExpectingEot { .. } => write!(f, "Expecting EOT"),
+            ParseError::LiteralOverflow { ref literal, width, .. } => {
+                write!(f, "overflows {} bits: {}", width, literal)
+            }
+            ParseError::MalformedLiteral { ref literal, .. } => write!(f, "malformed literal: {}", literal),
+        }
+    }
 }
 
+impl Error for ParseError {}
+
 pub struct ParseTableSt {
     pub table: Vec<ParseNode>,
     pub len: usize,
+    pub grammar: Grammar,
+    /// The evaluation width. A literal may use all of it but no more, so a
+    /// value is never silently truncated.
+    pub width: u32,
+}
+
+impl Default for ParseTableSt {
+    fn default() -> Self {
+        ParseTableSt::new()
+    }
 }
 
 impl ParseTableSt {
     pub fn new() -> Self {
         ParseTableSt {
             table: Vec::new(),
-            len: 0
+            len: 0,
+            grammar: Grammar::Precedence,
+            width: 32,
         }
     }
 
     pub fn parse_node_new(&mut self) -> ParseNode {
         let node = ParseNode {
             type_: ParseNodeType::None,
-            value: 0,
+            value: Word::zero(32),
+            name: String::new(),
             oper: ParseOperator::Plus,
             left: None,
             right: None,
+            stmts: Vec::new(),
+            span: Span::default(),
         };
         self.table.push(node.clone());
         self.len += 1;
         node
     }
 
-    pub fn parse_program(&mut self, st: &mut ScanTableSt) -> Option<ParseNode> {
-        let np1 = self.parse_expression(st)?;
+    /// Parses `statement (';' statement)* [';'] EOT`. A program with a single
+    /// statement yields that statement's node; longer programs yield a `Seq`
+    /// node holding the statements.
+    pub fn parse_program(&mut self, st: &mut ScanTableSt) -> Result<ParseNode, ParseError> {
+        let mut stmts = vec![self.parse_statement(st)?];
+
+        while st.accept(ScanToken::Semi) {
+            if st.get(0).unwrap().id == ScanToken::EOT {
+                break;
+            }
+            stmts.push(self.parse_statement(st)?);
+        }
+
+        let np1 = if stmts.len() == 1 {
+            stmts.pop().unwrap()
+        } else {
+            let mut np1 = self.parse_node_new();
+
+            np1.type_ = ParseNodeType::Seq;
+            np1.span = stmts[0].span.to(&stmts[stmts.len() - 1].span);
+            np1.stmts = stmts;
+            np1
+        };
 
         if !st.accept(ScanToken::EOT) {
-            parse_error("Expecting EOT");
+            let token = st.get(0).unwrap();
+            return Err(ParseError::ExpectingEot {
+                span: token.span,
+                found: token.value.clone(),
+            });
+        }
+
+        Ok(np1)
+    }
+
+    /// Parses `IDENT '=' expression` or a bare expression.
+    pub fn parse_statement(&mut self, st: &mut ScanTableSt) -> Result<ParseNode, ParseError> {
+        let is_assign = st.get(0).unwrap().id == ScanToken::Ident
+            && st.get(1).is_some_and(|token| token.id == ScanToken::Assign);
+        if !is_assign {
+            return self.parse_expression(st);
         }
 
-        Some(np1)
+        st.accept(ScanToken::Ident);
+        let ident = st.get_last(1).unwrap();
+        let (name, name_span) = (ident.value.clone(), ident.span);
+        st.accept(ScanToken::Assign);
+        let value = self.parse_expression(st)?;
+        let mut np1 = self.parse_node_new();
+
+        np1.type_ = ParseNodeType::Assign;
+        np1.name = name;
+        np1.span = name_span.to(&value.span);
+        np1.left = Some(Box::new(value));
+        Ok(np1)
     }
 
-    pub fn parse_expression(&mut self, st: &mut ScanTableSt) -> Option<ParseNode> {
+    /// Parses a full expression with the grammar selected in `self.grammar`.
+    /// Both grammars build the same `ParseNode` shapes.
+    pub fn parse_expression(&mut self, st: &mut ScanTableSt) -> Result<ParseNode, ParseError> {
+        match self.grammar {
+            Grammar::Flat => self.parse_expression_flat(st),
+            Grammar::Precedence => self.parse_binary(st, 1),
+        }
+    }
+
+    /// Legacy grammar: operators fold left to right with no precedence.
+    fn parse_expression_flat(&mut self, st: &mut ScanTableSt) -> Result<ParseNode, ParseError> {
         let mut np1 = self.parse_operand(st)?;
 
-        loop {
-            let token = st.get(0);
-            let is_operator = matches!(
-                token.unwrap().id,
-                ScanToken::Plus
-                    | ScanToken::Minus
-                    | ScanToken::Mult
-                    | ScanToken::Div
-                    | ScanToken::ShiftRight
-                    | ScanToken::ShiftLeft
-                    | ScanToken::ArithShiftRight
-                    | ScanToken::BitAnd
-                    | ScanToken::BitOr
-                    | ScanToken::BitXor
-            );
-
-            if is_operator {
-                let operator = match token.unwrap().id {
-                    ScanToken::Plus => Some(ParseOperator::Plus),
-                    ScanToken::Minus => Some(ParseOperator::Minus),
-                    ScanToken::Mult => Some(ParseOperator::Mult),
-                    ScanToken::Div => Some(ParseOperator::Div),
-                    ScanToken::ShiftRight => Some(ParseOperator::ShiftRight),
-                    ScanToken::ShiftLeft => Some(ParseOperator::ShiftLeft),
-                    ScanToken::ArithShiftRight => Some(ParseOperator::ArithShiftRight),
-                    ScanToken::BitAnd => Some(ParseOperator::BitAnd),
-                    ScanToken::BitOr => Some(ParseOperator::BitOr),
-                    ScanToken::BitXor => Some(ParseOperator::BitXor),
-                    _ => unreachable!(),
-                };
-                st.accept(ScanToken::Any); // Consume the operator token
-                let mut np2 = self.parse_node_new();
-
-                np2.type_= ParseNodeType::Oper2;
-                np2.oper = operator.unwrap();
-                np2.left = Some(Box::new(np1));
-                np2.right = Some(Box::new(self.parse_operand(st)?));
-                np1 = np2;
-            } else {
+        while let Some(operator) = self.get_operator(&st.get(0).unwrap().id) {
+            if operator_precedence(&operator) == 0 {
                 break;
             }
+            st.accept(ScanToken::Any); // Consume the operator token
+            let mut np2 = self.parse_node_new();
+
+            np2.type_ = ParseNodeType::Oper2;
+            np2.oper = operator;
+            let right = self.parse_operand(st)?;
+            np2.span = np1.span.to(&right.span);
+            np2.left = Some(Box::new(np1));
+            np2.right = Some(Box::new(right));
+            np1 = np2;
         }
-        Some(np1)
+        Ok(np1)
     }
 
-    fn get_operator(&self, token: ScanToken) -> Option<ParseOperator> {
+    /// C grammar: precedence climbing over `operator_precedence`.
+    fn parse_binary(&mut self, st: &mut ScanTableSt, min_prec: u32) -> Result<ParseNode, ParseError> {
+        let mut np1 = self.parse_operand(st)?;
+
+        while let Some(operator) = self.get_operator(&st.get(0).unwrap().id) {
+            let prec = operator_precedence(&operator);
+            if prec == 0 || prec < min_prec {
+                break;
+            }
+            st.accept(ScanToken::Any); // Consume the operator token
+            let mut np2 = self.parse_node_new();
+
+            np2.type_ = ParseNodeType::Oper2;
+            np2.oper = operator;
+            // All binary operators are left associative, so the right operand
+            // only absorbs operators that bind strictly tighter.
+            let right = self.parse_binary(st, prec + 1)?;
+            np2.span = np1.span.to(&right.span);
+            np2.left = Some(Box::new(np1));
+            np2.right = Some(Box::new(right));
+            np1 = np2;
+        }
+        Ok(np1)
+    }
+
+    fn get_operator(&self, token: &ScanToken) -> Option<ParseOperator> {
         match token {
             ScanToken::Plus => Some(ParseOperator::Plus),
             ScanToken::Minus => Some(ParseOperator::Minus),
@@ -146,108 +284,156 @@ impl ParseTableSt {
         }
     }
 
-    pub fn parse_operand(&mut self, st: &mut ScanTableSt) -> Option<ParseNode> {
+    pub fn parse_operand(&mut self, st: &mut ScanTableSt) -> Result<ParseNode, ParseError> {
         if st.accept(ScanToken::IntLit) {
-            Some(self.parse_literal_value(st, 10))
+            self.parse_literal_value(st, 10)
         } else if st.accept(ScanToken::HexLit) {
-            Some(self.parse_literal_value(st, 16))
+            self.parse_literal_value(st, 16)
         } else if st.accept(ScanToken::BinLit) {
-            Some(self.parse_literal_value(st, 2))
+            self.parse_literal_value(st, 2)
+        } else if st.accept(ScanToken::Ident) {
+            let ident = st.get_last(1).unwrap();
+            let (name, span) = (ident.value.clone(), ident.span);
+            let mut np1 = self.parse_node_new();
+
+            np1.type_ = ParseNodeType::Ident;
+            np1.name = name;
+            np1.span = span;
+            Ok(np1)
         } else if st.accept(ScanToken::Minus) {
             let mut np1 = self.parse_node_new();
 
             np1.type_= ParseNodeType::Oper1;
-            np1.oper = self.get_operator(ScanToken::Minus).unwrap();
-            np1.left = Some(Box::new(self.parse_operand(st)?));
-            Some(np1)
+            np1.oper = self.get_operator(&ScanToken::Minus).unwrap();
+            let op_span = st.get_last(1).unwrap().span;
+            let operand = self.parse_operand(st)?;
+            np1.span = op_span.to(&operand.span);
+            np1.left = Some(Box::new(operand));
+            Ok(np1)
         } else if st.accept(ScanToken::BitNot) {
             let mut np1 = self.parse_node_new();
             np1.type_= ParseNodeType::Oper1;
-            np1.oper = self.get_operator(ScanToken::BitNot).unwrap();
-            np1.left = Some(Box::new(self.parse_operand(st)?));
-            Some(np1)
+            np1.oper = self.get_operator(&ScanToken::BitNot).unwrap();
+            let op_span = st.get_last(1).unwrap().span;
+            let operand = self.parse_operand(st)?;
+            np1.span = op_span.to(&operand.span);
+            np1.left = Some(Box::new(operand));
+            Ok(np1)
         } else if st.accept(ScanToken::LParen) {
-            let np1 = self.parse_expression(st)?;
+            let lparen_span = st.get_last(1).unwrap().span;
+            let mut np1 = self.parse_expression(st)?;
             if !st.accept(ScanToken::RParen) {
-                parse_error("Expecting ')'");
+                return Err(ParseError::ExpectingRParen {
+                    span: st.get(0).unwrap().span,
+                    lparen: lparen_span,
+                });
             }
-            Some(np1)
+            // Widen the node to cover the parentheses it was written with.
+            np1.span = lparen_span.to(&st.get_last(1).unwrap().span);
+            Ok(np1)
         } else {
-            parse_error("Bad operand");
-            None
+            Err(ParseError::BadOperand {
+                span: st.get(0).unwrap().span,
+                after: st.get_last(1).map(|prev| prev.value.clone()),
+            })
         }
     }
 
-    fn parse_literal_value(&mut self, st: &mut ScanTableSt, base: u32) -> ParseNode {
-        let token = st.getLast(1).unwrap(); // Get the last scanned token
+    fn parse_literal_value(&mut self, st: &mut ScanTableSt, base: u32) -> Result<ParseNode, ParseError> {
+        let token = st.get_last(1).unwrap(); // Get the last scanned token
+        let span = token.span;
+        let width = self.width;
+        if base != 10 && token.value.len() == 2 {
+            return Err(ParseError::MalformedLiteral {
+                span,
+                literal: token.value.clone(),
+            });
+        }
         let value = match base {
-            10 => parse_literal_value_base_10(token.value.as_str()),
-            16 => parse_literal_value_base_16(token.value.as_str()),
-            2 => parse_literal_value_base_2(token.value.as_str()),
+            10 => parse_literal_value_base_10(token.value.as_str(), width),
+            16 => parse_literal_value_base_16(token.value.as_str(), width),
+            2 => parse_literal_value_base_2(token.value.as_str(), width),
             _ => panic!("Unsupported base"),
         };
+        let value = match value {
+            Some(value) => value,
+            None => {
+                return Err(ParseError::LiteralOverflow {
+                    span,
+                    literal: token.value.clone(),
+                    width,
+                })
+            }
+        };
         let mut np1 = self.parse_node_new();
 
         np1.type_= ParseNodeType::Literal;
-        np1.value = value as i32;
-        np1
+        np1.value = value;
+        np1.span = span;
+        Ok(np1)
     }
 }
 
-fn parse_literal_value_base_10(value: &str) -> u32 {
-    match value.parse() {
-        Ok(parsed) => parsed,
-        Err(_) => {
-            println!("overflows uint32_t: {}",value);
-            process::exit(1);
-        }
-    }
+fn parse_literal_value_base_10(value: &str, width: u32) -> Option<Word> {
+    Word::from_str_radix(value, 10, width)
 }
 
-fn parse_literal_value_base_16(value: &str) -> u32 {
-    match u32::from_str_radix(value.trim_start_matches("0x"), 16) {
-        Ok(parsed) => parsed,
-        Err(_) => {
-            println!("overflows uint32_t: {}",value);
-            process::exit(1);
-        }
-    }
+fn parse_literal_value_base_16(value: &str, width: u32) -> Option<Word> {
+    Word::from_str_radix(&value[2..], 16, width)
 }
 
-fn parse_literal_value_base_2(value: &str) -> u32 {
-    match u32::from_str_radix(value.trim_start_matches("0b"), 2) {
-        Ok(parsed) => parsed,
-        Err(_) => {
-            println!("overflows uint32_t: {}",value);
-            process::exit(1);
-        }
+fn parse_literal_value_base_2(value: &str, width: u32) -> Option<Word> {
+    Word::from_str_radix(&value[2..], 2, width)
+}
+
+/// Binding strength of a binary operator, following C. Higher binds tighter;
+/// 0 means the operator cannot appear between two operands.
+fn operator_precedence(oper: &ParseOperator) -> u32 {
+    match oper {
+        ParseOperator::Mult | ParseOperator::Div => 6,
+        ParseOperator::Plus | ParseOperator::Minus => 5,
+        ParseOperator::ShiftLeft | ParseOperator::ShiftRight | ParseOperator::ArithShiftRight => 4,
+        ParseOperator::BitAnd => 3,
+        ParseOperator::BitXor => 2,
+        ParseOperator::BitOr => 1,
+        ParseOperator::BitNot => 0,
     }
 }
 
+pub fn print_parse_tree(node: &ParseNode) {
+    print!("{}", format_parse_tree(node));
+}
 
-fn parse_error(err: &str) {
-    println!("{}", err);
-    process::exit(-1);
+/// Renders the tree one node per line, children indented under their parent.
+pub fn format_parse_tree(node: &ParseNode) -> String {
+    let mut out = String::new();
+    format_parse_node(node, 0, &mut out);
+    out
 }
 
-pub fn print_parse_tree(node: &ParseNode) {
+fn format_parse_node(node: &ParseNode, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let span = format!("{}:{}-{}:{}", node.span.start.line, node.span.start.col, node.span.end.line, node.span.end.col);
     match node.type_ {
-        ParseNodeType::Literal => println!("Literal: {}", node.value),
-        ParseNodeType::Oper1 => {
-            println!("Operator: {:?}", node.oper);
+        ParseNodeType::Literal => out.push_str(&format!("{}Literal: {} @ {}\n", indent, node.value, span)),
+        ParseNodeType::Ident => out.push_str(&format!("{}Ident: {} @ {}\n", indent, node.name, span)),
+        ParseNodeType::Oper1 | ParseNodeType::Oper2 | ParseNodeType::Assign | ParseNodeType::Seq => {
+            let label = match node.type_ {
+                ParseNodeType::Assign => format!("Assign: {}", node.name),
+                ParseNodeType::Seq => "Seq".to_string(),
+                _ => format!("Operator: {:?}", node.oper),
+            };
+            out.push_str(&format!("{}{} @ {}\n", indent, label, span));
             if let Some(ref left) = node.left {
-                print_parse_tree(left);
-            }
-        }
-        ParseNodeType::Oper2 => {
-            println!("Operator: {:?}", node.oper);
-            if let Some(ref left) = node.left {
-                print_parse_tree(left);
+                format_parse_node(left, depth + 1, out);
             }
             if let Some(ref right) = node.right {
-                print_parse_tree(right);
+                format_parse_node(right, depth + 1, out);
+            }
+            for stmt in &node.stmts {
+                format_parse_node(stmt, depth + 1, out);
             }
         }
-        ParseNodeType::None => println!("Empty Node"),
+        ParseNodeType::None => out.push_str(&format!("{}Empty Node\n", indent)),
     }
 }
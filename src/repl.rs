@@ -0,0 +1,124 @@
+use std::io::{self, BufRead, Write};
+use config::{Config, Grammar, Overflow};
+use diag::render;
+use eval::{eval_format, EvalEnvSt};
+use word::MAX_WIDTH;
+use parse::format_parse_tree;
+
+const HELP: &str = "\
+Enter an expression or statements such as `x = 0xF0; x | 1` to evaluate
+them, or one of:
+  :base <2|10|16>      output base
+  :width <1-65536>     evaluation width in bits
+  :signed              print decimal results as signed
+  :unsigned            print decimal results as unsigned
+  :grammar <flat|prec> operator grouping
+  :overflow <wrap|trap|saturate>
+                       what out-of-range `+ - * <<` results do
+  :tokens [expr]       show the token table (default: last expression)
+  :ast [expr]          show the parse tree (default: last expression)
+  :vars                show the defined variables
+  :config              show the current settings
+  :help                show this message
+  :quit                leave the REPL
+";
+
+/// Reads expressions from `input` until EOF or `:quit`, writing results and
+/// diagnostics to `out`. Meta-commands update `cp` in place. Variables stay
+/// defined for the rest of the session.
+pub fn run<R: BufRead, W: Write>(cp: &mut Config, input: R, out: &mut W) -> io::Result<()> {
+    let mut env = EvalEnvSt::new();
+    let mut last = String::new();
+    write!(out, "ntlang> ")?;
+    out.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line == ":quit" || line == ":q" {
+            break;
+        } else if line.starts_with(':') {
+            run_command(cp, &env, line, &last, out)?;
+        } else if !line.is_empty() {
+            last = line.to_string();
+            match ::evaluate_in(line, cp, &mut env) {
+                Ok(value) => writeln!(out, "{}", eval_format(cp, &value))?,
+                Err(err) => write!(out, "{}", render(line, &err.diagnostic()))?,
+            }
+        }
+        write!(out, "ntlang> ")?;
+        out.flush()?;
+    }
+    writeln!(out)
+}
+
+fn run_command<W: Write>(cp: &mut Config, env: &EvalEnvSt, line: &str, last: &str, out: &mut W) -> io::Result<()> {
+    let (cmd, arg) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+    match cmd {
+        ":base" => match arg.parse::<u32>() {
+            Ok(base) if base == 2 || base == 10 || base == 16 => cp.base = base,
+            _ => writeln!(out, "Error: base must be 2, 10 or 16")?,
+        },
+        ":width" => match arg.parse::<u32>() {
+            Ok(width) if (1..=MAX_WIDTH).contains(&width) => cp.width = width,
+            _ => writeln!(out, "Error: width must be between 1 and {}", MAX_WIDTH)?,
+        },
+        ":signed" => cp.unsigned_int = true,
+        ":unsigned" => cp.unsigned_int = false,
+        ":grammar" => match arg {
+            "flat" => cp.grammar = Grammar::Flat,
+            "prec" => cp.grammar = Grammar::Precedence,
+            _ => writeln!(out, "Error: grammar must be flat or prec")?,
+        },
+        ":overflow" => match arg {
+            "wrap" => cp.overflow = Overflow::Wrap,
+            "trap" => cp.overflow = Overflow::Trap,
+            "saturate" => cp.overflow = Overflow::Saturate,
+            _ => writeln!(out, "Error: overflow must be wrap, trap or saturate")?,
+        },
+        ":tokens" | ":ast" => {
+            let expr = if arg.is_empty() { last } else { arg };
+            if expr.is_empty() {
+                writeln!(out, "Error: no expression to inspect")?;
+                return Ok(());
+            }
+            let result = if cmd == ":tokens" {
+                ::tokenize(expr).map(|st| st.format_tokens())
+            } else {
+                ::parse(expr, cp).map(|node| format_parse_tree(&node))
+            };
+            match result {
+                Ok(text) => write!(out, "{}", text)?,
+                Err(err) => write!(out, "{}", render(expr, &err.diagnostic()))?,
+            }
+        }
+        ":vars" => {
+            let mut names: Vec<&String> = env.table.keys().collect();
+            names.sort();
+            for name in names {
+                writeln!(out, "{} = {}", name, eval_format(cp, &env.table[name]))?;
+            }
+        }
+        ":config" => writeln!(
+            out,
+            "base {}, width {}, {}, grammar {}, overflow {}",
+            cp.base,
+            cp.width,
+            if cp.unsigned_int { "signed" } else { "unsigned" },
+            match cp.grammar {
+                Grammar::Flat => "flat",
+                Grammar::Precedence => "prec",
+            },
+            match cp.overflow {
+                Overflow::Wrap => "wrap",
+                Overflow::Trap => "trap",
+                Overflow::Saturate => "saturate",
+            }
+        )?,
+        ":help" => write!(out, "{}", HELP)?,
+        _ => writeln!(out, "Error: unknown command {} (try :help)", cmd)?,
+    }
+    Ok(())
+}
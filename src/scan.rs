@@ -1,6 +1,11 @@
 // scan.rs
-use std::process;
+use std::iter::Peekable;
+use std::error::Error;
+use std::fmt;
+use std::str::CharIndices;
+use diag::Diagnostic;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum ScanToken {
     IntLit,
@@ -19,24 +24,128 @@ pub enum ScanToken {
     BitNot,
     LParen,
     RParen,
+    Ident,
+    Assign,
+    Semi,
     EOT,
     Any,
 }
 
+/// A position in the scanned input. `offset` is a byte offset; `line` and
+/// `col` are 1-based, with `col` counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SourcePos {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A half-open range `[start, end)` of the scanned input.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: SourcePos,
+    pub end: SourcePos,
+}
+
+impl Span {
+    pub fn new(start: SourcePos, end: SourcePos) -> Self {
+        Span { start, end }
+    }
+
+    /// Span covering everything from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanError {
+    InvalidChar(char, Span),
+}
+
+impl ScanError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match *self {
+            ScanError::InvalidChar(_, span) => Diagnostic::new("S001", &self.to_string(), span)
+                .with_hint("expected a literal, a variable, an operator or a parenthesis"),
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScanError::InvalidChar(c, _) => write!(f, "invalid char: {}", c),
+        }
+    }
+}
+
+impl Error for ScanError {}
+
 pub struct ScanTokenSt {
     pub id: ScanToken,
     pub value: String,
+    pub span: Span,
 }
 
 pub struct ScanTableSt {
+    pub input: String,
     pub table: Vec<ScanTokenSt>,
     pub len: usize,
     pub cur: usize,
 }
 
+/// Character iterator that keeps track of the current `SourcePos`.
+struct ScanCursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    input: &'a str,
+    pos: SourcePos,
+}
+
+impl<'a> ScanCursor<'a> {
+    fn new(input: &'a str) -> Self {
+        ScanCursor {
+            chars: input.char_indices().peekable(),
+            input,
+            pos: SourcePos { offset: 0, line: 1, col: 1 },
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn text(&self, start: SourcePos) -> &'a str {
+        &self.input[start.offset..self.pos.offset]
+    }
+}
+
+impl<'a> Iterator for ScanCursor<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let (offset, c) = self.chars.next()?;
+        self.pos.offset = offset + c.len_utf8();
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.col = 1;
+        } else {
+            self.pos.col += 1;
+        }
+        Some(c)
+    }
+}
+
+impl Default for ScanTableSt {
+    fn default() -> Self {
+        ScanTableSt::new()
+    }
+}
+
 impl ScanTableSt {
     pub fn new() -> Self {
         ScanTableSt {
+            input: String::new(),
             table: Vec::new(),
             len: 0,
             cur: 0,
@@ -44,6 +153,7 @@ impl ScanTableSt {
     }
 
     pub fn init(&mut self) {
+        self.table.clear();
         self.len = 0;
         self.cur = 0;
     }
@@ -52,118 +162,109 @@ impl ScanTableSt {
         let token = ScanTokenSt {
             id: ScanToken::Any,
             value: String::new(),
+            span: Span::default(),
         };
         self.table.push(token);
         self.len += 1;
         self.table.last_mut().unwrap()
     }
 
-    pub fn scan(&mut self, input: &str) {
-        let mut iter = input.chars().peekable();
-        while let Some(c) = iter.next() {
+    pub fn scan(&mut self, input: &str) -> Result<(), ScanError> {
+        self.init();
+        self.input = input.to_string();
+        let mut iter = ScanCursor::new(input);
+        loop {
+            let start = iter.pos;
+            let c = match iter.next() {
+                Some(c) => c,
+                None => break,
+            };
             if c == '+' {
-                self.add_token(ScanToken::Plus, c);
+                self.add_token(ScanToken::Plus, &iter, start);
             } else if c == '-' {
-                self.add_token(ScanToken::Minus, c);
+                self.add_token(ScanToken::Minus, &iter, start);
             } else if c == '*' {
-                self.add_token(ScanToken::Mult, c);
+                self.add_token(ScanToken::Mult, &iter, start);
             } else if c == '/' {
-                self.add_token(ScanToken::Div, c);
+                self.add_token(ScanToken::Div, &iter, start);
             } else if c == '>' {
-                if iter.peek() == Some(&'>') {
+                if iter.peek() == Some('>') {
                     iter.next();
-                    self.add_token(ScanToken::ShiftRight, c);
-                } else if iter.peek() == Some(&'-') {
+                    self.add_token(ScanToken::ShiftRight, &iter, start);
+                } else if iter.peek() == Some('-') {
                     iter.next();
-                    self.add_token(ScanToken::ArithShiftRight, c);
+                    self.add_token(ScanToken::ArithShiftRight, &iter, start);
                 } else {
-                    self.add_token(ScanToken::Any, c);
+                    self.add_token(ScanToken::Any, &iter, start);
                 }
             } else if c == '<' {
-                if iter.peek() == Some(&'<') {
+                if iter.peek() == Some('<') {
                     iter.next();
-                    self.add_token(ScanToken::ShiftLeft, c);
+                    self.add_token(ScanToken::ShiftLeft, &iter, start);
                 } else {
-                    self.add_token(ScanToken::Any, c);
+                    self.add_token(ScanToken::Any, &iter, start);
                 }
             } else if c == '&' {
-                self.add_token(ScanToken::BitAnd, c);
+                self.add_token(ScanToken::BitAnd, &iter, start);
             } else if c == '|' {
-                self.add_token(ScanToken::BitOr, c);
+                self.add_token(ScanToken::BitOr, &iter, start);
             } else if c == '^' {
-                self.add_token(ScanToken::BitXor, c);
+                self.add_token(ScanToken::BitXor, &iter, start);
             } else if c == '~' {
-                self.add_token(ScanToken::BitNot, c);
+                self.add_token(ScanToken::BitNot, &iter, start);
             } else if c == '(' {
-                self.add_token(ScanToken::LParen, c);
+                self.add_token(ScanToken::LParen, &iter, start);
             } else if c == ')' {
-                self.add_token(ScanToken::RParen, c);
-            }else if c == '0' {
-                if iter.peek() == Some(&'x') || iter.peek() == Some(&'X') {
-                    self.scan_hexlit(&mut iter);
-                } else if iter.peek() == Some(&'b') || iter.peek() == Some(&'B') {
-                    self.scan_binlit(&mut iter);
-                } else {
-                    self.add_token(ScanToken::Any, c);
-                }
-            } else if c.is_digit(10) {
-                self.scan_intlit(&mut iter,c);
-            }  else if c.is_whitespace() {
+                self.add_token(ScanToken::RParen, &iter, start);
+            } else if c == '=' {
+                self.add_token(ScanToken::Assign, &iter, start);
+            } else if c == ';' {
+                self.add_token(ScanToken::Semi, &iter, start);
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                self.scan_literal(ScanToken::Ident, &mut iter, start, |c| c.is_ascii_alphanumeric() || c == '_');
+            } else if c == '0' && (iter.peek() == Some('x') || iter.peek() == Some('X')) {
+                iter.next();
+                self.scan_literal(ScanToken::HexLit, &mut iter, start, |c| c.is_ascii_hexdigit());
+            } else if c == '0' && (iter.peek() == Some('b') || iter.peek() == Some('B')) {
+                iter.next();
+                self.scan_literal(ScanToken::BinLit, &mut iter, start, |c| c == '0' || c == '1');
+            } else if c.is_ascii_digit() {
+                self.scan_literal(ScanToken::IntLit, &mut iter, start, |c| c.is_ascii_digit());
+            } else if c.is_whitespace() {
                 continue;
             } else {
-                println!("scan error: invalid char: {}", c);
-                process::exit(-1);
+                return Err(ScanError::InvalidChar(c, Span::new(start, iter.pos)));
             }
         }
-        self.add_token(ScanToken::EOT, '\0');
-    }
-
-
-    fn add_token(&mut self, id: ScanToken, value: char) {
-        let token = self.new_token();
-        token.id = id;
-        token.value.push(value);
-    }
-
-    fn scan_intlit(&mut self, iter: &mut std::iter::Peekable<std::str::Chars>, c: char) {
+        let end = iter.pos;
         let token = self.new_token();
-        token.id = ScanToken::IntLit;
-        token.value.push(c);
-        while let Some(&c) = iter.peek() {
-            if c.is_digit(10) {
-                token.value.push(iter.next().unwrap());
-            } else {
-                break;
-            }
-        }
+        token.id = ScanToken::EOT;
+        token.value.push('\0');
+        token.span = Span::new(end, end);
+        Ok(())
     }
 
-    fn scan_hexlit(&mut self, iter: &mut std::iter::Peekable<std::str::Chars>) {
+    fn add_token(&mut self, id: ScanToken, iter: &ScanCursor, start: SourcePos) {
         let token = self.new_token();
-        token.id = ScanToken::HexLit;
-        token.value.push('0');
-        token.value.push(iter.next().unwrap());
-        while let Some(&c) = iter.peek() {
-            if c.is_digit(16) {
-                token.value.push(iter.next().unwrap());
-            } else {
-                break;
-            }
-        }
+        token.id = id;
+        token.value.push_str(iter.text(start));
+        token.span = Span::new(start, iter.pos);
     }
 
-    fn scan_binlit(&mut self, iter: &mut std::iter::Peekable<std::str::Chars>) {
-        let token = self.new_token();
-        token.id = ScanToken::BinLit;
-        token.value.push('0');
-        token.value.push(iter.next().unwrap());
-        while let Some(&c) = iter.peek() {
-            if c == '0' || c == '1' {
-                token.value.push(iter.next().unwrap());
+    /// Scans the rest of a literal or identifier whose prefix (and first
+    /// character, for decimal literals and identifiers) has been consumed.
+    fn scan_literal<F>(&mut self, id: ScanToken, iter: &mut ScanCursor, start: SourcePos, is_digit: F)
+    where
+        F: Fn(char) -> bool,
+    {
+        while let Some(c) = iter.peek() {
+            if is_digit(c) {
+                iter.next();
             } else {
                 break;
             }
         }
+        self.add_token(id, iter, start);
     }
 }
 
@@ -192,7 +293,19 @@ impl ScanTableSt {
         }
     }
 
-    pub fn getLast(&self, i: usize) -> Option<&ScanTokenSt> {
+    /// Renders the token table one token per line, for debugging.
+    pub fn format_tokens(&self) -> String {
+        let mut out = String::new();
+        for token in &self.table {
+            out.push_str(&format!(
+                "{:?} {:?} @ {}:{}\n",
+                token.id, token.value, token.span.start.line, token.span.start.col
+            ));
+        }
+        out
+    }
+
+    pub fn get_last(&self, i: usize) -> Option<&ScanTokenSt> {
         if self.cur >= i {
             Some(&self.table[self.cur - i])
         } else {
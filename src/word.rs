@@ -0,0 +1,386 @@
+//! Fixed-width bit vectors: the values `eval` computes with.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The widest `Config::width` the evaluator accepts. A `Word` may be wider,
+/// so that `eval` can compute exact results to check for overflow.
+pub const MAX_WIDTH: u32 = 65536;
+
+/// An unsigned integer of `width` bits, at least 1. Every operation
+/// wraps modulo 2^width, and the operands of a binary operation must have
+/// the same width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// Little-endian 64-bit limbs, as many as `width` needs, with the bits
+    /// above `width` clear.
+    limbs: Vec<u64>,
+    width: u32,
+}
+
+fn limb_count(width: u32) -> usize {
+    width.div_ceil(64) as usize
+}
+
+/// Whether no bit at or above `width` is set.
+fn fits(limbs: &[u64], width: u32) -> bool {
+    let n = limb_count(width);
+    let top = if width.is_multiple_of(64) { 0 } else { limbs[n - 1] >> (width % 64) };
+    top == 0 && limbs[n..].iter().all(|&limb| limb == 0)
+}
+
+/// Compares equally long limb slices.
+fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// `a -= b` on equally long limb slices, wrapping.
+fn sub_assign(a: &mut [u64], b: &[u64]) {
+    let mut borrow = false;
+    for (x, &y) in a.iter_mut().zip(b) {
+        let (diff, b1) = x.overflowing_sub(y);
+        let (diff, b2) = diff.overflowing_sub(borrow as u64);
+        *x = diff;
+        borrow = b1 || b2;
+    }
+}
+
+/// Divides `limbs` in place by `divisor`, returning the remainder.
+fn div_small(limbs: &mut [u64], divisor: u64) -> u64 {
+    let divisor = u128::from(divisor);
+    let mut rem = 0u128;
+    for limb in limbs.iter_mut().rev() {
+        let cur = rem << 64 | u128::from(*limb);
+        *limb = (cur / divisor) as u64;
+        rem = cur % divisor;
+    }
+    rem as u64
+}
+
+impl Word {
+    fn from_limbs(mut limbs: Vec<u64>, width: u32) -> Word {
+        assert!(width != 0, "zero width");
+        limbs.resize(limb_count(width), 0);
+        if !width.is_multiple_of(64) {
+            *limbs.last_mut().unwrap() &= (1 << (width % 64)) - 1;
+        }
+        Word { limbs, width }
+    }
+
+    /// The low `width` bits of `bits`.
+    pub fn new(bits: u128, width: u32) -> Word {
+        Word::from_limbs(vec![bits as u64, (bits >> 64) as u64], width)
+    }
+
+    pub fn zero(width: u32) -> Word {
+        Word::new(0, width)
+    }
+
+    /// All `width` bits set: -1 as a signed value.
+    pub fn ones(width: u32) -> Word {
+        Word::from_limbs(vec![u64::MAX; limb_count(width)], width)
+    }
+
+    /// The largest value of the width, signed or unsigned.
+    pub fn max_value(width: u32, signed: bool) -> Word {
+        let ones = Word::ones(width);
+        if signed {
+            ones.shr_by(1)
+        } else {
+            ones
+        }
+    }
+
+    /// The smallest value of the width, signed or unsigned.
+    pub fn min_value(width: u32, signed: bool) -> Word {
+        if signed {
+            Word::max_value(width, true).not()
+        } else {
+            Word::zero(width)
+        }
+    }
+
+    /// Parses unsigned `digits` in `radix`, or returns `None` if there are
+    /// none, one is not a digit, or the value needs more than `width` bits.
+    pub fn from_str_radix(digits: &str, radix: u32, width: u32) -> Option<Word> {
+        if digits.is_empty() {
+            return None;
+        }
+        // A spare limb catches whatever a digit carries past the width.
+        let mut limbs = vec![0u64; limb_count(width) + 1];
+        for c in digits.chars() {
+            let mut carry = u128::from(c.to_digit(radix)?);
+            for limb in limbs.iter_mut() {
+                let cur = u128::from(*limb) * u128::from(radix) + carry;
+                *limb = cur as u64;
+                carry = cur >> 64;
+            }
+            if !fits(&limbs, width) {
+                return None;
+            }
+        }
+        Some(Word::from_limbs(limbs, width))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The low 128 bits, zero-extended.
+    pub fn bits(&self) -> u128 {
+        let high = self.limbs.get(1).map_or(0, |&limb| u128::from(limb));
+        high << 64 | u128::from(self.limbs[0])
+    }
+
+    /// Bit `i`, counting from the least significant.
+    pub fn bit(&self, i: u32) -> bool {
+        self.limbs[(i / 64) as usize] >> (i % 64) & 1 == 1
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+
+    /// Whether exactly one bit is set.
+    pub fn is_power_of_two(&self) -> bool {
+        self.limbs.iter().map(|limb| limb.count_ones()).sum::<u32>() == 1
+    }
+
+    pub fn trailing_zeros(&self) -> u32 {
+        match self.limbs.iter().position(|&limb| limb != 0) {
+            Some(i) => 64 * i as u32 + self.limbs[i].trailing_zeros(),
+            None => self.width,
+        }
+    }
+
+    /// The bits up to and including the highest set one; 0 for zero.
+    pub fn significant_bits(&self) -> u32 {
+        match self.limbs.iter().rposition(|&limb| limb != 0) {
+            Some(i) => 64 * i as u32 + 64 - self.limbs[i].leading_zeros(),
+            None => 0,
+        }
+    }
+
+    /// The value as a shift count, saturated at the width: shifting by
+    /// the width or more moves every bit out.
+    pub fn as_shift(&self) -> u32 {
+        if self.significant_bits() > 32 {
+            self.width
+        } else {
+            (self.limbs[0] as u32).min(self.width)
+        }
+    }
+
+    /// Whether the top bit is set.
+    pub fn is_negative(&self) -> bool {
+        self.bit(self.width - 1)
+    }
+
+    /// The value truncated or zero-extended to `width` bits.
+    pub fn resize(&self, width: u32) -> Word {
+        Word::from_limbs(self.limbs.clone(), width)
+    }
+
+    /// The value truncated or sign-extended to `width` bits.
+    pub fn sign_extend(&self, width: u32) -> Word {
+        let value = self.resize(width);
+        if self.is_negative() && width > self.width {
+            value.or(&Word::ones(width).shl_by(self.width))
+        } else {
+            value
+        }
+    }
+
+    /// The digits of the unsigned value in `radix`, lowercase, without
+    /// leading zeros.
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        let bits_per_digit = match radix {
+            2 => 1,
+            8 => 3,
+            10 => return self.to_decimal(),
+            16 => 4,
+            _ => panic!("unsupported radix {}", radix),
+        };
+        let digits = self.significant_bits().div_ceil(bits_per_digit).max(1);
+        (0..digits)
+            .rev()
+            .map(|digit| {
+                let low = digit * bits_per_digit;
+                let value = (low..(low + bits_per_digit).min(self.width))
+                    .filter(|&i| self.bit(i))
+                    .fold(0, |value, i| value | 1 << (i - low));
+                std::char::from_digit(value, radix).unwrap()
+            })
+            .collect()
+    }
+
+    fn to_decimal(&self) -> String {
+        // Peel off 19 digits at a time, the most a u64 holds.
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut limbs = self.limbs.clone();
+        let mut chunks = vec![div_small(&mut limbs, CHUNK)];
+        while limbs.iter().any(|&limb| limb != 0) {
+            chunks.push(div_small(&mut limbs, CHUNK));
+        }
+        let mut out = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            out.push_str(&format!("{:019}", chunk));
+        }
+        out
+    }
+
+    fn check(&self, rhs: &Word) {
+        assert_eq!(self.width, rhs.width, "operands of different widths");
+    }
+
+    fn zip(&self, rhs: &Word, f: fn(u64, u64) -> u64) -> Word {
+        self.check(rhs);
+        let limbs = self.limbs.iter().zip(&rhs.limbs).map(|(&a, &b)| f(a, b)).collect();
+        Word::from_limbs(limbs, self.width)
+    }
+
+    fn shl_by(&self, count: u32) -> Word {
+        let (skip, bits) = ((count / 64) as usize, count % 64);
+        let mut limbs = vec![0u64; self.limbs.len()];
+        for (i, limb) in limbs.iter_mut().enumerate().skip(skip) {
+            *limb = self.limbs[i - skip] << bits;
+            if bits != 0 && i > skip {
+                *limb |= self.limbs[i - skip - 1] >> (64 - bits);
+            }
+        }
+        Word::from_limbs(limbs, self.width)
+    }
+
+    fn shr_by(&self, count: u32) -> Word {
+        let (skip, bits) = ((count / 64) as usize, count % 64);
+        let mut limbs = vec![0u64; self.limbs.len()];
+        for i in 0..limbs.len().saturating_sub(skip) {
+            limbs[i] = self.limbs[i + skip] >> bits;
+            if bits != 0 && i + skip + 1 < self.limbs.len() {
+                limbs[i] |= self.limbs[i + skip + 1] << (64 - bits);
+            }
+        }
+        Word::from_limbs(limbs, self.width)
+    }
+
+    pub fn neg(&self) -> Word {
+        self.not().add(&Word::new(1, self.width))
+    }
+
+    pub fn not(&self) -> Word {
+        Word::from_limbs(self.limbs.iter().map(|&limb| !limb).collect(), self.width)
+    }
+
+    pub fn add(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        let mut carry = false;
+        let limbs = self
+            .limbs
+            .iter()
+            .zip(&rhs.limbs)
+            .map(|(&a, &b)| {
+                let (sum, c1) = a.overflowing_add(b);
+                let (sum, c2) = sum.overflowing_add(carry as u64);
+                carry = c1 || c2;
+                sum
+            })
+            .collect();
+        Word::from_limbs(limbs, self.width)
+    }
+
+    pub fn sub(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        let mut limbs = self.limbs.clone();
+        sub_assign(&mut limbs, &rhs.limbs);
+        Word::from_limbs(limbs, self.width)
+    }
+
+    pub fn mul(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        let n = self.limbs.len();
+        let mut limbs = vec![0u64; n];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u128;
+            for j in 0..n - i {
+                let cur = u128::from(a) * u128::from(rhs.limbs[j]) + u128::from(limbs[i + j]) + carry;
+                limbs[i + j] = cur as u64;
+                carry = cur >> 64;
+            }
+        }
+        Word::from_limbs(limbs, self.width)
+    }
+
+    /// Unsigned division, or `None` if `rhs` is zero.
+    pub fn checked_div(&self, rhs: &Word) -> Option<Word> {
+        self.check(rhs);
+        if rhs.is_zero() {
+            return None;
+        }
+        let mut quotient = self.limbs.clone();
+        if rhs.significant_bits() <= 64 {
+            div_small(&mut quotient, rhs.limbs[0]);
+            return Some(Word::from_limbs(quotient, self.width));
+        }
+        // Shift and subtract a bit at a time. The remainder gets a spare
+        // limb, as doubling it may carry past the width.
+        let mut divisor = rhs.limbs.clone();
+        divisor.push(0);
+        let mut rem = vec![0u64; divisor.len()];
+        quotient.iter_mut().for_each(|limb| *limb = 0);
+        for i in (0..self.significant_bits()).rev() {
+            for j in (1..rem.len()).rev() {
+                rem[j] = rem[j] << 1 | rem[j - 1] >> 63;
+            }
+            rem[0] = rem[0] << 1 | self.bit(i) as u64;
+            if compare(&rem, &divisor) != Ordering::Less {
+                sub_assign(&mut rem, &divisor);
+                quotient[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        Some(Word::from_limbs(quotient, self.width))
+    }
+
+    /// Shift left; a count of the width or more gives zero.
+    pub fn shl(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        self.shl_by(rhs.as_shift())
+    }
+
+    /// Logical shift right; a count of the width or more gives zero.
+    pub fn shr(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        self.shr_by(rhs.as_shift())
+    }
+
+    /// Arithmetic shift right: the sign bit is copied into the vacated bits,
+    /// so a count of the width or more fills every bit with it.
+    pub fn sar(&self, rhs: &Word) -> Word {
+        self.check(rhs);
+        let count = rhs.as_shift();
+        let shifted = self.shr_by(count);
+        if self.is_negative() && count != 0 {
+            shifted.or(&Word::ones(self.width).shl_by(self.width - count))
+        } else {
+            shifted
+        }
+    }
+
+    pub fn and(&self, rhs: &Word) -> Word {
+        self.zip(rhs, |a, b| a & b)
+    }
+
+    pub fn or(&self, rhs: &Word) -> Word {
+        self.zip(rhs, |a, b| a | b)
+    }
+
+    pub fn xor(&self, rhs: &Word) -> Word {
+        self.zip(rhs, |a, b| a ^ b)
+    }
+}
+
+/// The unsigned value in decimal.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_str_radix(10))
+    }
+}
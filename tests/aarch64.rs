@@ -0,0 +1,120 @@
+extern crate ntlang;
+
+mod common;
+
+use common::{balanced, body, unoptimized};
+use ntlang::Emit;
+
+fn compile(program: &str, width: u32, signed: bool) -> String {
+    common::compile(program, &unoptimized(width, signed), Emit::Aarch64)
+}
+
+fn assemble(asm: &str) -> bool {
+    common::assemble(asm, &["--triple=aarch64"])
+}
+
+#[test]
+fn operators_map_to_instructions() {
+    let asm = compile("(a >- 3) & ~m | a >> b", 32, true);
+    assert_eq!(
+        body(&asm),
+        [
+            "stp x29, x30, [sp, #-16]!",
+            "mov x29, sp",
+            "sub sp, sp, #16",
+            "str w0, [sp, #0]",
+            "str w1, [sp, #4]",
+            "str w2, [sp, #8]",
+            "ldr w9, [sp, #0]",
+            "mov w10, #3",
+            "asr w9, w9, w10",
+            "ldr w10, [sp, #4]",
+            "mvn w10, w10",
+            "and w9, w9, w10",
+            "ldr w10, [sp, #0]",
+            "ldr w11, [sp, #8]",
+            "cmp w11, #32",
+            "lsr w10, w10, w11",
+            "csel w10, w10, wzr, lo",
+            "orr w9, w9, w10",
+            "sbfx x0, x9, #0, #32",
+            "mov sp, x29",
+            "ldp x29, x30, [sp], #16",
+            "ret",
+        ]
+    );
+    assert!(assemble(&asm), "{}", asm);
+}
+
+#[test]
+fn shifts_saturate_their_count() {
+    let asm = compile("a >- b", 32, true);
+    let lines = body(&asm);
+    let cmp = lines.iter().position(|line| *line == "cmp w10, #32").unwrap();
+    assert_eq!(lines[cmp + 1..cmp + 3], ["csinv w8, w10, wzr, lo", "asr w9, w9, w8"]);
+    let asm = compile("a << b", 32, true);
+    let lines = body(&asm);
+    let cmp = lines.iter().position(|line| *line == "cmp w10, #32").unwrap();
+    assert_eq!(lines[cmp + 1..cmp + 3], ["lsl w9, w9, w10", "csel w9, w9, wzr, lo"]);
+}
+
+#[test]
+fn results_are_extended_as_printed() {
+    assert!(body(&compile("x + 1", 32, true)).contains(&"sbfx x0, x9, #0, #32"));
+    assert!(body(&compile("x + 1", 32, false)).contains(&"ubfx x0, x9, #0, #32"));
+    assert!(body(&compile("x + 1", 12, true)).contains(&"sbfx x0, x9, #0, #12"));
+    assert!(body(&compile("x + 1", 16, false)).contains(&"ubfx x0, x9, #0, #16"));
+}
+
+#[test]
+fn division_checks_the_divisor() {
+    let asm = compile("x / y", 32, false);
+    let lines = body(&asm);
+    let cbz = lines.iter().position(|line| *line == "cbz w10, .Ldiv_by_zero").unwrap();
+    assert_eq!(lines[cbz + 1], "udiv w9, w9, w10");
+    assert_eq!(&lines[lines.len() - 2..], [".Ldiv_by_zero:", "brk #0"]);
+    assert!(assemble(&asm), "{}", asm);
+}
+
+#[test]
+fn spilled_temporaries_use_the_frame() {
+    // Eight live values at once, one more than there are temporaries.
+    let asm = compile(&balanced(7, &mut 0), 32, true);
+    assert!(asm.contains("spill slots: 1"), "{}", asm);
+    let lines = body(&asm);
+    // Variables a to d take the first four slots; the spill goes after.
+    assert!(lines.contains(&"str w16, [sp, #16]"), "{}", asm);
+    assert!(lines.contains(&"ldr w17, [sp, #16]"), "{}", asm);
+    assert!(assemble(&asm), "{}", asm);
+}
+
+#[test]
+fn large_frames_stay_encodable() {
+    for &(vars, far) in &[(70, false), (5000, true)] {
+        let assigns: Vec<String> = (0..vars).map(|i| format!("v{} = x + {}", i, i)).collect();
+        let program = format!("{}; v0 + v{}", assigns.join("; "), vars - 1);
+        let asm = compile(&program, 32, true);
+        assert!(!asm.contains("stur") && !asm.contains("ldur"));
+        assert_eq!(asm.contains("[sp, x8]"), far);
+        assert!(assemble(&asm), "{} variables", vars);
+    }
+}
+
+#[test]
+fn wide_widths_use_x_registers() {
+    let asm = compile("(a >- b) / c + 0x123456789", 64, true);
+    assert!(asm.contains("// x0 = a\n"), "{}", asm);
+    let lines = body(&asm);
+    assert!(lines.contains(&"str x1, [sp, #8]"), "{}", asm);
+    let cmp = lines.iter().position(|line| *line == "cmp x10, #64").unwrap();
+    assert_eq!(lines[cmp + 1..cmp + 3], ["csinv x8, x10, xzr, lo", "asr x9, x9, x8"]);
+    assert!(lines.contains(&"udiv x9, x9, x10"), "{}", asm);
+    let mov = lines.iter().position(|line| *line == "mov x10, #26505").unwrap();
+    assert_eq!(lines[mov + 1..mov + 3], ["movk x10, #9029, lsl #16", "movk x10, #1, lsl #32"]);
+    assert!(lines.contains(&"sbfx x0, x9, #0, #64"), "{}", asm);
+    assert!(assemble(&asm), "{}", asm);
+
+    let asm = compile("x << 3", 40, false);
+    assert!(body(&asm).contains(&"ubfx x0, x9, #0, #40"), "{}", asm);
+    assert!(assemble(&asm), "{}", asm);
+}
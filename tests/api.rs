@@ -0,0 +1,51 @@
+extern crate ntlang;
+
+use ntlang::eval::eval_format;
+use ntlang::{Config, EvalEnvSt, ParseNodeType, ParseOperator, ScanToken, Word};
+
+#[test]
+fn evaluate_returns_a_word_at_the_width() {
+    let mut config = Config::new();
+    let value = ntlang::evaluate("0xF0 | 0x0F", &config).unwrap();
+    assert_eq!(value, Word::new(0xFF, 32));
+    config.base = 16;
+    assert_eq!(eval_format(&config, &value), "0x000000FF");
+    config.base = 10;
+    config.width = 8;
+    assert_eq!(eval_format(&config, &ntlang::evaluate("0xFF", &config).unwrap()), "-1");
+    config.unsigned_int = false;
+    assert_eq!(eval_format(&config, &ntlang::evaluate("0xFF", &config).unwrap()), "255");
+}
+
+#[test]
+fn tokens_and_trees_are_available() {
+    let st = ntlang::tokenize("1 + x").unwrap();
+    let ids: Vec<&ScanToken> = st.table.iter().map(|token| &token.id).collect();
+    assert_eq!(ids, [&ScanToken::IntLit, &ScanToken::Plus, &ScanToken::Ident, &ScanToken::EOT]);
+
+    let node = ntlang::parse("1 + x", &Config::new()).unwrap();
+    assert_eq!((&node.type_, &node.oper), (&ParseNodeType::Oper2, &ParseOperator::Plus));
+    assert_eq!(node.left.as_ref().unwrap().value, Word::new(1, 32));
+    assert_eq!(node.right.as_ref().unwrap().name, "x");
+}
+
+#[test]
+fn errors_work_with_the_question_mark() {
+    fn twice(expr: &str) -> Result<u128, Box<dyn std::error::Error>> {
+        let value = ntlang::evaluate(expr, &Config::new())?;
+        Ok(2 * value.bits())
+    }
+    assert_eq!(twice("20 + 1").unwrap(), 42);
+    assert_eq!(twice("1 / 0").unwrap_err().to_string(), "Division by zero");
+}
+
+#[test]
+fn an_environment_carries_variables_between_calls() {
+    let config = Config::new();
+    let mut env = EvalEnvSt::new();
+    ntlang::evaluate_in("mask = 0xFF; shift = 4", &config, &mut env).unwrap();
+    let value = ntlang::evaluate_in("0x1234 >> shift & mask", &config, &mut env).unwrap();
+    assert_eq!(value.bits(), 0x23);
+    assert_eq!(env.get("mask"), Some(Word::new(0xFF, 32)));
+    assert!(ntlang::evaluate("mask", &config).is_err());
+}
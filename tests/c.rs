@@ -0,0 +1,114 @@
+#![cfg(target_os = "linux")]
+
+extern crate ntlang;
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::{compile, literals_fit, run, temp_path, INPUTS, PROGRAMS};
+use ntlang::codegen::VarTableSt;
+use ntlang::eval::eval_format;
+use ntlang::{Config, Emit, EvalEnvSt, Word};
+
+/// Builds `code` under UBSan, with any undefined behaviour fatal, and a
+/// `main` that calls `call` on its arguments and prints the result.
+/// Without `cc` the test fails, unless `NTLANG_SKIP_MISSING_TOOLS` is set,
+/// which gives `None`.
+fn build(code: &str, call: &str, path: &str) -> Option<()> {
+    let source = format!(
+        "#include <stdlib.h>\n{}\n#include <stdio.h>\n\
+         int main(int argc, char **argv) {{ (void)argc; (void)argv; printf(\"%lld\\n\", (long long)({})); return 0; }}\n",
+        code, call
+    );
+    fs::write(format!("{}.c", path), source).unwrap();
+    let status = run(Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Werror", "-fsanitize=undefined", "-fno-sanitize-recover=all"])
+        .args(["-o", path, &format!("{}.c", path)]))?;
+    assert!(status.success(), "cc failed on:\n{}", code);
+    Some(())
+}
+
+#[test]
+fn compiled_code_matches_eval() {
+    let path = temp_path("c");
+    for &(width, signed) in &[(32, true), (32, false), (12, true), (7, false)] {
+        let mut config = Config::new();
+        config.width = width;
+        config.unsigned_int = signed;
+        for program in PROGRAMS.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let params = VarTableSt::new(&ntlang::parse(program, &config).unwrap()).params;
+            let args: Vec<String> = (0..params.len()).map(|i| format!("(uint32_t)strtoul(argv[{}], 0, 0)", i + 1)).collect();
+            let args = args.join(", ");
+            let forms = [
+                (Emit::C, format!("ntlang_expr({})", args)),
+                (Emit::CDefine, format!("NTLANG_EXPR({})", args)),
+            ];
+            for &(emit, ref call) in forms.iter() {
+                // A #define has no room for assignments.
+                if emit == Emit::CDefine && program.contains('=') {
+                    continue;
+                }
+                if build(&compile(program, &config, emit), call, &path).is_none() {
+                    return;
+                }
+                for i in 0..INPUTS.len() {
+                    let inputs: Vec<u32> = (0..params.len()).map(|j| INPUTS[(i + j) % INPUTS.len()]).collect();
+                    let mut env = EvalEnvSt::new();
+                    for (param, &value) in params.iter().zip(&inputs) {
+                        env.set(param, Word::new(u128::from(value), width));
+                    }
+                    let expected = ntlang::evaluate_in(program, &config, &mut env)
+                        .ok()
+                        .map(|value| format!("{}\n", eval_format(&config, &value)));
+                    let output = Command::new(&path).args(inputs.iter().map(|value| value.to_string())).output().unwrap();
+                    // Division by zero aborts; UBSan fails the run too.
+                    let actual = if output.status.success() { Some(String::from_utf8(output.stdout).unwrap()) } else { None };
+                    assert_eq!(actual, expected, "{:?} {} with {:?} at width {}", emit, program, inputs, width);
+                }
+            }
+        }
+    }
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(format!("{}.c", path));
+}
+
+#[test]
+fn helpers_and_masks_are_emitted() {
+    let config = Config::new();
+    let code = compile("(a >- b) / c", &config, Emit::C);
+    assert!(code.contains("static inline uint32_t ntlang_div(uint32_t a, uint32_t b)"));
+    assert!(code.contains("static inline uint32_t ntlang_sra(uint32_t a, uint32_t n)"));
+    assert!(code.contains("return ntlang_signed(ntlang_div(ntlang_sra(a, b), c));"), "{}", code);
+    let code = compile("(a >- b) / c", &config, Emit::CDefine);
+    assert!(code.contains("#define NTLANG_EXPR(a, b, c) (ntlang_signed(ntlang_div(ntlang_sra(((uint32_t)(a)), ((uint32_t)(b))), ((uint32_t)(c)))))"), "{}", code);
+
+    // Below 32 bits, inputs and wrapping results are masked to the width.
+    let mut config = Config::new();
+    config.width = 12;
+    config.unsigned_int = false;
+    let code = compile("x + y", &config, Emit::C);
+    assert!(code.contains("(x & 0xFFFu)") && code.contains("(y & 0xFFFu)"), "{}", code);
+    assert!(code.contains(") & 0xFFFu)"), "{}", code);
+    assert!(!code.contains("ntlang_div") && !code.contains("ntlang_sra"), "{}", code);
+}
+
+#[test]
+fn reserved_names_are_renamed() {
+    let path = temp_path("c-names");
+    let config = Config::new();
+    let program = "uint32_t = 2; inline = uint32_t * 3; uint32_t_ = _Bool - abort; bool / inline + uint32_t_";
+    let code = compile(program, &config, Emit::C);
+    assert!(code.contains("ntlang_expr(uint32_t _Bool_, uint32_t abort_, uint32_t bool_)"), "{}", code);
+    assert!(code.contains("uint32_t uint32_t_;") && code.contains("uint32_t uint32_t__;"), "{}", code);
+    if build(&code, "ntlang_expr(10, 4, 12)", &path).is_none() {
+        return;
+    }
+    let output = Command::new(&path).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "8\n");
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(format!("{}.c", path));
+}
@@ -0,0 +1,114 @@
+//! Programs, inputs and helpers shared by the backend tests. Each test
+//! file uses only some of them.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::process::{self, Command, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ntlang::{Config, Emit, Error, ParseError};
+
+/// Programs every backend must compute like `eval`: each operator, shift
+/// counts of the width or more, division by zero, assignments and deep
+/// nesting.
+pub const PROGRAMS: [&str; 19] = [
+    "1 + 2 * 3",
+    "63 + 64 - 8191 * 8192",
+    "0x7FFFFFFF ^ 0x80000000 | -0x40",
+    "-x >> 3 ^ ~y",
+    "(a >- 3) & m",
+    "x << y | x >> (32 - y)",
+    "t = x * 0x9E3779B9; t ^ t >> 16",
+    "t = x * 0x9E3779B9; u = t >- 7; u ^ t << y",
+    "x / y - y / (x | 1)",
+    "100 / (x - x)",
+    "(x >- y) ^ (y >> x)",
+    "(a & b) + (a | b) - (a ^ b)",
+    "a >- b / c + (x << 3)",
+    "0 - x",
+    "x ^ x",
+    "x >- (y + 31) << 31",
+    "1 / x; y = x + 1; y",
+    "a = b; b = a * a; a - b",
+    "1+(2+(3+(4+(5+(6+(7+(8+(9*x))))))))",
+];
+
+/// Values for the free variables of `PROGRAMS`.
+pub const INPUTS: [u32; 6] = [0, 1, 5, 31, 0x8000_0000, 0xDEAD_BEEF];
+
+/// Whether `program` parses under `config`. Literals must fit in the width,
+/// so some programs do not parse at the narrow ones.
+pub fn literals_fit(program: &str, config: &Config) -> bool {
+    !matches!(ntlang::parse(program, config), Err(Error::Parse(ParseError::LiteralOverflow { .. })))
+}
+
+/// A configuration for `width` bits that leaves the code unoptimized, so
+/// expected listings follow the source.
+pub fn unoptimized(width: u32, signed: bool) -> Config {
+    let mut config = Config::new();
+    config.width = width;
+    config.unsigned_int = signed;
+    config.optimize = false;
+    config
+}
+
+/// The text `program` compiles to with `emit`.
+pub fn compile(program: &str, config: &Config, emit: Emit) -> String {
+    let config = Config {
+        emit: Some(emit),
+        ..config.clone()
+    };
+    String::from_utf8(ntlang::compile(program, &config).unwrap()).unwrap()
+}
+
+/// The instructions and labels of the function in an assembly listing.
+pub fn body(asm: &str) -> Vec<&str> {
+    asm.lines()
+        .skip_while(|line| *line != "ntlang_expr:")
+        .skip(1)
+        .take_while(|line| !line.trim_start().starts_with(".size"))
+        .map(|line| line.trim())
+        .collect()
+}
+
+/// A complete binary tree of the given depth over the variables a to d.
+pub fn balanced(depth: u32, leaf: &mut usize) -> String {
+    if depth == 0 {
+        *leaf += 1;
+        return ["a", "b", "c", "d"][*leaf % 4].to_string();
+    }
+    let op = ["+", "-", "*", "^", "|", "&"][(*leaf + depth as usize) % 6];
+    let left = balanced(depth - 1, leaf);
+    let right = balanced(depth - 1, leaf);
+    format!("({} {} {})", left, op, right)
+}
+
+/// A path in the temporary directory no other test uses, including tests
+/// running in parallel in this process.
+pub fn temp_path(name: &str) -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("ntlang-{}-{}-{}", name, process::id(), n));
+    path.to_str().unwrap().to_string()
+}
+
+/// Runs `command` to completion. A missing tool fails the test, unless
+/// `NTLANG_SKIP_MISSING_TOOLS` is set, which gives `None`.
+pub fn run(command: &mut Command) -> Option<ExitStatus> {
+    match command.status() {
+        Ok(status) => Some(status),
+        Err(_) if env::var_os("NTLANG_SKIP_MISSING_TOOLS").is_some() => None,
+        Err(err) => panic!("cannot run {:?}: {}; set NTLANG_SKIP_MISSING_TOOLS to skip", command, err),
+    }
+}
+
+/// Whether `asm` assembles with `llvm-mc` given `args`, which select the
+/// target. A skipped `llvm-mc` passes.
+pub fn assemble(asm: &str, args: &[&str]) -> bool {
+    let path = format!("{}.s", temp_path("asm"));
+    fs::write(&path, asm).unwrap();
+    let status = run(Command::new("llvm-mc").args(args).args(["-filetype=obj", "-o", "/dev/null"]).arg(&path));
+    fs::remove_file(&path).unwrap();
+    status.is_none_or(|status| status.success())
+}
@@ -0,0 +1,82 @@
+extern crate ntlang;
+
+use ntlang::{render, Config, Diagnostic, SourcePos, Span};
+
+fn error(source: &str) -> String {
+    let err = ntlang::evaluate(source, &Config::new()).unwrap_err();
+    render(source, &err.diagnostic())
+}
+
+#[test]
+fn carets_underline_the_offending_tokens() {
+    assert_eq!(
+        error("3 << + 1").lines().collect::<Vec<_>>(),
+        [
+            "error[P001]: Bad operand",
+            " --> 1:6",
+            "  |",
+            "1 | 3 << + 1",
+            "  |      ^",
+            "  = hint: expected operand after `<<`",
+        ]
+    );
+    assert_eq!(
+        error("8 / (2 - 2)").lines().collect::<Vec<_>>(),
+        [
+            "error[E001]: Division by zero",
+            " --> 1:5",
+            "  |",
+            "1 | 8 / (2 - 2)",
+            "  |     ^^^^^^^",
+            "  = hint: the right operand of `/` evaluates to 0",
+        ]
+    );
+    assert_eq!(
+        error("0x + 1").lines().collect::<Vec<_>>(),
+        [
+            "error[P005]: malformed literal: 0x",
+            " --> 1:1",
+            "  |",
+            "1 | 0x + 1",
+            "  | ^^",
+            "  = hint: add at least one digit after `0x`",
+        ]
+    );
+}
+
+#[test]
+fn each_error_has_a_code_and_a_hint() {
+    let cases: [(&str, &str, &str); 8] = [
+        ("4 $ 2", "S001", "expected a literal, a variable, an operator or a parenthesis"),
+        ("(1 + 2", "P002", "add `)` to close the `(` at 1:1"),
+        ("1 + 2)", "P003", "`)` cannot follow a complete expression"),
+        ("0x1FFFFFFFF", "P004", "literals must fit in 32 bits"),
+        ("0x", "P005", "add at least one digit after `0x`"),
+        ("1 + 0b", "P005", "add at least one digit after `0b`"),
+        ("1 +", "P001", "expected operand after `+`"),
+        ("y + 1", "E004", "assign it first, e.g. `y = 0; ...`"),
+    ];
+    for &(source, code, hint) in cases.iter() {
+        let err = ntlang::evaluate(source, &Config::new()).unwrap_err();
+        let diag = err.diagnostic();
+        assert_eq!((diag.code, diag.hint.as_deref()), (code, Some(hint)), "{}", source);
+    }
+}
+
+#[test]
+fn end_of_input_gets_one_caret() {
+    let rendered = error("1 +");
+    assert!(rendered.contains("1 | 1 +\n  |    ^\n"), "{}", rendered);
+}
+
+#[test]
+fn later_lines_are_quoted_with_their_number() {
+    let rendered = error("x = 1;\ny + 1");
+    assert!(rendered.contains(" --> 2:1\n"), "{}", rendered);
+    assert!(rendered.contains("2 | y + 1\n  | ^\n"), "{}", rendered);
+
+    // A span running past the end of its line is underlined to the end.
+    let pos = |offset, line, col| SourcePos { offset, line, col };
+    let diag = Diagnostic::new("X000", "spans lines", Span::new(pos(2, 1, 3), pos(7, 2, 2)));
+    assert_eq!(render("a b\nc d", &diag), "error[X000]: spans lines\n --> 1:3\n  |\n1 | a b\n  |   ^\n");
+}
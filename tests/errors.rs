@@ -0,0 +1,75 @@
+extern crate ntlang;
+
+use std::process::Command;
+use ntlang::{Config, Error, EvalError, ParseError, ScanError};
+
+fn error(source: &str) -> Error {
+    ntlang::evaluate(source, &Config::new()).unwrap_err()
+}
+
+/// The exit code and output of the command line tool.
+fn ntlang(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_ntlang")).args(args).output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn each_stage_returns_its_own_error() {
+    match error("1 # 2") {
+        Error::Scan(ScanError::InvalidChar('#', span)) => assert_eq!(span.start.col, 3),
+        other => panic!("expected an invalid char, got {:?}", other),
+    }
+    match error("1 * * 2") {
+        Error::Parse(ParseError::BadOperand { after, .. }) => assert_eq!(after.as_deref(), Some("*")),
+        other => panic!("expected a bad operand, got {:?}", other),
+    }
+    match error("(1") {
+        Error::Parse(ParseError::ExpectingRParen { lparen, .. }) => assert_eq!(lparen.start.col, 1),
+        other => panic!("expected a missing `)`, got {:?}", other),
+    }
+    match error("1 2") {
+        Error::Parse(ParseError::ExpectingEot { found, .. }) => assert_eq!(found, "2"),
+        other => panic!("expected input after the end, got {:?}", other),
+    }
+    match error("99999999999") {
+        Error::Parse(ParseError::LiteralOverflow { literal, width, .. }) => {
+            assert_eq!((literal.as_str(), width), ("99999999999", 32))
+        }
+        other => panic!("expected an overflowing literal, got {:?}", other),
+    }
+    match error("7 / (3 - 3)") {
+        Error::Eval(EvalError::DivisionByZero(span)) => assert_eq!((span.start.col, span.end.col), (5, 12)),
+        other => panic!("expected a division by zero, got {:?}", other),
+    }
+    match error("x = 1; x + y") {
+        Error::Eval(EvalError::UndefinedVariable(name, span)) => {
+            assert_eq!((name.as_str(), span.start.col), ("y", 12))
+        }
+        other => panic!("expected an undefined variable, got {:?}", other),
+    }
+}
+
+#[test]
+fn errors_display_their_message() {
+    assert_eq!(error("1 # 2").to_string(), "invalid char: #");
+    assert_eq!(error("1 * * 2").to_string(), "Bad operand");
+    assert_eq!(error("(1").to_string(), "Expecting ')'");
+    assert_eq!(error("1 2").to_string(), "Expecting EOT");
+    assert_eq!(error("1 / 0").to_string(), "Division by zero");
+    assert_eq!(error("y").to_string(), "Undefined variable: y");
+}
+
+#[test]
+fn exit_codes_name_the_failing_stage() {
+    assert_eq!(ntlang(&["-e", "6 * 7"]), (0, "42\n".to_string()));
+    assert_eq!(ntlang(&["-w"]).0, 1);
+    assert_eq!(ntlang(&["--frobnicate"]), (1, "Error: Unknown argument --frobnicate\n".to_string()));
+    assert_eq!(ntlang(&["-e", "1 # 2"]).0, 2);
+    assert_eq!(ntlang(&["-e", "1 +"]).0, 3);
+    assert_eq!(ntlang(&["-e", "1 / 0"]).0, 4);
+    assert_eq!(ntlang(&["-e", "x + 1", "--overflow", "trap", "--emit", "c"]).0, 5);
+
+    let (code, out) = ntlang(&["-e", "1 / 0"]);
+    assert_eq!(code, 4);
+    assert!(out.starts_with("error[E001]: Division by zero\n"), "{}", out);
+}
@@ -0,0 +1,89 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+extern crate ntlang;
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use common::{literals_fit, temp_path, INPUTS, PROGRAMS};
+use ntlang::codegen::VarTableSt;
+use ntlang::eval::eval_format;
+use ntlang::{Config, Emit, EvalEnvSt, Word};
+
+/// Runs the executable at `path` and returns its status and output.
+fn run(path: &str, args: &[String]) -> (i32, String) {
+    let output = Command::new(path).args(args).output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn executable_matches_eval() {
+    let path = temp_path("exe");
+    let configs = [(10, 32, true), (10, 32, false), (16, 32, true), (2, 9, true), (10, 12, true), (16, 7, false)];
+    for &(base, width, signed) in &configs {
+        let mut config = Config::new();
+        config.base = base;
+        config.width = width;
+        config.unsigned_int = signed;
+        config.emit = Some(Emit::Exe);
+        for program in PROGRAMS.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let exe = ntlang::compile(program, &config).unwrap();
+            fs::write(&path, &exe).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+            let params = VarTableSt::new(&ntlang::parse(program, &config).unwrap()).params;
+            for i in 0..INPUTS.len() {
+                let inputs: Vec<u32> = (0..params.len()).map(|j| INPUTS[(i + j) % INPUTS.len()]).collect();
+                let mut env = EvalEnvSt::new();
+                for (param, &value) in params.iter().zip(&inputs) {
+                    env.set(param, Word::new(u128::from(value), width));
+                }
+                let expected = match ntlang::evaluate_in(program, &config, &mut env) {
+                    Ok(value) => (0, format!("{}\n", eval_format(&config, &value))),
+                    Err(_) => (4, String::new()),
+                };
+                let args: Vec<String> = inputs.iter().map(|value| value.to_string()).collect();
+                assert_eq!(run(&path, &args), expected, "{} with {:?}, base {}, width {}", program, inputs, base, width);
+            }
+        }
+    }
+
+    // Arguments may be hexadecimal, binary or negative; anything else, or
+    // the wrong number of them, is a usage error.
+    let config = Config {
+        emit: Some(Emit::Exe),
+        ..Config::new()
+    };
+    fs::write(&path, ntlang::compile("x - y", &config).unwrap()).unwrap();
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    assert_eq!(run(&path, &args(&["0x1F", "0b11"])), (0, "28\n".to_string()));
+    assert_eq!(run(&path, &args(&["-5", "0XfF"])), (0, "-260\n".to_string()));
+    assert_eq!(run(&path, &args(&["1"])).0, 1);
+    assert_eq!(run(&path, &args(&["1", "2x"])).0, 1);
+    assert_eq!(run(&path, &args(&["1", ""])).0, 1);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn output_file_is_executable() {
+    let path = temp_path("exe-output");
+    let status = Command::new(env!("CARGO_BIN_EXE_ntlang"))
+        .args(["-e", "x * 6", "--emit", "exe", "-o", &path])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+    assert_eq!(run(&path, &["7".to_string()]), (0, "42\n".to_string()));
+    fs::remove_file(&path).unwrap();
+
+    // -o only applies to --emit.
+    let status = Command::new(env!("CARGO_BIN_EXE_ntlang")).args(["-e", "1", "-o", &path]).status().unwrap();
+    assert_eq!(status.code(), Some(1));
+    assert!(fs::metadata(&path).is_err());
+}
@@ -0,0 +1,59 @@
+extern crate ntlang;
+
+use ntlang::{Config, Flags};
+
+fn flags(program: &str, width: u32) -> Option<Flags> {
+    let mut config = Config::new();
+    config.width = width;
+    ntlang::trace(program, &config).unwrap().flags
+}
+
+fn nzcv(n: bool, z: bool, c: bool, v: bool) -> Option<Flags> {
+    Some(Flags { n, z, c, v })
+}
+
+#[test]
+fn flags_match_an_arm_alu() {
+    let cases: [(&str, u32, Option<Flags>); 17] = [
+        ("0x7FFFFFFF + 1", 32, nzcv(true, false, false, true)),
+        ("0xFFFFFFFF + 1", 32, nzcv(false, true, true, false)),
+        ("0xFFFFFFFF + 0xFFFFFFFF", 32, nzcv(true, false, true, false)),
+        ("0x80000000 + 0x80000000", 32, nzcv(false, true, true, true)),
+        // C is set when a subtraction does not borrow, as after ARM's CMP.
+        ("5 - 5", 32, nzcv(false, true, true, false)),
+        ("3 - 5", 32, nzcv(true, false, false, false)),
+        ("0x80000000 - 1", 32, nzcv(false, false, true, true)),
+        ("0x80000001 << 1", 32, nzcv(false, false, true, false)),
+        ("3 >> 1", 32, nzcv(false, false, true, false)),
+        ("-2 >- 1", 32, nzcv(true, false, false, false)),
+        ("7 >> 0", 32, nzcv(false, false, false, false)),
+        // Beyond the width the last bit out is one that was shifted in.
+        ("1 << 32", 32, nzcv(false, true, true, false)),
+        ("1 << 33", 32, nzcv(false, true, false, false)),
+        ("0x80 >- 12", 8, nzcv(true, false, true, false)),
+        ("0xF0 & 0x0F", 32, nzcv(false, true, false, false)),
+        ("100 + 100", 8, nzcv(true, false, false, true)),
+        ("-(1 + 1)", 32, nzcv(false, false, false, false)),
+    ];
+    for &(program, width, expected) in cases.iter() {
+        assert_eq!(flags(program, width), expected, "{} at width {}", program, width);
+    }
+    assert_eq!(flags("x = 42; x", 32), None);
+}
+
+#[test]
+fn each_binary_operation_is_a_step() {
+    let mut config = Config::new();
+    config.width = 8;
+    config.flags = true;
+    let report = ntlang::trace("x = 6; (x - 1) * (x + 0xFA)", &config).unwrap();
+    let steps: Vec<(usize, &str, Flags)> = report
+        .steps
+        .iter()
+        .filter_map(|step| step.flags.map(|flags| (step.depth, step.expr.as_str(), flags)))
+        .collect();
+    let exprs: Vec<(usize, &str)> = steps.iter().map(|&(depth, expr, _)| (depth, expr)).collect();
+    assert_eq!(exprs, [(2, "x - 1"), (2, "x + 250"), (1, "(x - 1) * (x + 250)")]);
+    assert!(steps[1].2.c && steps[1].2.z);
+    assert_eq!(report.to_string().lines().last(), Some("flags: N=0 Z=1 C=0 V=0"));
+}
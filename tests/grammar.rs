@@ -0,0 +1,54 @@
+extern crate ntlang;
+
+use ntlang::parse::format_parse_tree;
+use ntlang::{Config, Grammar};
+
+fn args(list: &[&str]) -> Result<Config, String> {
+    let mut args = vec!["ntlang".to_string()];
+    args.extend(list.iter().map(|arg| arg.to_string()));
+    Config::parse_args(args)
+}
+
+fn run(program: &str, grammar: Grammar) -> u128 {
+    let mut config = Config::new();
+    config.grammar = grammar;
+    ntlang::evaluate(program, &config).unwrap().bits()
+}
+
+#[test]
+fn flat_grammar_folds_left_to_right() {
+    let cases: [(&str, u128, u128); 5] = [
+        ("1 + 2 * 3", 9, 7),
+        ("2 * 3 + 1", 7, 7),
+        ("4 | 2 ^ 6", 0, 4),
+        ("1 + 1 << 2", 8, 8),
+        ("1 << 2 + 1", 5, 8),
+    ];
+    for &(program, flat, prec) in cases.iter() {
+        assert_eq!(run(program, Grammar::Flat), flat, "{} flat", program);
+        assert_eq!(run(program, Grammar::Precedence), prec, "{} prec", program);
+    }
+    // Parentheses group under either grammar.
+    assert_eq!(run("1 + (2 * 3)", Grammar::Flat), 7);
+}
+
+#[test]
+fn both_grammars_build_the_same_nodes() {
+    let mut config = Config::new();
+    let prec = format_parse_tree(&ntlang::parse("(a + b) * -c", &config).unwrap());
+    config.grammar = Grammar::Flat;
+    let flat = format_parse_tree(&ntlang::parse("a + b * -c", &config).unwrap());
+    let strip = |tree: &str| -> Vec<String> {
+        tree.lines().map(|line| line.split(" @ ").next().unwrap().to_string()).collect()
+    };
+    assert_eq!(strip(&flat), strip(&prec));
+}
+
+#[test]
+fn grammar_is_selected_with_g() {
+    assert_eq!(args(&[]).unwrap().grammar, Grammar::Precedence);
+    assert_eq!(args(&["-g", "flat"]).unwrap().grammar, Grammar::Flat);
+    assert_eq!(args(&["-g", "prec"]).unwrap().grammar, Grammar::Precedence);
+    assert_eq!(args(&["-g", "lisp"]).err().unwrap(), "Invalid argument for -g");
+    assert_eq!(args(&["-g"]).err().unwrap(), "Missing argument for -g");
+}
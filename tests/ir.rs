@@ -0,0 +1,50 @@
+extern crate ntlang;
+
+mod common;
+
+use common::{literals_fit, INPUTS, PROGRAMS};
+use ntlang::codegen::ir;
+use ntlang::codegen::ir_interp::{self, IrTrap};
+use ntlang::codegen::verify::expected_result;
+use ntlang::{Config, EvalEnvSt, Grammar, Word};
+
+#[test]
+fn ir_matches_eval() {
+    for &grammar in &[Grammar::Flat, Grammar::Precedence] {
+        for &(width, signed) in &[(32, true), (32, false), (12, true), (3, false)] {
+            let mut config = Config::new();
+            config.grammar = grammar;
+            config.width = width;
+            config.unsigned_int = signed;
+            for program in PROGRAMS.iter() {
+                if !literals_fit(program, &config) {
+                    continue;
+                }
+                let node = ntlang::parse(program, &config).unwrap();
+                let func = ir::lower(&node, &config);
+                for i in 0..INPUTS.len() {
+                    let args: Vec<u32> = (0..func.vars.params.len())
+                        .map(|j| INPUTS[(i + 2 * j) % INPUTS.len()])
+                        .collect();
+                    let mut env = EvalEnvSt::new();
+                    env.width = width;
+                    for (param, &value) in func.vars.params.iter().zip(&args) {
+                        env.set(param, Word::new(u128::from(value), width));
+                    }
+                    let expected = ntlang::eval::eval(&mut env, &Some(&node))
+                        .map(|value| expected_result(&value, &config, 64))
+                        .map_err(|_| IrTrap::DivisionByZero);
+                    assert_eq!(
+                        ir_interp::run(&func, &args),
+                        expected,
+                        "{} with {:?} at width {}:\n{}",
+                        program,
+                        args,
+                        width,
+                        func
+                    );
+                }
+            }
+        }
+    }
+}
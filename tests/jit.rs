@@ -0,0 +1,29 @@
+extern crate ntlang;
+
+mod common;
+
+use common::{INPUTS, PROGRAMS};
+use ntlang::{Config, EvalEnvSt, Word};
+
+#[test]
+fn jit_matches_eval() {
+    for &optimize in &[true, false] {
+        let mut config = Config::new();
+        config.optimize = optimize;
+        for program in PROGRAMS.iter() {
+            let jit = ntlang::jit(program, &config).unwrap();
+            if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+                assert!(jit.is_native());
+            }
+            for i in 0..INPUTS.len() {
+                let inputs: Vec<u32> = (0..jit.params().len()).map(|j| INPUTS[(i + 2 * j) % INPUTS.len()]).collect();
+                let mut env = EvalEnvSt::new();
+                for (param, &value) in jit.params().iter().zip(&inputs) {
+                    env.set(param, Word::new(u128::from(value), 32));
+                }
+                let expected = ntlang::evaluate_in(program, &config, &mut env).ok().map(|value| value.bits() as u32);
+                assert_eq!(jit.call(&inputs).ok(), expected, "{} with {:?}", program, inputs);
+            }
+        }
+    }
+}
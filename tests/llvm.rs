@@ -0,0 +1,160 @@
+extern crate ntlang;
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::{literals_fit, run, temp_path, unoptimized, PROGRAMS};
+use ntlang::{Config, Emit};
+
+fn compile(program: &str, width: u32, signed: bool) -> String {
+    common::compile(program, &unoptimized(width, signed), Emit::Llvm)
+}
+
+/// The instructions and labels of the function in `ll`.
+fn body(ll: &str) -> Vec<&str> {
+    ll.lines()
+        .skip_while(|line| !line.starts_with("define "))
+        .skip(2)
+        .take_while(|line| *line != "}")
+        .map(|line| line.trim())
+        .collect()
+}
+
+/// Whether `llvm-as` accepts `ll`.
+fn assemble(ll: &str) -> bool {
+    let path = format!("{}.ll", temp_path("llvm"));
+    fs::write(&path, ll).unwrap();
+    let status = run(Command::new("llvm-as").args(["-o", "/dev/null"]).arg(&path));
+    fs::remove_file(&path).unwrap();
+    status.is_none_or(|status| status.success())
+}
+
+#[test]
+fn shift_counts_saturate() {
+    let ll = compile("a << b >> c", 32, true);
+    assert!(ll.contains("define signext i32 @ntlang_expr(i32 %v.a, i32 %v.b, i32 %v.c) {"), "{}", ll);
+    assert_eq!(
+        body(&ll),
+        [
+            "%t1 = icmp ult i32 %v.b, 32",
+            "%t2 = shl i32 %v.a, %v.b",
+            "%t3 = select i1 %t1, i32 %t2, i32 0",
+            "%t4 = icmp ult i32 %v.c, 32",
+            "%t5 = lshr i32 %t3, %v.c",
+            "%t6 = select i1 %t4, i32 %t5, i32 0",
+            "ret i32 %t6",
+        ]
+    );
+    assert!(assemble(&ll), "{}", ll);
+
+    assert_eq!(
+        body(&compile("a >- b", 32, true)),
+        [
+            "%t1 = icmp ult i32 %v.b, 32",
+            "%t2 = select i1 %t1, i32 %v.b, i32 31",
+            "%t3 = ashr i32 %v.a, %t2",
+            "ret i32 %t3",
+        ]
+    );
+
+    // A constant count is checked at compile time.
+    assert_eq!(body(&compile("x >> 3", 32, true)), ["%t1 = lshr i32 %v.x, 3", "ret i32 %t1"]);
+    assert_eq!(body(&compile("x >- 35", 32, true)), ["%t1 = ashr i32 %v.x, 31", "ret i32 %t1"]);
+    assert_eq!(body(&compile("x << 35", 32, true)), ["ret i32 0"]);
+}
+
+#[test]
+fn narrow_widths_truncate_the_parameters() {
+    let ll = compile("a << b >> c", 12, false);
+    assert!(ll.contains("define zeroext i12 @ntlang_expr(i32 %v.a, i32 %v.b, i32 %v.c) {"), "{}", ll);
+    assert_eq!(
+        body(&ll),
+        [
+            "%t1 = trunc i32 %v.a to i12",
+            "%t2 = trunc i32 %v.b to i12",
+            "%t3 = trunc i32 %v.c to i12",
+            "%t4 = icmp ult i12 %t2, 12",
+            "%t5 = shl i12 %t1, %t2",
+            "%t6 = select i1 %t4, i12 %t5, i12 0",
+            "%t7 = icmp ult i12 %t3, 12",
+            "%t8 = lshr i12 %t6, %t3",
+            "%t9 = select i1 %t7, i12 %t8, i12 0",
+            "ret i12 %t9",
+        ]
+    );
+    assert!(assemble(&ll), "{}", ll);
+}
+
+#[test]
+fn wide_widths_pass_the_parameters_at_the_width() {
+    let ll = compile("a << b", 64, true);
+    assert!(ll.contains("define signext i64 @ntlang_expr(i64 %v.a, i64 %v.b) {"), "{}", ll);
+    assert_eq!(
+        body(&ll),
+        [
+            "%t1 = icmp ult i64 %v.b, 64",
+            "%t2 = shl i64 %v.a, %v.b",
+            "%t3 = select i1 %t1, i64 %t2, i64 0",
+            "ret i64 %t3",
+        ]
+    );
+    assert_eq!(body(&compile("x >- 0x10000000000000000", 128, true)), ["%t1 = ashr i128 %v.x, 127", "ret i128 %t1"]);
+}
+
+#[test]
+fn division_checks_the_divisor() {
+    let ll = compile("x / y", 32, false);
+    assert_eq!(
+        body(&ll),
+        [
+            "%t1 = icmp eq i32 %v.y, 0",
+            "br i1 %t1, label %div_by_zero, label %div1",
+            "div1:",
+            "%t2 = udiv i32 %v.x, %v.y",
+            "ret i32 %t2",
+            "div_by_zero:",
+            "call void @llvm.trap()",
+            "unreachable",
+        ]
+    );
+    assert!(ll.ends_with("\ndeclare void @llvm.trap() cold noreturn nounwind\n"), "{}", ll);
+    assert!(!compile("x * y", 32, true).contains("llvm.trap"));
+    assert!(assemble(&ll), "{}", ll);
+}
+
+#[test]
+fn variables_never_shadow_generated_names() {
+    let ll = compile("entry / div_by_zero + t1 + t1 * 3 + div1", 32, true);
+    assert!(
+        ll.contains("@ntlang_expr(i32 %v.entry, i32 %v.div_by_zero, i32 %v.t1, i32 %v.div1)"),
+        "{}",
+        ll
+    );
+    assert!(assemble(&ll), "{}", ll);
+
+    // 12 / 4 + 5 + 5 * 3 + 1, as the exit status of `lli`.
+    let main = "define i32 @main() {\n  %r = call i32 @ntlang_expr(i32 12, i32 4, i32 5, i32 1)\n  ret i32 %r\n}\n";
+    let path = format!("{}.ll", temp_path("llvm-names"));
+    fs::write(&path, format!("{}\n{}", ll, main)).unwrap();
+    let status = run(Command::new("lli").arg(&path));
+    fs::remove_file(&path).unwrap();
+    if let Some(status) = status {
+        assert_eq!(status.code(), Some(24), "{}", ll);
+    }
+}
+
+#[test]
+fn modules_are_valid() {
+    for &(width, signed) in &[(32, true), (32, false), (16, true), (12, false), (7, true), (64, false), (200, true)] {
+        let mut config = Config::new();
+        config.width = width;
+        for program in PROGRAMS.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let ll = compile(program, width, signed);
+            assert!(assemble(&ll), "{} at width {}:\n{}", program, width, ll);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+extern crate ntlang;
+
+use ntlang::opt::{format_expr, optimize};
+use ntlang::{Config, EvalEnvSt, Word};
+
+const SIMPLIFIED: [(&str, &str); 12] = [
+    ("(x << 0) | 0 & 0xFF ^ 0", "x"),
+    ("x & ~0", "x"),
+    ("x ^ x", "0"),
+    ("x * 8", "x << 3"),
+    ("16 * x", "x << 4"),
+    ("x / 4", "x >> 2"),
+    ("1 + 2 * 3", "7"),
+    ("~~x - 0", "x"),
+    ("x ^ -1", "~x"),
+    ("100 / (x - x)", "100 / 0"),
+    ("(x / y) * 0", "(x / y) * 0"),
+    ("t = 3 * 0; t + 0", "t = 0; t"),
+];
+
+#[test]
+fn simplifies() {
+    let config = Config::new();
+    for &(program, expected) in SIMPLIFIED.iter() {
+        let node = ntlang::parse(program, &config).unwrap();
+        assert_eq!(format_expr(&optimize(&node, 32).0), expected, "{}", program);
+    }
+}
+
+const OPERANDS: [&str; 12] = ["x", "y", "0", "1", "2", "8", "31", "32", "0xFF", "0x80000000", "~0", "-1"];
+const OPERATORS: [&str; 10] = ["+", "-", "*", "/", "<<", ">>", ">-", "&", "|", "^"];
+
+/// A small linear congruential generator, so the test is reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % n
+    }
+}
+
+fn expression(rng: &mut Rng, depth: u32) -> String {
+    if depth == 0 || rng.below(4) == 0 {
+        return OPERANDS[rng.below(OPERANDS.len())].to_string();
+    }
+    match rng.below(6) {
+        0 => format!("-{}", expression(rng, depth - 1)),
+        1 => format!("~{}", expression(rng, depth - 1)),
+        // Repeat a subtree so the `x op x` rules get exercised.
+        2 => {
+            let x = expression(rng, depth - 1);
+            format!("({}) {} ({})", x, OPERATORS[rng.below(OPERATORS.len())], x)
+        }
+        _ => format!(
+            "({}) {} ({})",
+            expression(rng, depth - 1),
+            OPERATORS[rng.below(OPERATORS.len())],
+            expression(rng, depth - 1)
+        ),
+    }
+}
+
+#[test]
+fn optimized_matches_eval() {
+    let config = Config::new();
+    let mut rng = Rng(1);
+    let inputs: [(u128, u128); 4] = [(0, 0), (1, 31), (0x8000_0000, 3), (0xDEAD_BEEF, 0xFFFF_FFFF)];
+    for _ in 0..2000 {
+        let program = format!("t = {}; {}", expression(&mut rng, 3), expression(&mut rng, 4).replace('y', "t"));
+        let node = ntlang::parse(&program, &config).unwrap();
+        for &width in &[32, 12] {
+            let (optimized, _) = optimize(&node, width);
+            for &(x, y) in inputs.iter() {
+                let run = |node| {
+                    let mut env = EvalEnvSt::new();
+                    env.width = width;
+                    env.set("x", Word::new(x, width));
+                    env.set("y", Word::new(y, width));
+                    ntlang::eval::eval(&mut env, &Some(node)).ok()
+                };
+                assert_eq!(
+                    run(&optimized),
+                    run(&node),
+                    "{} became {} at width {} with x={:#x}, y={:#x}",
+                    program,
+                    format_expr(&optimized),
+                    width,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+extern crate ntlang;
+
+use ntlang::eval::eval_format;
+use ntlang::{CodegenError, Config, Emit, Error, EvalError, Overflow};
+
+fn run(program: &str, width: u32, signed: bool, overflow: Overflow) -> Result<String, Error> {
+    let mut config = Config::new();
+    config.width = width;
+    config.unsigned_int = signed;
+    config.overflow = overflow;
+    ntlang::evaluate(program, &config).map(|value| eval_format(&config, &value))
+}
+
+#[test]
+fn saturates_to_the_range_of_the_width() {
+    let cases: [(&str, u32, bool, &str); 10] = [
+        ("0x7FFFFFFF + 1", 32, true, "2147483647"),
+        ("0x7FFFFFFF + 1", 32, false, "2147483648"),
+        ("-0x7FFFFFFF - 2", 32, true, "-2147483648"),
+        ("3 - 5", 32, false, "0"),
+        ("3 - 5", 32, true, "-2"),
+        ("100 * 100", 8, true, "127"),
+        ("-100 * 100", 8, true, "-128"),
+        ("100 * 100", 8, false, "255"),
+        ("1 << 7", 8, true, "127"),
+        ("-1 << 7", 8, true, "-128"),
+    ];
+    for &(program, width, signed, expected) in cases.iter() {
+        let value = run(program, width, signed, Overflow::Saturate).unwrap();
+        assert_eq!(value, expected, "{} at width {}", program, width);
+    }
+    // Wide words saturate too.
+    let value = run("(1 << 200) * (1 << 100)", 256, false, Overflow::Saturate).unwrap();
+    assert_eq!(value, format!("{}", ntlang::Word::ones(256)));
+}
+
+#[test]
+fn trap_names_the_subexpression() {
+    match run("x = 0x7FFFFFFF; y = x + 1; y / 2", 32, true, Overflow::Trap) {
+        Err(Error::Eval(EvalError::Overflow(expr, span))) => {
+            assert_eq!(expr, "x + 1");
+            assert_eq!(span.start.col, 21);
+        }
+        other => panic!("expected an overflow, got {:?}", other),
+    }
+    assert!(run("0x7FFFFFFF + 1", 32, false, Overflow::Trap).is_ok());
+    assert!(run("1 - 2", 32, false, Overflow::Trap).is_err());
+    assert!(run("1 << 31", 32, true, Overflow::Trap).is_err());
+    assert!(run("-1 << 31", 32, true, Overflow::Trap).is_ok());
+}
+
+#[test]
+fn negation_is_checked_like_subtraction() {
+    match run("-(0x80000000)", 32, false, Overflow::Trap) {
+        Err(Error::Eval(EvalError::Overflow(expr, _))) => assert_eq!(expr, "-0x80000000"),
+        other => panic!("expected an overflow, got {:?}", other),
+    }
+    assert!(run("-(0x80000000)", 32, true, Overflow::Trap).is_err());
+    assert!(run("-0", 32, false, Overflow::Trap).is_ok());
+    assert_eq!(run("-(0x80000000)", 32, true, Overflow::Saturate).unwrap(), "2147483647");
+    assert_eq!(run("-(0x80000000)", 32, false, Overflow::Saturate).unwrap(), "0");
+    assert_eq!(run("-(0x80000000)", 32, false, Overflow::Wrap).unwrap(), "2147483648");
+    assert_eq!(run("-127", 8, true, Overflow::Trap).unwrap(), "-127");
+}
+
+#[test]
+fn in_range_results_do_not_depend_on_the_policy() {
+    let programs = ["6 * 7 - 2", "-1 >> 4", "-8 >- 1", "(0 - 3) * 5", "0x7FFF << 16 ^ ~0"];
+    for program in programs.iter() {
+        let wrapped = run(program, 32, true, Overflow::Wrap).unwrap();
+        assert_eq!(run(program, 32, true, Overflow::Trap).unwrap(), wrapped, "{}", program);
+        assert_eq!(run(program, 32, true, Overflow::Saturate).unwrap(), wrapped, "{}", program);
+    }
+}
+
+#[test]
+fn generated_code_refuses_other_policies() {
+    for &overflow in &[Overflow::Trap, Overflow::Saturate] {
+        let mut config = Config::new();
+        config.overflow = overflow;
+        config.emit = Some(Emit::C);
+        match ntlang::compile("x + 1", &config) {
+            Err(Error::Codegen(CodegenError::Unsupported { .. })) => {}
+            other => panic!("expected unsupported overflow, got {:?}", other),
+        }
+        assert!(ntlang::verify("x + 1", &config).is_err());
+        assert!(ntlang::jit("x + 1", &config).is_err());
+    }
+    let mut config = Config::new();
+    config.emit = Some(Emit::C);
+    assert!(ntlang::compile("x + 1", &config).is_ok());
+}
@@ -0,0 +1,53 @@
+extern crate ntlang;
+
+use ntlang::parse::format_parse_tree;
+use ntlang::Config;
+
+fn run(program: &str) -> i64 {
+    let value = ntlang::evaluate(program, &Config::new()).unwrap();
+    value.bits() as u32 as i32 as i64
+}
+
+#[test]
+fn operators_bind_as_in_c() {
+    let cases: [(&str, i64); 12] = [
+        ("1 + 2 * 3", 7),
+        ("2 * 3 + 1", 7),
+        ("10 - 6 / 2", 7),
+        ("1 + 1 << 2", 8),
+        ("1 << 2 + 1", 8),
+        ("0xF0 >> 4 - 2", 0x3C),
+        ("1 << 4 & 0x18", 0x10),
+        ("6 & 3 ^ 1", 3),
+        ("1 ^ 6 & 3", 3),
+        ("4 | 2 ^ 6", 4),
+        ("1 | 2 & 0", 1),
+        ("-8 >- 1 + 1", -2),
+    ];
+    for &(program, expected) in cases.iter() {
+        assert_eq!(run(program), expected, "{}", program);
+    }
+}
+
+#[test]
+fn operators_of_equal_rank_group_left_to_right() {
+    assert_eq!(run("10 - 4 - 3"), 3);
+    assert_eq!(run("64 / 4 / 2"), 8);
+    assert_eq!(run("1 << 3 >> 1"), 4);
+    assert_eq!(run("-(2 - 5) * 2"), 6);
+    assert_eq!(run("(1 + 2) * 3"), 9);
+
+    let tree = format_parse_tree(&ntlang::parse("a - b - c", &Config::new()).unwrap());
+    let lines: Vec<&str> = tree.lines().map(|line| line.split(" @ ").next().unwrap()).collect();
+    assert_eq!(
+        lines,
+        ["Operator: Minus", "  Operator: Minus", "    Ident: a", "    Ident: b", "  Ident: c"]
+    );
+}
+
+#[test]
+fn unary_operators_bind_tightest() {
+    assert_eq!(run("-2 * 3"), -6);
+    assert_eq!(run("~0 & 0xF"), 0xF);
+    assert_eq!(run("-1 + 3"), 2);
+}
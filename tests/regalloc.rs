@@ -0,0 +1,49 @@
+extern crate ntlang;
+
+mod common;
+
+use common::balanced;
+use ntlang::codegen::{ir, regalloc, riscv};
+use ntlang::Config;
+
+#[test]
+fn right_deep_chain_needs_two_registers() {
+    let mut config = Config::new();
+    config.optimize = false;
+    let node = ntlang::parse("1+(2+(3+(4+(5+(6+(7+(8+(9*x))))))))", &config).unwrap();
+    let func = ir::lower(&node, &config);
+
+    let naive = regalloc::linear_scan(&func, 5);
+    assert!(naive.spill_slots > 0);
+
+    let ordered = regalloc::linear_scan(&regalloc::sethi_ullman(&func), 5);
+    assert_eq!((ordered.registers, ordered.spill_slots), (2, 0));
+}
+
+#[test]
+fn balanced_tree_needs_its_sethi_ullman_number() {
+    let mut config = Config::new();
+    config.optimize = false;
+    for depth in 1..8 {
+        let node = ntlang::parse(&balanced(depth, &mut 0), &config).unwrap();
+        let func = regalloc::sethi_ullman(&ir::lower(&node, &config));
+        let alloc = regalloc::linear_scan(&func, 16);
+        assert_eq!(alloc.registers, depth as usize + 1, "depth {}", depth);
+    }
+}
+
+#[test]
+fn spilled_code_matches_eval() {
+    for &(width, signed) in &[(32, true), (32, false), (9, true)] {
+        let mut config = Config::new();
+        config.optimize = false;
+        config.width = width;
+        config.unsigned_int = signed;
+        let program = balanced(7, &mut 0);
+        let node = ntlang::parse(&program, &config).unwrap();
+        let rv = riscv::compile(&ir::legalize(&ir::lower(&node, &config)), 32).unwrap();
+        assert!(rv.stats.spill_slots > 0, "{}", rv.stats);
+        let report = ntlang::verify(&program, &config).unwrap();
+        assert!(report.ok(), "width {}:\n{}", width, report);
+    }
+}
@@ -0,0 +1,78 @@
+extern crate ntlang;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use ntlang::Config;
+
+/// Runs the REPL on `input` and returns what it wrote after each prompt.
+fn session(input: &str) -> Vec<String> {
+    let mut config = Config::new();
+    let mut out = Vec::new();
+    ntlang::repl::run(&mut config, input.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    out.split("ntlang> ").skip(1).map(|reply| reply.to_string()).collect()
+}
+
+#[test]
+fn lines_are_evaluated_in_turn() {
+    assert_eq!(session("1 + 2 * 3\n\n0 - 1\n"), ["7\n", "", "-1\n", "\n"]);
+}
+
+#[test]
+fn meta_commands_change_the_config() {
+    let replies = session(":base 16\n255\n:width 8\n255\n:signed\n:base 10\n255\n:unsigned\n255\n:config\n");
+    assert_eq!(replies[1], "0x000000FF\n");
+    assert_eq!(replies[3], "0xFF\n");
+    assert_eq!(replies[6], "-1\n");
+    assert_eq!(replies[8], "255\n");
+    assert_eq!(replies[9], "base 10, width 8, unsigned, grammar prec, overflow wrap\n");
+
+    let replies = session(":grammar flat\n1 + 2 * 3\n:grammar prec\n1 + 2 * 3\n");
+    assert_eq!((replies[1].as_str(), replies[3].as_str()), ("9\n", "7\n"));
+}
+
+#[test]
+fn bad_commands_are_reported_and_ignored() {
+    let replies = session(":base 8\n:width 0\n:frob\n:tokens\n10\n");
+    assert_eq!(
+        replies[..5],
+        [
+            "Error: base must be 2, 10 or 16\n",
+            "Error: width must be between 1 and 65536\n",
+            "Error: unknown command :frob (try :help)\n",
+            "Error: no expression to inspect\n",
+            "10\n",
+        ]
+    );
+}
+
+#[test]
+fn tokens_and_ast_inspect_the_last_expression() {
+    let replies = session("1 + 2\n:tokens\n:ast\n:ast -x\n");
+    assert_eq!(replies[1], "IntLit \"1\" @ 1:1\nPlus \"+\" @ 1:3\nIntLit \"2\" @ 1:5\nEOT \"\\0\" @ 1:6\n");
+    assert_eq!(replies[2], "Operator: Plus @ 1:1-1:6\n  Literal: 1 @ 1:1-1:2\n  Literal: 2 @ 1:5-1:6\n");
+    assert_eq!(replies[3], "Operator: Minus @ 1:1-1:3\n  Ident: x @ 1:2-1:3\n");
+}
+
+#[test]
+fn errors_are_rendered_and_the_session_goes_on() {
+    let replies = session("1 +\n:quit\n2\n");
+    assert!(replies[0].starts_with("error[P001]: Bad operand\n"), "{}", replies[0]);
+    // Nothing after :quit is read.
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[1], "\n");
+}
+
+#[test]
+fn the_tool_starts_a_repl_without_e() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ntlang"))
+        .args(["-b", "16"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"x = 0x12\nx << 4\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ntlang> 0x00000012\nntlang> 0x00000120\nntlang> \n");
+}
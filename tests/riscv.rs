@@ -0,0 +1,114 @@
+extern crate ntlang;
+
+mod common;
+
+use ntlang::codegen::riscv::{fits_imm12, RvImmOp, RvInst};
+use ntlang::codegen::riscv_emu::{self, RvTrap};
+use ntlang::codegen::verify::expected_result;
+use ntlang::codegen::{ir, riscv};
+use ntlang::{Config, Emit, EvalEnvSt, Word};
+
+/// `v0 = x + 0; ...; v0 + v<n-1>`: a frame with `n` variable slots.
+fn many_variables(n: usize) -> String {
+    let assigns: Vec<String> = (0..n).map(|i| format!("v{} = x + {}", i, i)).collect();
+    format!("{}; v0 + v{}", assigns.join("; "), n - 1)
+}
+
+/// The immediates of `addi` and the offsets of loads and stores in `asm`.
+fn immediates(asm: &str) -> Vec<i64> {
+    asm.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let operand = match words.next() {
+                Some("addi") => words.last()?,
+                Some("lw") | Some("ld") | Some("sw") | Some("sd") => words.last()?.split('(').next()?,
+                _ => return None,
+            };
+            Some(operand.parse().unwrap())
+        })
+        .collect()
+}
+
+/// Whether `asm` assembles for RV32 or RV64 with the M extension.
+fn assemble(asm: &str, xlen: u32) -> bool {
+    common::assemble(asm, &[&format!("--triple=riscv{}", xlen), "-mattr=+m"])
+}
+
+#[test]
+fn frames_beyond_2k_stay_encodable() {
+    let program = many_variables(600);
+    for &(emit, xlen) in &[(Emit::Riscv32, 32), (Emit::Riscv64, 64)] {
+        let mut config = Config::new();
+        config.optimize = false;
+        config.emit = Some(emit);
+        let asm = String::from_utf8(ntlang::compile(&program, &config).unwrap()).unwrap();
+        assert!(asm.contains("    li t5, -"), "no far frame adjustment:\n{}", asm);
+        for imm in immediates(&asm) {
+            assert!(fits_imm12(imm), "rv{}: immediate {} out of range", xlen, imm);
+        }
+        assert!(assemble(&asm, xlen), "rv{} output does not assemble", xlen);
+
+        let report = ntlang::verify(&program, &config).unwrap();
+        assert!(report.ok(), "{}", report);
+    }
+}
+
+#[test]
+fn emulator_rejects_unencodable_immediates() {
+    let config = Config::new();
+    let node = ntlang::parse("x + 1", &config).unwrap();
+    let func = ir::legalize(&ir::lower(&node, &config));
+    for &xlen in &[32, 64] {
+        let mut rv = riscv::compile(&func, xlen).unwrap();
+        assert_eq!(riscv_emu::run(&rv, &[41]), Ok(42));
+
+        let bad = [
+            RvInst::OpImm(RvImmOp::Addi, riscv::SP, riscv::SP, -2416),
+            RvInst::Lw(riscv::A0, 2048, riscv::SP),
+            RvInst::OpImm(RvImmOp::Slli, riscv::A0, riscv::A0, i64::from(xlen)),
+        ];
+        for inst in bad.iter() {
+            rv.insts.insert(0, inst.clone());
+            match riscv_emu::run(&rv, &[41]) {
+                Err(RvTrap::BadImmediate(_)) => {}
+                other => panic!("{} ran: {:?}", inst, other),
+            }
+            rv.insts.remove(0);
+        }
+    }
+}
+
+#[test]
+fn rv64_computes_up_to_64_bits() {
+    let programs = [
+        "t = x * 0x1E3779B97 - -y; u = t >- y; u ^ t << (y & 63)",
+        "x / y - y / (x | 1)",
+        "(x >- y) ^ (y >> x) + ~x",
+        "x << 40 >> 3",
+    ];
+    let inputs: [u64; 5] = [0, 5, 63, 0x8000_0000_0000_0000, 0xDEAD_BEEF_CAFE_F00D];
+    for &(width, signed) in &[(64, true), (64, false), (40, true), (33, false)] {
+        let mut config = Config::new();
+        config.width = width;
+        config.unsigned_int = signed;
+        for program in programs.iter() {
+            let node = ntlang::parse(program, &config).unwrap();
+            let func = ir::legalize(&ir::lower(&node, &config));
+            assert!(riscv::compile(&func, 32).is_err());
+            let rv = riscv::compile(&func, 64).unwrap();
+            assert!(assemble(&rv.to_string(), 64), "{}", rv);
+            for &x in inputs.iter() {
+                for &y in inputs.iter() {
+                    let mut env = EvalEnvSt::new();
+                    env.set("x", Word::new(u128::from(x), width));
+                    env.set("y", Word::new(u128::from(y), width));
+                    let expected = ntlang::evaluate_in(program, &config, &mut env)
+                        .ok()
+                        .map(|value| expected_result(&value, &config, 64));
+                    let actual = riscv_emu::run(&rv, &[x, y]).ok();
+                    assert_eq!(actual, expected, "{} with x={:#x} y={:#x} at width {}", program, x, y, width);
+                }
+            }
+        }
+    }
+}
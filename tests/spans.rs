@@ -0,0 +1,59 @@
+extern crate ntlang;
+
+use ntlang::{Config, ParseNode, ScanToken, Span};
+
+/// `(offset, line, col)` of the start and end of `span`.
+fn ends(span: Span) -> ((usize, usize, usize), (usize, usize, usize)) {
+    (
+        (span.start.offset, span.start.line, span.start.col),
+        (span.end.offset, span.end.line, span.end.col),
+    )
+}
+
+#[test]
+fn tokens_record_where_they_were_scanned() {
+    let st = ntlang::tokenize("x = 0x1F >> 2;\n  y").unwrap();
+    let tokens: Vec<(&ScanToken, &str, _)> = st
+        .table
+        .iter()
+        .map(|token| (&token.id, token.value.as_str(), ends(token.span)))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (&ScanToken::Ident, "x", ((0, 1, 1), (1, 1, 2))),
+            (&ScanToken::Assign, "=", ((2, 1, 3), (3, 1, 4))),
+            (&ScanToken::HexLit, "0x1F", ((4, 1, 5), (8, 1, 9))),
+            (&ScanToken::ShiftRight, ">>", ((9, 1, 10), (11, 1, 12))),
+            (&ScanToken::IntLit, "2", ((12, 1, 13), (13, 1, 14))),
+            (&ScanToken::Semi, ";", ((13, 1, 14), (14, 1, 15))),
+            (&ScanToken::Ident, "y", ((17, 2, 3), (18, 2, 4))),
+            (&ScanToken::EOT, "\0", ((18, 2, 4), (18, 2, 4))),
+        ]
+    );
+}
+
+#[test]
+fn columns_count_characters_and_offsets_bytes() {
+    let err = ntlang::tokenize("1 + é").err().unwrap();
+    let span = err.diagnostic().span;
+    assert_eq!(ends(span), ((4, 1, 5), (6, 1, 6)));
+}
+
+#[test]
+fn nodes_cover_their_source() {
+    let source = "a = (1 + b) * ~c";
+    let node = ntlang::parse(source, &Config::new()).unwrap();
+    let text = |node: &ParseNode| source[node.span.start.offset..node.span.end.offset].to_string();
+    assert_eq!(text(&node), source);
+    let product = node.left.as_ref().unwrap();
+    assert_eq!(text(product), "(1 + b) * ~c");
+    assert_eq!(text(product.left.as_ref().unwrap()), "(1 + b)");
+    assert_eq!(text(product.left.as_ref().unwrap().right.as_ref().unwrap()), "b");
+    assert_eq!(text(product.right.as_ref().unwrap()), "~c");
+
+    let program = "x = 1;\ny = x + 2";
+    let node = ntlang::parse(program, &Config::new()).unwrap();
+    assert_eq!(ends(node.span), ((0, 1, 1), (16, 2, 10)));
+    assert_eq!(ends(node.stmts[1].span), ((7, 2, 1), (16, 2, 10)));
+}
@@ -0,0 +1,43 @@
+extern crate ntlang;
+
+use ntlang::Config;
+
+#[test]
+fn trace_lists_nodes_in_post_order() {
+    let mut config = Config::new();
+    config.width = 8;
+    config.base = 16;
+    config.trace = true;
+    let report = ntlang::trace("a = 0xF0; (a >- 3) & 0x0F", &config).unwrap();
+    let expected = [
+        "    0xF0",
+        "  a = 0xF0 -> 0xF0",
+        "      a -> 0xF0",
+        "      0x03",
+        "    >- 0xF0 0x03 -> 0xFE",
+        "    0x0F",
+        "  & 0xFE 0x0F -> 0x0E",
+        "; 0xF0 0x0E -> 0x0E",
+        "result: 0x0E",
+    ];
+    assert_eq!(report.to_string().lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn trace_follows_base_and_signedness() {
+    let mut config = Config::new();
+    config.trace = true;
+    let report = ntlang::trace("-(1 - 3)", &config).unwrap();
+    let lines: Vec<String> = report.to_string().lines().map(|line| line.trim().to_string()).collect();
+    assert_eq!(lines, ["1", "3", "- 1 3 -> -2", "- -2 -> 2", "result: 2"]);
+
+    config.base = 2;
+    config.width = 4;
+    config.flags = true;
+    let report = ntlang::trace("7 + 9", &config).unwrap();
+    assert_eq!(report.steps.len(), 3);
+    assert_eq!(report.steps[2].operands.len(), 2);
+    let text = report.to_string();
+    assert!(text.contains("+ 0b0111 0b1001 -> 0b0000  N=0 Z=1 C=1 V=0"), "{}", text);
+    assert!(text.ends_with("flags: N=0 Z=1 C=1 V=0\n"), "{}", text);
+}
@@ -0,0 +1,71 @@
+extern crate ntlang;
+
+use ntlang::{Config, Emit, Error, EvalError, ParseError, ScanToken};
+
+fn run(program: &str) -> Result<u128, Error> {
+    ntlang::evaluate(program, &Config::new()).map(|value| value.bits())
+}
+
+#[test]
+fn statements_run_in_order_and_yield_the_last_value() {
+    assert_eq!(run("x = 5").unwrap(), 5);
+    assert_eq!(run("x = 5;").unwrap(), 5);
+    assert_eq!(run("x = 3; y = x * x; y - x").unwrap(), 6);
+    assert_eq!(run("x = 1; x = x << 4 | x; x").unwrap(), 0x11);
+    assert_eq!(run("mask_2 = 0xFF & 0x1234; mask_2").unwrap(), 0x34);
+}
+
+#[test]
+fn identifiers_are_tokens() {
+    let st = ntlang::tokenize("_a1 = b2;").unwrap();
+    let tokens: Vec<(&ScanToken, &str)> = st.table.iter().map(|token| (&token.id, token.value.as_str())).collect();
+    assert_eq!(
+        tokens,
+        [
+            (&ScanToken::Ident, "_a1"),
+            (&ScanToken::Assign, "="),
+            (&ScanToken::Ident, "b2"),
+            (&ScanToken::Semi, ";"),
+            (&ScanToken::EOT, "\0"),
+        ]
+    );
+}
+
+#[test]
+fn misplaced_assignments_and_separators_are_rejected() {
+    match run("1 + x = 2") {
+        Err(Error::Parse(ParseError::ExpectingEot { found, .. })) => assert_eq!(found, "="),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    assert!(run("x = y = 1").is_err());
+    assert!(run("(x = 1) + 1").is_err());
+    assert!(run(";").is_err());
+    assert!(run("x = 1;; 2").is_err());
+    match run("x = 1; y") {
+        Err(Error::Eval(EvalError::UndefinedVariable(name, _))) => assert_eq!(name, "y"),
+        other => panic!("expected an undefined variable, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_repl_keeps_variables_between_lines() {
+    let mut config = Config::new();
+    let mut out = Vec::new();
+    ntlang::repl::run(&mut config, "b = 2; a = 1\na + b\n:vars\n".as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out, "ntlang> 1\nntlang> 3\nntlang> a = 1\nb = 2\nntlang> \n");
+}
+
+#[test]
+fn long_programs_run_on_a_test_thread() {
+    let mut program = "x = 0".to_string();
+    for _ in 0..100_000 {
+        program.push_str("; x = x + 1");
+    }
+    program.push_str("; x");
+    let mut config = Config::new();
+    assert_eq!(ntlang::evaluate(&program, &config).unwrap().bits(), 100_000);
+    assert!(ntlang::explain_opt(&program, &config).is_ok());
+    config.emit = Some(Emit::Ir);
+    assert!(ntlang::compile(&program, &config).is_ok());
+}
@@ -0,0 +1,22 @@
+extern crate ntlang;
+
+mod common;
+
+use common::{literals_fit, PROGRAMS};
+use ntlang::Config;
+
+#[test]
+fn riscv_matches_eval() {
+    for &(width, signed) in &[(32, true), (32, false), (16, true), (5, false)] {
+        let mut config = Config::new();
+        config.width = width;
+        config.unsigned_int = signed;
+        for program in PROGRAMS.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let report = ntlang::verify(program, &config).unwrap();
+            assert!(report.ok(), "{} at width {}:\n{}", program, width, report);
+        }
+    }
+}
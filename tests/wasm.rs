@@ -0,0 +1,69 @@
+extern crate ntlang;
+
+mod common;
+
+use common::{literals_fit, INPUTS, PROGRAMS};
+use ntlang::codegen::ir;
+use ntlang::codegen::verify::expected_result;
+use ntlang::codegen::wasm_interp::{self, WasmTrap};
+use ntlang::codegen::wasm::{self, WasmType};
+use ntlang::{Config, EvalEnvSt, Word};
+
+#[test]
+fn wasm_matches_eval() {
+    for &(width, signed) in &[(32, true), (32, false), (16, true), (7, false)] {
+        let mut config = Config::new();
+        config.width = width;
+        config.unsigned_int = signed;
+        for program in PROGRAMS.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let node = ntlang::parse(program, &config).unwrap();
+            let module = wasm::compile(&ir::legalize(&ir::lower(&node, &config)));
+            let bytes = module.encode();
+            let bits = if module.result == WasmType::I32 { 32 } else { 64 };
+            for (i, &first) in INPUTS.iter().enumerate() {
+                let args: Vec<u32> = (0..module.vars.params.len())
+                    .map(|j| if j == 0 { first } else { INPUTS[(i + j) % INPUTS.len()] })
+                    .collect();
+                let mut env = EvalEnvSt::new();
+                env.width = width;
+                for (param, &value) in module.vars.params.iter().zip(&args) {
+                    env.set(param, Word::new(u128::from(value), width));
+                }
+                let expected = ntlang::eval::eval(&mut env, &Some(&node)).ok();
+                let args: Vec<u64> = args.iter().map(|&v| v as u64).collect();
+                let actual = wasm_interp::run(&bytes, &module.name, &args);
+                match expected {
+                    Some(value) => assert_eq!(
+                        actual,
+                        Ok(expected_result(&value, &config, bits)),
+                        "{} with {:?} at width {}",
+                        program,
+                        args,
+                        width
+                    ),
+                    None => assert_eq!(actual, Err(WasmTrap::IntegerDivideByZero), "{}", program),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn wasm_rejects_corrupt_modules() {
+    let config = Config::new();
+    let node = ntlang::parse("x + 1", &config).unwrap();
+    let module = wasm::compile(&ir::lower(&node, &config));
+    let bytes = module.encode();
+    assert_eq!(wasm_interp::run(&bytes, &module.name, &[41]), Ok(42));
+    assert_eq!(
+        wasm_interp::run(&bytes, "main", &[41]),
+        Err(WasmTrap::NoExport("main".to_string()))
+    );
+    assert!(wasm_interp::run(&bytes[..bytes.len() - 1], &module.name, &[41]).is_err());
+    let mut bad_magic = bytes.clone();
+    bad_magic[1] = b'b';
+    assert!(wasm_interp::run(&bad_magic, &module.name, &[41]).is_err());
+}
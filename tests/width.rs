@@ -0,0 +1,131 @@
+extern crate ntlang;
+
+use ntlang::eval::eval_format;
+use ntlang::{CodegenError, Config, Emit, Error, ParseError};
+
+fn run(program: &str, width: u32, base: u32, signed: bool) -> String {
+    let mut config = Config::new();
+    config.width = width;
+    config.base = base;
+    config.unsigned_int = signed;
+    let value = ntlang::evaluate(program, &config).unwrap();
+    eval_format(&config, &value)
+}
+
+#[test]
+fn computes_at_the_configured_width() {
+    let cases: [(&str, u32, u32, bool, &str); 18] = [
+        ("1 << 32", 64, 10, true, "4294967296"),
+        ("0xFFFFFFFFFFFFFFFF + 1", 64, 16, true, "0x0000000000000000"),
+        ("0xFFFFFFFFFFFFFFFF * 0xFFFFFFFFFFFFFFFF", 64, 10, true, "1"),
+        ("-1", 64, 10, false, "18446744073709551615"),
+        ("-1 >> 60", 64, 10, true, "15"),
+        ("(1 << 63) >- 62", 64, 10, true, "-2"),
+        ("x = 1 << 100; x / 3", 128, 10, true, "422550200076076467165567735125"),
+        ("1 << 127", 128, 10, true, "-170141183460469231731687303715884105728"),
+        ("1 << 128", 128, 10, true, "0"),
+        ("~0", 128, 16, true, "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"),
+        ("-1", 5, 2, true, "0b11111"),
+        // Masking after every operation: the shift sees 8 bits, not 32.
+        ("-1 >> 4", 8, 10, false, "15"),
+        ("0x80 >- 7", 8, 10, true, "-1"),
+        ("(0 - 2) / 2", 8, 10, false, "127"),
+        // Shifting by the width or more moves every bit out.
+        ("1 << 20", 16, 10, false, "0"),
+        ("0x80 >> 9", 8, 10, false, "0"),
+        ("0x80 >- 9", 8, 10, true, "-1"),
+        ("250 + 50", 8, 10, false, "44"),
+    ];
+    for &(program, width, base, signed, expected) in cases.iter() {
+        assert_eq!(run(program, width, base, signed), expected, "{} at width {}", program, width);
+    }
+}
+
+#[test]
+fn literals_may_use_the_whole_width() {
+    let mut config = Config::new();
+    config.width = 128;
+    assert!(ntlang::evaluate("0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", &config).is_ok());
+    assert!(ntlang::evaluate("340282366920938463463374607431768211455", &config).is_ok());
+    match ntlang::evaluate("0x1FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", &config) {
+        Err(Error::Parse(ParseError::LiteralOverflow { width: 128, .. })) => {}
+        other => panic!("expected an overflow, got {:?}", other),
+    }
+
+    config.width = 40;
+    assert!(ntlang::evaluate("0xFFFFFFFFFF", &config).is_ok());
+    assert!(ntlang::evaluate("0x10000000000", &config).is_err());
+
+    // Narrow widths reject a literal rather than truncate it.
+    config.width = 8;
+    assert!(ntlang::evaluate("255", &config).is_ok());
+    match ntlang::evaluate("300", &config) {
+        Err(Error::Parse(ParseError::LiteralOverflow { width: 8, .. })) => {}
+        other => panic!("expected an overflow, got {:?}", other),
+    }
+    assert!(ntlang::evaluate("0xFFFFFFFF", &config).is_err());
+}
+
+#[test]
+fn register_backends_stop_at_their_register_width() {
+    let mut config = Config::new();
+    config.width = 33;
+    match ntlang::verify("x + 1", &config) {
+        Err(Error::Codegen(CodegenError::Unsupported { .. })) => {}
+        other => panic!("expected unsupported width, got {:?}", other.map(|report| report.ok())),
+    }
+    assert!(ntlang::jit("x + 1", &config).is_err());
+    for &emit in &[Emit::Riscv32, Emit::C, Emit::Wat, Emit::Exe] {
+        config.emit = Some(emit);
+        assert!(ntlang::compile("x + 1", &config).is_err(), "{:?}", emit);
+    }
+    for &emit in &[Emit::Riscv64, Emit::Aarch64, Emit::X86_64] {
+        config.emit = Some(emit);
+        config.width = 64;
+        assert!(ntlang::compile("x + 1", &config).is_ok(), "{:?}", emit);
+        config.width = 65;
+        assert!(ntlang::compile("x + 1", &config).is_err(), "{:?}", emit);
+    }
+
+    config.width = 64;
+    config.emit = Some(Emit::Ir);
+    let ir = String::from_utf8(ntlang::compile("x + 0x123456789", &config).unwrap()).unwrap();
+    assert!(ir.contains("const.u64 4886718345"), "{}", ir);
+    config.emit = Some(Emit::Llvm);
+    let ll = String::from_utf8(ntlang::compile("x + 0x123456789", &config).unwrap()).unwrap();
+    assert!(ll.contains("@ntlang_expr(i64 %v.x)") && ll.contains("add i64 %v.x, 4886718345"), "{}", ll);
+}
+
+#[test]
+fn variables_follow_the_width() {
+    let mut config = Config::new();
+    let mut env = ntlang::EvalEnvSt::new();
+    config.width = 64;
+    ntlang::evaluate_in("x = 0x123456789", &config, &mut env).unwrap();
+    config.width = 16;
+    let value = ntlang::evaluate_in("x", &config, &mut env).unwrap();
+    assert_eq!((value.bits(), value.width()), (0x6789, 16));
+}
+
+#[test]
+fn computes_beyond_128_bits() {
+    let cases: [(&str, u32, u32, bool, &str); 8] = [
+        ("((1 << 255) - 1) / ((1 << 200) + 7)", 256, 10, false, "36028797018963967"),
+        ("x = (1 << 130) + 5; x * x", 512, 10, false, "1852673427797059126777135760139006525665931049327086563169856328423880802697241"),
+        ("-1 * -1", 256, 10, false, "1"),
+        ("1 << 255", 256, 10, true, "-57896044618658097711785492504343953926634992332820282019728792003956564819968"),
+        ("(1 << 511) >- 500", 512, 10, true, "-2048"),
+        ("(1 << 300) / 3", 512, 16, false, "0x00000000000000000000000000000000000000000000000000000555555555555555555555555555555555555555555555555555555555555555555555555555"),
+        ("~0 >> 255", 256, 2, false, "0b0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001"),
+        ("1 << 257", 256, 10, false, "0"),
+    ];
+    for &(program, width, base, signed, expected) in cases.iter() {
+        assert_eq!(run(program, width, base, signed), expected, "{} at width {}", program, width);
+    }
+
+    let mut config = Config::new();
+    config.width = 256;
+    assert!(ntlang::evaluate(&format!("0x{}", "F".repeat(64)), &config).is_ok());
+    assert!(ntlang::evaluate(&format!("0x1{}", "0".repeat(64)), &config).is_err());
+    assert!(ntlang::evaluate("(1 << 255) / ((1 << 255) << 1)", &config).is_err());
+}
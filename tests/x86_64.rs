@@ -0,0 +1,137 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+extern crate ntlang;
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::{balanced, body, literals_fit, run, temp_path, INPUTS, PROGRAMS};
+use ntlang::codegen::verify::expected_result;
+use ntlang::codegen::VarTableSt;
+use ntlang::{Config, Emit, EvalEnvSt, Word};
+
+/// Inputs beyond 32 bits, tried after the shared ones.
+const WIDE_INPUTS: [u64; 2] = [0x8000_0000_0000_0000, 0xDEAD_BEEF_CAFE_F00D];
+
+fn compile(program: &str, config: &Config) -> String {
+    common::compile(program, config, Emit::X86_64)
+}
+
+/// Links `asm` with a driver that calls it with its arguments and prints
+/// the returned `rax` in hex. Without `cc` the test fails, unless
+/// `NTLANG_SKIP_MISSING_TOOLS` is set, which gives `None`.
+fn build(asm: &str, params: usize, path: &str) -> Option<()> {
+    let args: Vec<String> = (0..params).map(|_| "unsigned long".to_string()).collect();
+    let calls: Vec<String> = (0..params).map(|i| format!("strtoul(argv[{}], 0, 0)", i + 1)).collect();
+    let driver = format!(
+        "#include <stdio.h>\n#include <stdlib.h>\n\
+         unsigned long ntlang_expr({});\n\
+         int main(int argc, char **argv) {{ (void)argc; printf(\"%lx\\n\", ntlang_expr({})); return 0; }}\n",
+        if args.is_empty() { "void".to_string() } else { args.join(", ") },
+        calls.join(", ")
+    );
+    fs::write(format!("{}.c", path), driver).unwrap();
+    fs::write(format!("{}.s", path), asm).unwrap();
+    let status = run(Command::new("cc").args(["-o", path, &format!("{}.c", path), &format!("{}.s", path)]))?;
+    assert!(status.success(), "cc failed on:\n{}", asm);
+    Some(())
+}
+
+#[test]
+fn assembled_code_matches_eval() {
+    let path = temp_path("x86-64");
+    let inputs: Vec<u64> = INPUTS.iter().map(|&value| u64::from(value)).chain(WIDE_INPUTS.iter().cloned()).collect();
+    let spilled = balanced(7, &mut 0);
+    let mut programs: Vec<&str> = PROGRAMS.to_vec();
+    programs.push(&spilled);
+    for &(width, signed) in &[(64, true), (64, false), (40, true), (33, false), (32, true), (32, false), (12, true), (7, false)] {
+        let mut config = Config::new();
+        config.width = width;
+        config.unsigned_int = signed;
+        for program in programs.iter() {
+            if !literals_fit(program, &config) {
+                continue;
+            }
+            let params = VarTableSt::new(&ntlang::parse(program, &config).unwrap()).params;
+            if build(&compile(program, &config), params.len(), &path).is_none() {
+                return;
+            }
+            for i in 0..inputs.len() {
+                let inputs: Vec<u64> = (0..params.len()).map(|j| inputs[(i + j) % inputs.len()]).collect();
+                let mut env = EvalEnvSt::new();
+                for (param, &value) in params.iter().zip(&inputs) {
+                    env.set(param, Word::new(u128::from(value), width));
+                }
+                let expected = ntlang::evaluate_in(program, &config, &mut env)
+                    .ok()
+                    .map(|value| format!("{:x}\n", expected_result(&value, &config, 64)));
+                let output = Command::new(&path).args(inputs.iter().map(|value| value.to_string())).output().unwrap();
+                // Division by zero ends in `ud2`.
+                let actual = if output.status.success() { Some(String::from_utf8(output.stdout).unwrap()) } else { None };
+                assert_eq!(actual, expected, "{} with {:?} at width {}", program, inputs, width);
+            }
+        }
+    }
+    for ext in ["", ".c", ".s"].iter() {
+        let _ = fs::remove_file(format!("{}{}", path, ext));
+    }
+}
+
+#[test]
+fn operators_map_to_instructions() {
+    let config = Config::new();
+    let asm = compile("(a >- b) / c", &config);
+    let lines = body(&asm);
+    let sar = lines.iter().position(|line| *line == "sar r8d, cl").unwrap();
+    assert_eq!(&lines[sar - 4..sar], ["mov ecx, r9d", "mov edx, 31", "cmp ecx, 32", "cmovae ecx, edx"]);
+    let div = lines.iter().position(|line| *line == "div r9d").unwrap();
+    assert_eq!(&lines[div - 4..div], ["test r9d, r9d", "jz .Ldiv_by_zero", "mov eax, r8d", "xor edx, edx"]);
+    assert_eq!(lines[div + 1], "mov r8d, eax");
+    assert!(lines.contains(&"movsxd rax, eax"));
+    assert_eq!(&lines[lines.len() - 2..], [".Ldiv_by_zero:", "ud2"]);
+}
+
+#[test]
+fn shifts_saturate_their_count() {
+    let config = Config::new();
+    let asm = compile("a << b", &config);
+    let lines = body(&asm);
+    let shl = lines.iter().position(|line| *line == "shl r8d, cl").unwrap();
+    assert_eq!(&lines[shl + 1..shl + 4], ["xor edx, edx", "cmp ecx, 32", "cmovae r8d, edx"]);
+    // A constant count needs no check.
+    assert!(!compile("a >> 3", &config).contains("cmp"));
+}
+
+#[test]
+fn spilled_temporaries_are_memory_operands() {
+    let mut config = Config::new();
+    config.optimize = false;
+    let asm = compile(&balanced(7, &mut 0), &config);
+    assert!(asm.contains("spill slots: 2"), "{}", asm);
+    // Variables a to d take the first four slots; spills go after.
+    let lines = body(&asm);
+    assert!(lines.iter().any(|line| line.starts_with("mov dword ptr [rbp - 20], ")), "{}", asm);
+    assert!(lines.iter().any(|line| line.ends_with(", dword ptr [rbp - 20]") && !line.starts_with("mov ")), "{}", asm);
+}
+
+#[test]
+fn wide_widths_use_64_bit_registers() {
+    let mut config = Config::new();
+    config.width = 64;
+    let asm = compile("(a >- b) / c", &config);
+    assert!(asm.contains("# rdi = a\n"), "{}", asm);
+    let lines = body(&asm);
+    assert!(lines.contains(&"mov qword ptr [rbp - 8], rdi"), "{}", asm);
+    let sar = lines.iter().position(|line| *line == "sar r8, cl").unwrap();
+    assert_eq!(&lines[sar - 4..sar], ["mov rcx, r9", "mov edx, 63", "cmp rcx, 64", "cmovae rcx, rdx"]);
+    let div = lines.iter().position(|line| *line == "div r9").unwrap();
+    assert_eq!(&lines[div - 2..div + 2], ["mov rax, r8", "xor edx, edx", "div r9", "mov r8, rax"]);
+
+    // Below 64 bits, values are masked and the result is extended by hand.
+    config.width = 40;
+    let asm = compile("x * 0x123456789", &config);
+    let lines = body(&asm);
+    assert!(lines.contains(&"mov r9, 1099511627775"), "{}", asm);
+    assert!(lines.ends_with(&["shl rax, 24", "sar rax, 24", "leave", "ret"]), "{}", asm);
+}